@@ -0,0 +1,366 @@
+// ----------------------------------------------------------------------------
+// The Acalog catalog API.
+//
+// Every college publishes its catalog with Acalog, whose API lists a
+// catalog's courses and returns them as XML, each part of an entry in its
+// own titled <field>. That doesn't break when the catalog pages are
+// restyled, so it's tried before scraping the print pages. It needs a key
+// (catalog.acalog_key); without one, or when it fails, scrape_catalog falls
+// back to the print pages. Both read entries into a CatalogEntry, so a
+// course reads the same whichever was used.
+// ----------------------------------------------------------------------------
+
+use crate::config::CONFIG;
+use crate::course_api::*;
+use crate::scrape_descriptions::*;
+use async_trait::async_trait;
+use log::{info, warn};
+use std::collections::HashSet;
+use xml::reader::{EventReader, XmlEvent};
+
+// Course IDs per listing request
+const LISTING_PAGE_SIZE: usize = 500;
+// Listing pages read at most, far more than any catalog has
+const MAX_LISTING_PAGES: usize = 50;
+// Courses per getItems request, which takes the IDs in its URL
+const ITEMS_PER_REQUEST: usize = 100;
+
+/// A school's API host and catalog
+pub struct AcalogCatalog {
+    // "https://pomona.apis.acalog.com/v1/"
+    pub api: &'static str,
+    // Same as cur_cat_oid in the print page URLs
+    pub catalog: u64,
+}
+
+pub fn acalog_catalog(style: &School) -> Option<AcalogCatalog> {
+    let (api, catalog) = match style {
+        School::HarveyMudd => ("https://hmc.apis.acalog.com/v1/", 18),
+        School::ClaremontMckenna => ("https://claremontmckenna.apis.acalog.com/v1/", 29),
+        School::Pomona => ("https://pomona.apis.acalog.com/v1/", 40),
+        School::Scripps => ("https://scrippscollege.apis.acalog.com/v1/", 25),
+        School::Pitzer => ("https://pitzer.apis.acalog.com/v1/", 17),
+        School::Keck if CONFIG.catalog.keck.is_set() => ("https://kgi.apis.acalog.com/v1/", CONFIG.catalog.keck.catalog),
+        School::ClaremontGraduate if CONFIG.catalog.claremont_graduate.is_set() => (
+            "https://cgu.apis.acalog.com/v1/",
+            CONFIG.catalog.claremont_graduate.catalog,
+        ),
+        _ => return None,
+    };
+
+    Some(AcalogCatalog { api, catalog })
+}
+
+impl AcalogCatalog {
+    pub fn listing_url(&self, key: &str, page: usize) -> String {
+        format!(
+            "{}search/courses?key={}&format=xml&method=listing&catalog={}&options[limit]={}&options[page]={}",
+            self.api, key, self.catalog, LISTING_PAGE_SIZE, page
+        )
+    }
+
+    pub fn items_url(&self, key: &str, ids: &[u64]) -> String {
+        let ids: String = ids.iter().map(|id| format!("&ids[]={}", id)).collect();
+
+        format!(
+            "{}content?key={}&format=xml&method=getItems&type=courses&catalog={}&options[full]=1{}",
+            self.api, key, self.catalog, ids
+        )
+    }
+}
+
+/// Course IDs in a listing response, the <id> of each <result>
+pub fn parse_course_ids(xml: &str) -> Result<Vec<u64>, String> {
+    let mut ids = Vec::new();
+    let mut path: Vec<String> = Vec::new();
+
+    for event in EventReader::from_str(xml) {
+        match event.map_err(|e| e.to_string())? {
+            XmlEvent::StartElement { name, .. } => path.push(name.local_name),
+            XmlEvent::EndElement { .. } => {
+                path.pop();
+            }
+            XmlEvent::Characters(text) if path.ends_with(&["result".to_string(), "id".to_string()]) => {
+                ids.push(text.trim().parse().map_err(|_| format!("Invalid course ID \"{}\"", text))?);
+            }
+            _ => {}
+        }
+    }
+
+    Ok(ids)
+}
+
+/// Which part of an entry a field holds, from its title ("Credit(s):",
+/// "When Offered", "Prerequisite(s)")
+pub fn entry_part<'a>(entry: &'a mut CatalogEntry, field_title: &str) -> Option<&'a mut String> {
+    let title = field_title.trim().trim_end_matches(':').to_lowercase().replace("(s)", "");
+
+    let part = match title.trim_end_matches('s') {
+        "description" | "course description" => &mut entry.description,
+        "credit" | "course credit" => &mut entry.credits,
+        "instructor" => &mut entry.instructors,
+        "offered" | "when offered" => &mut entry.offered,
+        "prerequisite" => &mut entry.prerequisites,
+        "corequisite" => &mut entry.corequisites,
+        _ => return None,
+    };
+
+    Some(part)
+}
+
+/// Courses in a getItems response. Each <course> has its <prefix>, <code>
+/// and <name>, then a <field> per part of the entry with a title and the
+/// text in <data>, which may be XHTML.
+pub fn parse_courses(xml: &str) -> Result<Vec<CatalogEntry>, String> {
+    let mut entries = Vec::new();
+
+    let mut path: Vec<String> = Vec::new();
+    let mut entry = CatalogEntry::default();
+    let mut prefix = String::new();
+    let mut code = String::new();
+    let mut field_title = String::new();
+    let mut field_data = String::new();
+
+    for event in EventReader::from_str(xml) {
+        match event.map_err(|e| e.to_string())? {
+            XmlEvent::StartElement { name, .. } => {
+                match name.local_name.as_str() {
+                    "course" => {
+                        entry = CatalogEntry::default();
+                        prefix.clear();
+                        code.clear();
+                    }
+                    "field" => {
+                        field_title.clear();
+                        field_data.clear();
+                    }
+                    _ => {}
+                }
+
+                path.push(name.local_name);
+            }
+            XmlEvent::Characters(text) | XmlEvent::CData(text) | XmlEvent::Whitespace(text) => {
+                // Everything inside <data>, however deep in its XHTML
+                if path.iter().any(|element| element == "data") {
+                    field_data.push_str(&text);
+                    continue;
+                }
+
+                let parent = path.len().checked_sub(2).map(|i| path[i].as_str());
+
+                match (parent, path.last().map(String::as_str)) {
+                    (Some("content"), Some("prefix")) => prefix.push_str(text.trim()),
+                    (Some("content"), Some("code")) => code.push_str(text.trim()),
+                    (Some("content"), Some("name")) => entry.title.push_str(&text),
+                    (Some("field"), Some("title")) => field_title.push_str(&text),
+                    _ => {}
+                }
+            }
+            XmlEvent::EndElement { name } => {
+                path.pop();
+
+                match name.local_name.as_str() {
+                    "field" => {
+                        if let Some(part) = entry_part(&mut entry, &field_title) {
+                            *part = field_data.clone();
+                        }
+                    }
+                    "course" => {
+                        entry.code = format!("{} {}", prefix, code);
+                        entries.push(std::mem::take(&mut entry));
+                    }
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(entries)
+}
+
+/// Descriptions from a getItems response, as extract_description reads
+/// them from a print page
+pub fn extract_api_descriptions(xml: &str, style: &School) -> Result<Vec<CourseDescription>, String> {
+    Ok(parse_courses(xml)?
+        .into_iter()
+        .filter(|entry| !points_elsewhere(&entry.description))
+        .map(|entry| entry.into_description(style.clone()))
+        .collect())
+}
+
+// Request errors name the URL, which has the key in its query, and end
+// up in the logs and /health
+async fn get(url: &str, key: &str) -> Result<String, String> {
+    let redact = |e: reqwest::Error| redact_key(&e.to_string(), key);
+
+    let client = reqwest::Client::builder()
+        .user_agent("api.5scheduler.io")
+        .build()
+        .map_err(redact)?;
+
+    let response = client
+        .get(url)
+        .send()
+        .await
+        .map_err(redact)?
+        .error_for_status()
+        .map_err(redact)?;
+
+    response.text().await.map_err(redact)
+}
+
+fn redact_key(message: &str, key: &str) -> String {
+    if key.is_empty() {
+        message.to_string()
+    } else {
+        message.replace(key, "<key>")
+    }
+}
+
+/// Reads catalogs through the Acalog API
+pub struct AcalogApi;
+
+#[async_trait]
+impl CatalogSource for AcalogApi {
+    fn name(&self) -> &'static str {
+        "the Acalog API"
+    }
+
+    async fn fetch(&self, style: &School, pages: &mut SchoolPages) -> Result<Vec<CourseDescription>, String> {
+        let catalog = acalog_catalog(style).ok_or_else(|| format!("No Acalog catalog for {:?}", style))?;
+        let key = &CONFIG.catalog.acalog_key;
+
+        let mut ids: Vec<u64> = Vec::new();
+        let mut seen: HashSet<u64> = HashSet::new();
+
+        for page in 1..=MAX_LISTING_PAGES {
+            let listed = parse_course_ids(&get(&catalog.listing_url(key, page), key).await?)?;
+            let done = listed.len() < LISTING_PAGE_SIZE;
+            let before = ids.len();
+
+            ids.extend(listed.into_iter().filter(|id| seen.insert(*id)));
+
+            // A page of IDs we already have means the API ignored the page
+            // number, and would keep sending the same one
+            if done || ids.len() == before {
+                break;
+            }
+
+            if page == MAX_LISTING_PAGES {
+                warn!("Stopped listing {:?} courses after {} pages", style, MAX_LISTING_PAGES);
+            }
+        }
+
+        info!("Reading {} {:?} courses from the Acalog API", ids.len(), style);
+
+        let mut descriptions = Vec::new();
+
+        for (page, chunk) in ids.chunks(ITEMS_PER_REQUEST).enumerate() {
+            let xml = get(&catalog.items_url(key, chunk), key).await?;
+            descriptions.extend(pages.read(&format!("api/{}", page + 1), &xml, |xml| {
+                extract_api_descriptions(xml, style)
+            })?);
+        }
+
+        Ok(descriptions)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::health::*;
+    use crate::MEMORY_DATABASE;
+
+    #[test]
+    fn reads_listing_ids() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<search xmlns="http://acalog.com/search/1.0">
+  <hits>2</hits>
+  <results>
+    <result><id>40001</id><type>course</type></result>
+    <result><id> 40002 </id><type>course</type></result>
+  </results>
+</search>"#;
+
+        assert_eq!(parse_course_ids(xml), Ok(vec![40001, 40002]));
+        assert!(parse_course_ids("<results><result><id>x</id></result></results>").is_err());
+    }
+
+    #[test]
+    fn names_entry_parts_by_field_title() {
+        let mut entry = CatalogEntry::default();
+
+        for title in ["Credit(s):", "Course Credit", "Description", "Instructor(s)", "When Offered", "Prerequisites:"] {
+            assert!(entry_part(&mut entry, title).is_some(), "{}", title);
+        }
+
+        assert!(entry_part(&mut entry, "Grading").is_none());
+    }
+
+    #[test]
+    fn reads_course_entries() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<catalog xmlns="http://acalog.com/catalog/1.0" xmlns:a="http://www.w3.org/2005/Atom" xmlns:h="http://www.w3.org/1999/xhtml">
+  <courses>
+    <course id="acalog-course-1">
+      <a:title>ECON 167 PO - Econometrics</a:title>
+      <content>
+        <prefix>ECON</prefix>
+        <code>167 PO</code>
+        <name>Econometrics</name>
+        <field type="acalog-field-1">
+          <a:title>Credit</a:title>
+          <data>1</data>
+        </field>
+        <field type="acalog-field-2">
+          <a:title>Description</a:title>
+          <data><h:p>Theory and <h:em>practice</h:em>.</h:p></data>
+        </field>
+        <field type="acalog-field-3">
+          <a:title>Grading</a:title>
+          <data>Letter</data>
+        </field>
+      </content>
+    </course>
+    <course id="acalog-course-2">
+      <content>
+        <prefix>MATH</prefix>
+        <code>060 HM</code>
+        <name>Linear Algebra</name>
+      </content>
+    </course>
+  </courses>
+</catalog>"#;
+
+        let entries = parse_courses(xml).unwrap();
+
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].code, "ECON 167 PO");
+        assert_eq!(entries[0].title, "Econometrics");
+        assert_eq!(entries[0].credits, "1");
+        assert_eq!(entries[0].description, "Theory and practice.");
+        assert_eq!(entries[1].code, "MATH 060 HM");
+        assert_eq!(entries[1].description, "");
+    }
+
+    #[tokio::test]
+    async fn keeps_the_key_out_of_health() {
+        let key = "secret-acalog-key";
+        let catalog = AcalogCatalog { api: "http://127.0.0.1:1/v1/", catalog: 40 };
+        let source = Source::Catalog(School::Pomona);
+
+        // Nothing listens on port 1, so the request fails
+        let started = std::time::Instant::now();
+        let result = get(&catalog.listing_url(key, 1), key).await;
+        record_result(&source, started, &result, |xml| xml.len()).await;
+
+        let error = result.unwrap_err();
+        assert!(error.contains("key=<key>"), "{}", error);
+        assert!(!error.contains(key));
+
+        let health = MEMORY_DATABASE.lock().await.health_cache.get(&source.key()).cloned().unwrap();
+        assert!(!serde_json::to_string(&health).unwrap().contains(key));
+    }
+}
@@ -0,0 +1,219 @@
+// ----------------------------------------------------------------------------
+// Registry of campus buildings, loaded from buildings.json.
+//
+// The Pomona API, the CMC scrape and locations.json all spell buildings
+// differently ("Hahn Social Science Bldg", "Hahn", "Hahn Building"...).
+// Every Location is normalized against this registry when courses are
+// ingested, so the rest of the server sees one name and ID per building.
+// Names that match nothing are remembered and reported after each update.
+// ----------------------------------------------------------------------------
+
+use crate::config::CONFIG;
+use crate::course_api::School;
+use crate::geo::Coordinates;
+use ::serde::{Deserialize, Serialize};
+use lazy_static::lazy_static;
+use log::error;
+use std::collections::{BTreeSet, HashMap};
+use std::sync::Mutex;
+
+// Abbreviations that show up in building names, and what they stand for
+const ABBREVIATIONS: &[(&str, &str)] = &[
+    ("bldg", "building"),
+    ("bld", "building"),
+    ("ctr", "center"),
+    ("conservcy", "conservancy"),
+    ("lab", "laboratory"),
+    ("labs", "laboratories"),
+];
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Building {
+    pub id: String,
+    pub name: String,
+    pub school: School,
+    #[serde(default)]
+    pub aliases: Vec<String>,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    // Optional footprint as [latitude, longitude] points, used for the
+    // coordinates when latitude/longitude aren't given
+    #[serde(default)]
+    pub outline: Vec<[f64; 2]>,
+    // "Arranged", "Online" and friends, which aren't real places
+    #[serde(default)]
+    pub placeholder: bool,
+}
+
+impl Building {
+    /// Latitude and longitude of the building, or the centroid of its
+    /// outline
+    pub fn coordinates(&self) -> Option<Coordinates> {
+        if let (Some(lat), Some(lon)) = (self.latitude, self.longitude) {
+            return Coordinates::new(lat, lon).ok();
+        }
+
+        if self.outline.is_empty() {
+            return None;
+        }
+
+        let n = self.outline.len() as f64;
+        let lat = self.outline.iter().map(|p| p[0]).sum::<f64>() / n;
+        let lon = self.outline.iter().map(|p| p[1]).sum::<f64>() / n;
+
+        Coordinates::new(lat, lon).ok()
+    }
+
+    fn names(&self) -> impl Iterator<Item = &String> {
+        std::iter::once(&self.name).chain(self.aliases.iter())
+    }
+}
+
+/// Lowercases, drops punctuation and expands abbreviations, so
+/// "Hahn Social Science Bldg." and "hahn social science building" match
+fn normalize_name(name: &str) -> String {
+    name.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| {
+            ABBREVIATIONS
+                .iter()
+                .find(|(short, _)| *short == word)
+                .map_or(word, |(_, long)| long)
+        })
+        .collect::<Vec<&str>>()
+        .join(" ")
+}
+
+pub struct BuildingRegistry {
+    buildings: Vec<Building>,
+    by_name: HashMap<String, Vec<usize>>,
+    unmatched: Mutex<BTreeSet<(String, String)>>,
+}
+
+impl BuildingRegistry {
+    pub fn new(buildings: Vec<Building>) -> Self {
+        let mut by_name: HashMap<String, Vec<usize>> = HashMap::new();
+
+        for (i, building) in buildings.iter().enumerate() {
+            for name in building.names() {
+                let entry = by_name.entry(normalize_name(name)).or_default();
+
+                if !entry.contains(&i) {
+                    entry.push(i);
+                }
+            }
+        }
+
+        Self {
+            buildings,
+            by_name,
+            unmatched: Mutex::new(BTreeSet::new()),
+        }
+    }
+
+    pub fn load(path: &str) -> Result<Self, String> {
+        let data = std::fs::read_to_string(path)
+            .map_err(|e| format!("Could not read {}: {}", path, e))?;
+
+        let buildings: Vec<Building> =
+            serde_json::from_str(&data).map_err(|e| format!("Could not parse {}: {}", path, e))?;
+
+        Ok(Self::new(buildings))
+    }
+
+    pub fn buildings(&self) -> &Vec<Building> {
+        &self.buildings
+    }
+
+    pub fn get(&self, id: &str) -> Option<&Building> {
+        self.buildings.iter().find(|b| b.id == id)
+    }
+
+    /// Finds a building by any of its names, preferring one owned by
+    /// `school`. A name shared by several other schools' buildings is
+    /// ambiguous and matches nothing.
+    pub fn find(&self, school: &School, name: &str) -> Option<&Building> {
+        let candidates = self.by_name.get(&normalize_name(name))?;
+
+        let own = candidates
+            .iter()
+            .find(|i| &self.buildings[**i].school == school);
+
+        match (own, candidates.len()) {
+            (Some(i), _) => Some(&self.buildings[*i]),
+            (None, 1) => Some(&self.buildings[candidates[0]]),
+            _ => None,
+        }
+    }
+
+    /// Like `find`, but remembers names that match nothing so they can
+    /// be reported (and added to buildings.json)
+    pub fn resolve(&self, school: &School, name: &str) -> Option<&Building> {
+        let building = self.find(school, name);
+
+        if building.is_none() && !name.trim().is_empty() {
+            self.unmatched
+                .lock()
+                .unwrap()
+                .insert((format!("{:?}", school), name.trim().to_string()));
+        }
+
+        building
+    }
+
+    /// Splits the room off a "<building> Room <room>" string, using the
+    /// building's known names to find where the room starts
+    pub fn extract_room(&self, building: Option<&Building>, text: &str) -> String {
+        let text = text.trim();
+
+        let rest = building
+            .into_iter()
+            .flat_map(|b| b.names())
+            .filter_map(|name| {
+                text.get(..name.len())
+                    .filter(|prefix| prefix.eq_ignore_ascii_case(name))
+                    .map(|_| &text[name.len()..])
+            })
+            // Prefer the longest name, "Seaver North Laboratory" over "Seaver"
+            .min_by_key(|rest| rest.len());
+
+        match rest {
+            Some(rest) => {
+                let rest = rest.trim();
+
+                ["Room", "Rm.", "Rm"]
+                    .iter()
+                    .find_map(|word| rest.strip_prefix(word))
+                    .unwrap_or(rest)
+                    .trim()
+                    .to_string()
+            }
+            None => text
+                .split_once("Room")
+                .unwrap_or(("", text))
+                .1
+                .trim()
+                .to_string(),
+        }
+    }
+
+    /// Names seen since the last call that matched no building, as
+    /// (school, name) pairs
+    pub fn take_unmatched(&self) -> Vec<(String, String)> {
+        let mut unmatched = self.unmatched.lock().unwrap();
+
+        std::mem::take(&mut *unmatched).into_iter().collect()
+    }
+}
+
+lazy_static! {
+    pub static ref BUILDINGS: BuildingRegistry =
+        match BuildingRegistry::load(&CONFIG.paths.buildings) {
+            Ok(registry) => registry,
+            Err(e) => {
+                error!("{}, building names will not be normalized", e);
+                BuildingRegistry::new(Vec::new())
+            }
+        };
+}
@@ -1,7 +1,12 @@
 use crate::course_api::*;
 use std::convert::TryInto;
 
-pub fn compute_timings(courses: Vec<Course>) -> (Vec<Vec<i64>>, Vec<Vec<i64>>) {
+/// Seats taken in classes starting and ending at each minute of each day
+/// (Monday first), counting only meetings whose location passes `include`
+pub fn compute_timings(
+    courses: &[Course],
+    include: impl Fn(&Location) -> bool,
+) -> (Vec<Vec<i64>>, Vec<Vec<i64>>) {
     let mut day_timings: Vec<i64> = Vec::new();
     
     for _ in 0..24 {
@@ -21,7 +26,16 @@ pub fn compute_timings(courses: Vec<Course>) -> (Vec<Vec<i64>>, Vec<Vec<i64>>) {
 
     for course in courses {
         for timing in course.get_timings() {
+            if !include(timing.get_location()) || !timing.has_time() {
+                continue;
+            }
+
             for day in timing.get_days() {
+                // Unscheduled meetings have no day to count towards
+                if day == Day::NA {
+                    continue;
+                }
+
                 start_timings[day.to_index()][timing.get_start_time_index() as usize] += course.get_seats_taken();
                 end_timings[day.to_index()][timing.get_end_time_index() as usize] += course.get_seats_taken();
             }
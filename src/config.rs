@@ -0,0 +1,384 @@
+// ----------------------------------------------------------------------------
+// Server configuration.
+//
+// Settings are layered, each overriding the last:
+//   1. Compiled-in defaults (the values that used to be constants)
+//   2. A TOML config file (./config.toml, or --config / FIVEC_CONFIG)
+//   3. Environment variables: FIVEC_<SECTION>__<KEY>, e.g. FIVEC_SERVER__ADDRESS
+//   4. Command line flags: --set <section>.<key>=<value>, plus shorthands
+//      like --address and --plain-http
+//
+// See config.example.toml for every available setting.
+// ----------------------------------------------------------------------------
+
+use ::serde::{Deserialize, Serialize};
+use clap::{Parser, Subcommand};
+use lazy_static::lazy_static;
+use std::path::PathBuf;
+use toml::Value;
+
+const DEFAULT_CONFIG_FILE: &str = "./config.toml";
+const ENV_PREFIX: &str = "FIVEC_";
+
+// Debug vs release address
+#[cfg(debug_assertions)]
+const DEFAULT_ADDRESS: &str = "127.0.0.1:8080";
+#[cfg(not(debug_assertions))]
+const DEFAULT_ADDRESS: &str = "0.0.0.0:8080";
+
+#[derive(Parser, Debug)]
+#[clap(name = "fivec-scheduler-server", about = "5scheduler server & API")]
+pub struct Cli {
+    #[clap(subcommand)]
+    pub command: Option<Command>,
+
+    /// Path to a TOML config file
+    #[clap(long, short, env = "FIVEC_CONFIG", global = true)]
+    pub config: Option<PathBuf>,
+
+    /// Address to bind to, shorthand for --set server.address=<ADDRESS>
+    #[clap(long, global = true)]
+    pub address: Option<String>,
+
+    /// Serve plain HTTP (e.g. behind a reverse proxy), shorthand for --set server.tls=false
+    #[clap(long, global = true)]
+    pub plain_http: bool,
+
+    /// Override any config value, e.g. --set update.api_update_interval=600
+    #[clap(long = "set", value_name = "KEY=VALUE", multiple_occurrences = true, global = true)]
+    pub overrides: Vec<String>,
+}
+
+/// What the binary should do. Everything but `serve` runs once and exits,
+/// so these can be run from cron or by hand.
+#[derive(Subcommand, Debug, Clone)]
+pub enum Command {
+    /// Run the API server and update loop (the default)
+    Serve,
+    /// Fetch courses, areas and perm counts, then save them to the cache
+    RefreshCourses,
+    /// Scrape every college catalog and merge descriptions into the course cache
+    RefreshCatalog {
+        /// Parse every catalog page again, even ones that haven't changed
+        #[clap(long)]
+        full: bool,
+    },
+    /// Fetch the next seven days of menus
+    RefreshMenus,
+    /// Look up coordinates for course locations missing from the locations cache
+    GeocodeLocations,
+    /// Write every cache as a single JSON document
+    Export {
+        /// File to write to, defaults to stdout
+        #[clap(long, short)]
+        output: Option<PathBuf>,
+    },
+    /// Check that every cache file loads and looks sane
+    ValidateCache,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ServerConfig {
+    pub address: String,
+    // When false, serve plain HTTP and ignore the certificate paths
+    pub tls: bool,
+    pub private_key: String,
+    pub certificate_chain: String,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            address: DEFAULT_ADDRESS.to_string(),
+            tls: true,
+            private_key: "/etc/letsencrypt/live/api.5scheduler.io/privkey.pem".to_string(),
+            certificate_chain: "/etc/letsencrypt/live/api.5scheduler.io/fullchain.pem".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UpdateConfig {
+    // Seconds per API update
+    pub api_update_interval: u64,
+    pub description_interval_multiplier: u64,
+    pub menu_interval_multiplier: u64,
+}
+
+impl Default for UpdateConfig {
+    fn default() -> Self {
+        Self {
+            api_update_interval: 1200,
+            description_interval_multiplier: 100,
+            menu_interval_multiplier: 100,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PathsConfig {
+    pub courses: String,
+    pub codes: String,
+    pub areas: String,
+    pub locations: String,
+    pub descriptions: String,
+    pub menus: String,
+    pub jay_place_menus: String,
+    pub buildings: String,
+    pub description_overrides: String,
+    pub catalog_pages: String,
+    pub requirements: String,
+    pub geocode_health: String,
+    pub cafe_health: String,
+}
+
+impl Default for PathsConfig {
+    fn default() -> Self {
+        Self {
+            courses: "./course_cache.json".to_string(),
+            codes: "./code_data.json".to_string(),
+            areas: "./areas_data.json".to_string(),
+            locations: "./locations.json".to_string(),
+            descriptions: "./descriptions.json".to_string(),
+            menus: "./menu_cache.json".to_string(),
+            jay_place_menus: "./jay_place_menus.json".to_string(),
+            buildings: "./buildings.json".to_string(),
+            description_overrides: "./description_overrides.json".to_string(),
+            catalog_pages: "./catalog_pages.json".to_string(),
+            requirements: "./requirements.json".to_string(),
+            geocode_health: "./geocode_health.json".to_string(),
+            cafe_health: "./cafe_health.json".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GeocodingConfig {
+    // Look up buildings missing from buildings.json on Nominatim
+    pub nominatim_fallback: bool,
+    // Sent to Nominatim with each request, as their usage policy asks
+    pub nominatim_email: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MatchingConfig {
+    // Fuzzy title matches scoring below this (0 to 1) get no description
+    pub min_score: f32,
+    // Matches scoring below this are listed by /descriptions/matches
+    pub review_score: f32,
+}
+
+impl Default for MatchingConfig {
+    fn default() -> Self {
+        Self {
+            min_score: 0.6,
+            review_score: 0.8,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CatalogConfig {
+    // Key for the Acalog API. Without one, catalogs are scraped from their
+    // print pages only.
+    pub acalog_key: String,
+    pub keck: GraduateCatalog,
+    pub claremont_graduate: GraduateCatalog,
+}
+
+/// A graduate school's catalog, found by the cur_cat_oid and navoid in the
+/// address of its course descriptions page. Both are required, see
+/// Config::check_graduate_catalogs.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GraduateCatalog {
+    pub catalog: u64,
+    pub navoid: u64,
+}
+
+impl GraduateCatalog {
+    pub fn is_set(&self) -> bool {
+        self.catalog != 0 && self.navoid != 0
+    }
+}
+
+// Cafe IDs for cafebonappetit, or URLs for Sodexo/Eatec
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CafesConfig {
+    pub mcconnell: String,
+    pub pit_stop: String,
+    pub mallot: String,
+    pub collins: String,
+    pub hub: String,
+    pub hoch: String,
+    pub frank: String,
+    pub frary: String,
+    pub oldenborg: String,
+}
+
+impl Default for CafesConfig {
+    fn default() -> Self {
+        Self {
+            mcconnell: "219".to_string(),
+            pit_stop: "220".to_string(),
+            mallot: "2253".to_string(),
+            collins: "50".to_string(),
+            hub: "51".to_string(),
+            hoch: "https://menus.sodexomyway.com/BiteMenu/MenuOnly?menuId=15258&locationId=13147001&whereami=http://hmc.sodexomyway.com/dining-near-me/hoch-shanahan-dining-commons".to_string(),
+            frank: "https://my.pomona.edu/eatec/Frank.json".to_string(),
+            frary: "https://my.pomona.edu/eatec/Frary.json".to_string(),
+            oldenborg: "https://my.pomona.edu/eatec/Oldenborg.json".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub update: UpdateConfig,
+    pub paths: PathsConfig,
+    pub geocoding: GeocodingConfig,
+    pub matching: MatchingConfig,
+    pub catalog: CatalogConfig,
+    pub cafes: CafesConfig,
+}
+
+lazy_static! {
+    pub static ref CLI: Cli = Cli::parse();
+    pub static ref CONFIG: Config = match Config::load(&CLI) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("Invalid configuration: {}", e);
+            std::process::exit(2);
+        }
+    };
+}
+
+impl Config {
+    pub fn load(cli: &Cli) -> Result<Config, String> {
+        let mut table = match &cli.config {
+            Some(path) => read_config_file(path)?,
+            None => {
+                let path = PathBuf::from(DEFAULT_CONFIG_FILE);
+
+                if path.exists() {
+                    read_config_file(&path)?
+                } else {
+                    Value::Table(toml::value::Table::new())
+                }
+            }
+        };
+
+        // Environment overrides
+        for (key, value) in std::env::vars() {
+            if !key.starts_with(ENV_PREFIX) || !key.contains("__") {
+                continue;
+            }
+
+            let path = key[ENV_PREFIX.len()..].to_lowercase().replace("__", ".");
+            set_value(&mut table, &path, &value)?;
+        }
+
+        // Command line overrides
+        for entry in &cli.overrides {
+            let (path, value) = entry
+                .split_once('=')
+                .ok_or(format!("Expected KEY=VALUE, got \"{}\"", entry))?;
+
+            set_value(&mut table, path.trim(), value.trim())?;
+        }
+
+        if let Some(address) = &cli.address {
+            set_value(&mut table, "server.address", address)?;
+        }
+
+        if cli.plain_http {
+            set_value(&mut table, "server.tls", "false")?;
+        }
+
+        table.try_into::<Config>().map_err(|e| e.to_string())
+    }
+
+    /// Checks the graduate catalog IDs, which have no usable default since
+    /// they change every year. Rather than quietly going without KGI and
+    /// CGU descriptions, anything that scrapes catalogs won't start
+    /// without them.
+    pub fn check_graduate_catalogs(&self) -> Result<(), String> {
+        let graduate = [
+            ("catalog.keck", &self.catalog.keck),
+            ("catalog.claremont_graduate", &self.catalog.claremont_graduate),
+        ];
+
+        for (name, catalog) in graduate {
+            if !catalog.is_set() {
+                return Err(format!(
+                    "{} needs its catalog and navoid, from the address of that catalog's course descriptions page",
+                    name
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn read_config_file(path: &PathBuf) -> Result<Value, String> {
+    let data = std::fs::read_to_string(path)
+        .map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+
+    toml::from_str(&data).map_err(|e| format!("Could not parse {}: {}", path.display(), e))
+}
+
+/// Sets a dotted path like "server.address" in the config table, parsing
+/// the raw value as whatever type the default config has at that path.
+fn set_value(table: &mut Value, path: &str, raw: &str) -> Result<(), String> {
+    let defaults = Value::try_from(Config::default()).map_err(|e| e.to_string())?;
+    let keys: Vec<&str> = path.split('.').collect();
+
+    let mut default = &defaults;
+    for key in &keys {
+        default = default
+            .get(key)
+            .ok_or(format!("Unknown setting \"{}\"", path))?;
+    }
+
+    let value = match default {
+        Value::String(_) => Value::String(raw.to_string()),
+        Value::Integer(_) => Value::Integer(
+            raw.parse::<i64>()
+                .map_err(|_| format!("Expected a number for \"{}\", got \"{}\"", path, raw))?,
+        ),
+        Value::Boolean(_) => Value::Boolean(
+            raw.parse::<bool>()
+                .map_err(|_| format!("Expected true or false for \"{}\", got \"{}\"", path, raw))?,
+        ),
+        _ => return Err(format!("\"{}\" is a section, not a setting", path)),
+    };
+
+    let mut current = table;
+
+    for (i, key) in keys.iter().enumerate() {
+        let map = current
+            .as_table_mut()
+            .ok_or(format!("\"{}\" is not a section", path))?;
+
+        if i == keys.len() - 1 {
+            map.insert(key.to_string(), value);
+            return Ok(());
+        }
+
+        current = map
+            .entry(key.to_string())
+            .or_insert(Value::Table(toml::value::Table::new()));
+    }
+
+    Ok(())
+}
@@ -1,14 +1,26 @@
+use crate::buildings::*;
+use crate::credits::*;
 use crate::database::*;
+use crate::geo::Coordinates;
+use crate::health::*;
+use crate::identifier::*;
+use crate::meet_time::*;
+use crate::fees::*;
+use crate::offered::*;
+use crate::requirements::REQUIREMENTS;
 use crate::http::Method;
 use crate::scrape_descriptions::*;
 use crate::menu::*;
 use ::serde::*;
 use chrono::*;
+use lazy_static::lazy_static;
+use log::warn;
 use regex::Regex;
 use reqwest::header::*;
 use reqwest::*;
 use serde_json::Value;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::time::Instant;
 
 const SCHEDULE_API_URL: &str = "https://webapps.cmc.edu/course-search/search.php?";
 
@@ -20,6 +32,12 @@ const POM_HEADERS: &str = "text/json; charset=utf-8";
 const COURSE_REGEX: &str = r"([A-Z]+){1} *([0-9]+[ -Z]{0,3}){1} {0,2}([A-Z]{2})?-([0-9]*)";
 
 const TIME_FMT: &str = "%I:%M%p";
+// "08/30/2021-10/15/2021", the dates of a half-semester meeting
+const DATE_RANGE_REGEX: &str = r"(\d{1,2}/\d{1,2}/\d{4}) *- *(\d{1,2}/\d{1,2}/\d{4})";
+
+lazy_static! {
+    static ref DATE_RANGE: Regex = Regex::new(DATE_RANGE_REGEX).unwrap();
+}
 
 #[derive(Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
 pub enum CourseStatus {
@@ -65,9 +83,63 @@ impl School {
             _ => School::NA,
         }
     }
+
+    /// Parses a school from a query string, either as a code ("HM", "HMC")
+    /// or by name ("HarveyMudd"), ignoring case
+    pub fn from_query(s: &str) -> Option<School> {
+        let school = School::new_from_string(&s.to_uppercase());
+
+        if school != School::NA {
+            return Some(school);
+        }
+
+        [
+            School::ClaremontMckenna,
+            School::Pitzer,
+            School::Pomona,
+            School::HarveyMudd,
+            School::Scripps,
+            School::Keck,
+            School::ClaremontGraduate,
+            School::NA,
+        ]
+        .iter()
+        .find(|school| format!("{:?}", school).eq_ignore_ascii_case(s))
+        .cloned()
+    }
 }
 
 impl Day {
+    /// Parses a day from a query string, either as its letter ("M", "R")
+    /// or by name ("Monday"), ignoring case
+    pub fn from_query(s: &str) -> Option<Day> {
+        let s = s.trim();
+        let mut chars = s.chars();
+
+        let day = match (chars.next(), chars.next()) {
+            (Some(c), None) => Day::new_from_char(c.to_ascii_uppercase()),
+            _ => [
+                Day::Monday,
+                Day::Tuesday,
+                Day::Wednesday,
+                Day::Thursday,
+                Day::Friday,
+                Day::Saturday,
+                Day::Sunday,
+            ]
+            .iter()
+            .find(|day| format!("{:?}", day).eq_ignore_ascii_case(s))
+            .cloned()
+            .unwrap_or(Day::NA),
+        };
+
+        if day == Day::NA {
+            None
+        } else {
+            Some(day)
+        }
+    }
+
     pub fn new_from_char(c: char) -> Self {
         match c {
             'M' => Day::Monday,
@@ -113,9 +185,56 @@ pub struct Location {
     school: School,
     building: String,
     room: String,
+    // ID in the building registry, if the building was found there
+    #[serde(default)]
+    building_id: Option<String>,
 }
 
 impl Location {
+    /// Creates a location with the building normalized against the
+    /// registry. Unknown buildings keep their name and get no ID.
+    pub fn new(school: School, building: String, room: String) -> Self {
+        match BUILDINGS.resolve(&school, &building) {
+            Some(found) => Location {
+                // Fill in the school if the source didn't give one
+                school: if school == School::NA { found.school.clone() } else { school },
+                building: found.name.clone(),
+                room,
+                building_id: Some(found.id.clone()),
+            },
+            None => Location {
+                school,
+                building: building.trim().to_string(),
+                room,
+                building_id: None,
+            },
+        }
+    }
+
+    pub fn get_building_id(&self) -> Option<&String> {
+        self.building_id.as_ref()
+    }
+
+    /// Whether this is "Arranged", "Online" or similar rather than a place
+    pub fn is_placeholder(&self) -> bool {
+        self.building.trim().is_empty()
+            || BUILDINGS
+                .find(&self.school, &self.building)
+                .map_or(false, |b| b.placeholder)
+    }
+
+    pub fn get_school(&self) -> &School {
+        &self.school
+    }
+
+    pub fn get_building(&self) -> &String {
+        &self.building
+    }
+
+    pub fn get_room(&self) -> &String {
+        &self.room
+    }
+
     pub fn get_minimal_location(&self) -> String {
         format!("{} {}", self.building, self.room)
     }
@@ -129,12 +248,65 @@ impl Location {
     }
 }
 
+/// How a meeting happens. Arranged and TBA meetings have no time, and
+/// their start and end times are both 00:00.
+#[derive(Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub enum MeetingKind {
+    InPerson,
+    Online,
+    // Time arranged with the instructor
+    Arranged,
+    // Time not announced yet
+    TBA,
+}
+
+impl Default for MeetingKind {
+    fn default() -> Self {
+        MeetingKind::InPerson
+    }
+}
+
+impl MeetingKind {
+    /// Classifies a meeting from its (normalized) location and the raw
+    /// schedule text, `timed` being whether a time could be parsed
+    fn classify(timed: bool, location: &Location, raw: &str) -> Self {
+        let building_id = location.get_building_id().map(|id| id.as_str());
+
+        let arranged = building_id == Some("arranged")
+            || raw
+                .to_uppercase()
+                .split(|c: char| !c.is_alphanumeric())
+                .any(|word| word == "ARR" || word == "ARRANGED");
+
+        if building_id == Some("online") {
+            MeetingKind::Online
+        } else if timed {
+            MeetingKind::InPerson
+        } else if arranged {
+            MeetingKind::Arranged
+        } else {
+            MeetingKind::TBA
+        }
+    }
+}
+
 #[derive(Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
 pub struct CourseTiming {
     days: Vec<Day>,
     start_time: NaiveTime,
     end_time: NaiveTime,
     location: Location,
+    #[serde(default)]
+    kind: MeetingKind,
+    // Set when the meeting doesn't run the whole term (half-semester, PE)
+    #[serde(default)]
+    start_date: Option<NaiveDate>,
+    #[serde(default)]
+    end_date: Option<NaiveDate>,
+    // Every room of a meeting held in more than one, the first being
+    // location's. Empty for the usual single room.
+    #[serde(default)]
+    rooms: Vec<String>,
 }
 
 #[derive(Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
@@ -150,9 +322,142 @@ impl SharedCourseList {
             custom_courses,
         }
     }
+
+    /// Every course on the schedule, local then custom
+    pub fn courses(&self) -> impl Iterator<Item = &Course> {
+        self.local_courses.iter().chain(self.custom_courses.iter())
+    }
+}
+
+/// Days from a code like "MWF", or just Day::NA if it isn't one ("TBA",
+/// "ARR", blank)
+fn parse_days(code: &str) -> Vec<Day> {
+    let code = code.trim();
+
+    if code.is_empty() || !code.chars().all(|c| "MTWRFSU".contains(c)) {
+        return vec![Day::NA];
+    }
+
+    code.chars().map(Day::new_from_char).collect()
+}
+
+/// Both times of a meeting, or None if either can't be parsed
+fn parse_times(start: &str, end: &str) -> Option<(NaiveTime, NaiveTime)> {
+    let start = NaiveTime::parse_from_str(start.trim(), TIME_FMT).ok()?;
+    let end = NaiveTime::parse_from_str(end.trim(), TIME_FMT).ok()?;
+
+    Some((start, end))
+}
+
+/// A date as the Pomona API ("2021-08-30T00:00:00") or the CMC search
+/// ("08/30/2021") writes it
+fn parse_date(date: &str) -> Option<NaiveDate> {
+    let date = date.trim();
+
+    NaiveDateTime::parse_from_str(date, "%Y-%m-%dT%H:%M:%S")
+        .map(|dt| dt.date())
+        .or_else(|_| NaiveDate::parse_from_str(date, "%Y-%m-%d"))
+        .or_else(|_| NaiveDate::parse_from_str(date, "%m/%d/%Y"))
+        .ok()
+}
+
+/// Finds a "08/30/2021-10/15/2021" range in schedule text, returning the
+/// dates and the text with the range removed
+fn extract_date_range(text: &str) -> (Option<NaiveDate>, Option<NaiveDate>, String) {
+    match DATE_RANGE.captures(text) {
+        Some(captures) => (
+            parse_date(&captures[1]),
+            parse_date(&captures[2]),
+            DATE_RANGE.replace(text, "").replace("()", "").trim().to_string(),
+        ),
+        None => (None, None, text.to_string()),
+    }
 }
 
 impl CourseTiming {
+    /// `times` is None for meetings without a time, which get 00:00 for
+    /// both and a kind from the location and `raw` schedule text
+    pub fn new(
+        days: Vec<Day>,
+        times: Option<(NaiveTime, NaiveTime)>,
+        location: Location,
+        raw: &str,
+        (start_date, end_date): (Option<NaiveDate>, Option<NaiveDate>),
+    ) -> Self {
+        let kind = MeetingKind::classify(times.is_some(), &location, raw);
+        let midnight = NaiveTime::from_hms(0, 0, 0);
+        let (start_time, end_time) = times.unwrap_or((midnight, midnight));
+
+        Self {
+            days,
+            start_time,
+            end_time,
+            location,
+            kind,
+            start_date,
+            end_date,
+            rooms: Vec::new(),
+        }
+    }
+
+    /// Sets the rooms of a meeting held in several at once, which stays a
+    /// single timing
+    pub fn with_rooms(mut self, mut rooms: Vec<String>) -> Self {
+        let mut seen = HashSet::new();
+        rooms.retain(|room| seen.insert(room.clone()));
+
+        if rooms.len() > 1 {
+            self.rooms = rooms;
+        }
+
+        self
+    }
+
+    /// Every room the meeting is in
+    pub fn get_rooms(&self) -> Vec<String> {
+        if self.rooms.is_empty() {
+            vec![self.location.room.clone()]
+        } else {
+            self.rooms.clone()
+        }
+    }
+
+    pub fn get_kind(&self) -> &MeetingKind {
+        &self.kind
+    }
+
+    pub fn get_date_range(&self) -> (Option<NaiveDate>, Option<NaiveDate>) {
+        (self.start_date, self.end_date)
+    }
+
+    /// Whether the meeting runs on a date. Meetings without dates run the
+    /// whole term.
+    pub fn runs_on(&self, date: NaiveDate) -> bool {
+        self.start_date.is_none_or(|start| start <= date) && self.end_date.is_none_or(|end| date <= end)
+    }
+
+    /// Whether two meetings run on any of the same dates, so a first-half
+    /// and a second-half course never clash
+    pub fn dates_overlap(&self, other: &CourseTiming) -> bool {
+        let starts_by = |start: Option<NaiveDate>, end: Option<NaiveDate>| match (start, end) {
+            (Some(start), Some(end)) => start <= end,
+            _ => true,
+        };
+
+        starts_by(self.start_date, other.end_date) && starts_by(other.start_date, self.end_date)
+    }
+
+    /// Whether the meeting has a real time. A class can't start and end
+    /// at midnight, so 00:00-00:00 also means no time, as in caches
+    /// written before meetings had a kind.
+    pub fn has_time(&self) -> bool {
+        let untimed = self.kind == MeetingKind::Arranged || self.kind == MeetingKind::TBA;
+
+        let midnight = NaiveTime::from_hms(0, 0, 0);
+
+        !untimed && !(self.start_time == midnight && self.end_time == midnight)
+    }
+
     pub fn get_days_code(&self) -> String {
         self.days.iter().map(|x| x.to_char()).collect::<String>()
     }
@@ -164,6 +469,10 @@ impl CourseTiming {
         self.location.get_full_location()
     }
 
+    pub fn get_location(&self) -> &Location {
+        &self.location
+    }
+
     pub fn get_start_time_str(&self) -> String {
         format!("{}", self.start_time.format(TIME_FMT))
     }
@@ -176,12 +485,13 @@ impl CourseTiming {
         self.days.clone()
     }
 
+    // Minute of the day, 0..1440
     pub fn get_start_time_index(&self) -> u32 {
-        self.start_time.hour() + (self.start_time.minute() * 60)
+        self.start_time.hour() * 60 + self.start_time.minute()
     }
 
     pub fn get_end_time_index(&self) -> u32 {
-        self.end_time.hour() + (self.end_time.minute() * 60)
+        self.end_time.hour() * 60 + self.end_time.minute()
     }
 }
 
@@ -198,7 +508,7 @@ pub struct Course {
     seats_taken: i64,
     seats_remaining: i64,
     credits: u64, // Credits * 100, so 3.50 credits is 350, allowing for a decimal
-    credits_hmc: u64,
+    credits_hmc: u64, // HMC units * 100, exact for every course, see credits.rs
     status: CourseStatus,
     timing: Vec<CourseTiming>,
     instructors: Vec<String>,
@@ -207,14 +517,30 @@ pub struct Course {
     prerequisites: String,
     corequisites: String,
     offered: String,
+    #[serde(default)]
+    offered_schedule: OfferedSchedule,
     perm_count: u64,
     fee: u64,
-    associations: Vec<String>
+    #[serde(default)]
+    fees: Vec<Fee>,
+    associations: Vec<String>,
+    // IDs of the requirements in requirements.json it counts for
+    #[serde(default)]
+    fulfills: Vec<String>,
 }
 
 impl Course {
+    /// Canonical identifier from separate fields, or the fields joined
+    /// as they are if they don't parse
     pub fn create_identifier(code: String, id: String, dept: String, section: String) -> String {
-        format!("{}-{}-{}-{}", code, id, dept, section)
+        CourseIdentifier::from_parts(&code, &id, &dept, &section)
+            .map(|identifier| identifier.to_string())
+            .unwrap_or_else(|_| format!("{}-{}-{}-{}", code, id, dept, section))
+    }
+
+    /// The parsed identifier, None for custom courses with made up ones
+    pub fn get_course_identifier(&self) -> Option<CourseIdentifier> {
+        self.identifier.parse().ok()
     }
 
     pub fn get_identifier(&self) -> &String {
@@ -264,6 +590,10 @@ impl Course {
         &self.credits
     }
 
+    pub fn get_exact_credits(&self) -> u64 {
+        self.credits_hmc
+    }
+
     pub fn get_timings(&self) -> Vec<CourseTiming> {
         self.timing.clone()
     }
@@ -343,14 +673,33 @@ impl Course {
         self.corequisites.clone()
     }
 
-    pub fn set_fee(&mut self, fee: u64) {
-        self.fee = fee
+    /// Also sets `fee` to the first one in whole dollars
+    pub fn set_fees(&mut self, fees: Vec<Fee>) {
+        self.fee = first_fee_dollars(&fees);
+        self.fees = fees;
     }
 
     pub fn get_fee(&self) -> u64 {
         self.fee
     }
 
+    pub fn get_fees(&self) -> &Vec<Fee> {
+        &self.fees
+    }
+
+    pub fn get_offered(&self) -> &String {
+        &self.offered
+    }
+
+    pub fn set_offered(&mut self, offered: String, schedule: OfferedSchedule) {
+        self.offered = offered;
+        self.offered_schedule = schedule;
+    }
+
+    pub fn get_offered_schedule(&self) -> &OfferedSchedule {
+        &self.offered_schedule
+    }
+
     pub fn format_reqs(rec: String) -> String {
         let mut reqs = rec;
 
@@ -397,7 +746,7 @@ impl Course {
 
         let description = pom["Description"].as_str().unwrap_or("").to_string();
 
-        let mut credits = (pom["Credits"].as_str().unwrap_or("").replace("\"","").parse::<f64>().unwrap_or(0.)) as u64 * 100;
+        let listed_credits = pom["Credits"].as_str().unwrap_or("");
 
         let mut instrutors = Vec::new();
 
@@ -457,66 +806,62 @@ impl Course {
             let building = time["Building"].as_str().unwrap_or("").to_string();
             let meet_time = time["MeetTime"].as_str().unwrap_or("").to_string();
 
-            let times = meet_time.split(".").nth(0).unwrap_or("").split("-").collect::<Vec<&str>>();
-
-            println!("{:?}", times);
-            
-            let mut start_time = times.get(0).unwrap_or(&"12:00AM").to_string();
-            let end_time = times.get(1).unwrap_or(&"12:00AM").to_string();
-
-            if start_time.len() == 5 {
-                start_time = format!("{}{}", start_time, end_time[5..].to_string());
-            }
-
-            let start_time =
-            NaiveTime::parse_from_str(start_time.trim(), TIME_FMT)
-                .unwrap_or(NaiveTime::from_hms(0, 0, 0));
-
-            let end_time =
-            NaiveTime::parse_from_str(end_time.trim(), TIME_FMT)
-                .unwrap_or(NaiveTime::from_hms(0, 0, 0));
-
-            let days = time["Weekdays"].as_str().unwrap_or("").to_string();
-
-            let days: Vec<Day> = days
-                .chars()
-                .map(|c| Day::new_from_char(c))
-                .collect();
-
-            let mut room_building = meet_time.split_once(".").unwrap_or(("", meet_time.as_str())).1.to_string();
-            
-            if room_building.contains("(") {
-                room_building = room_building.split_once("(").unwrap().0.to_string();
-            }
-
-            let room = room_building.split_once("Room").unwrap_or(("", room_building.as_str())).1.trim().to_string();
-
-            let location = Location {
-                building,
-                room,
-                school,
+            // Dates on the schedule entry, then on the course, then in the text
+            let (text_start, text_end, meet_time) = extract_date_range(&meet_time);
+            let date_field = |field: &str| {
+                time[field]
+                    .as_str()
+                    .or_else(|| pom[field].as_str())
+                    .and_then(parse_date)
             };
+            let dates = (
+                date_field("StartDate").or(text_start),
+                date_field("EndDate").or(text_end),
+            );
 
+            // A blank MeetTime just means there's nothing scheduled yet
+            let parsed = match parse_meet_time(&meet_time) {
+                Ok(parsed) => parsed,
+                Err(MeetTimeError::Empty) => MeetTime::default(),
+                Err(e) => {
+                    warn!("Could not parse MeetTime for {}: {}", identifier, e);
+                    MeetTime::default()
+                }
+            };
 
-            let time = CourseTiming {
-                start_time,
-                end_time,
-                location,
-                days,
+            let days = time["Weekdays"]
+                .as_str()
+                .filter(|days| !days.trim().is_empty())
+                .or(parsed.days.as_deref())
+                .unwrap_or("");
+            let days = parse_days(days);
+
+            // One timing per meeting, however many rooms it's in
+            let places = if parsed.places.is_empty() {
+                vec![String::new()]
+            } else {
+                parsed.places
             };
 
-            timing.push(time);
-        }
+            let found = BUILDINGS.find(&school, &building);
+            let rooms: Vec<String> = places
+                .iter()
+                .map(|place| BUILDINGS.extract_room(found, place))
+                .collect();
+            let location = Location::new(school.clone(), building.clone(), rooms[0].clone());
 
+            timing.push(CourseTiming::new(days, parsed.times, location, &meet_time, dates).with_rooms(rooms));
+        }
 
-        let credits_hmc: u64;
 
-        if pom["PrimaryAssociation"].as_str().unwrap_or("") == "HM" {
-            credits_hmc = credits;
-            credits = credits / 3;
+        let system = if pom["PrimaryAssociation"].as_str().unwrap_or("") == "HM" {
+            CreditSystem::HmcUnits
         } else {
-            credits_hmc = credits * 3;
-        }
+            CreditSystem::CourseCredits
+        };
+
+        let credits_hmc = exact_credits(listed_credits, system, identifier_split[0]);
+        let credits = CreditSystem::CourseCredits.round(credits_hmc);
 
         Course {
             identifier: identifier.clone(),
@@ -535,10 +880,13 @@ impl Course {
             prerequisites: String::new(),
             corequisites: String::new(),
             fee: 0,
+            fees: Vec::new(),
+            fulfills: Vec::new(),
             perm_count,
             notes: String::new(),
             status,
             offered: "".to_string(),
+            offered_schedule: OfferedSchedule::default(),
             credits_hmc,
             associations: Vec::new(),
         }
@@ -547,6 +895,18 @@ impl Course {
     pub fn add_association(&mut self, association: String) {
         self.associations.push(association);
     }
+
+    pub fn get_associations(&self) -> &Vec<String> {
+        &self.associations
+    }
+
+    pub fn set_fulfills(&mut self, fulfills: Vec<String>) {
+        self.fulfills = fulfills;
+    }
+
+    pub fn get_fulfills(&self) -> &Vec<String> {
+        &self.fulfills
+    }
 }
 
 #[derive(Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
@@ -571,7 +931,7 @@ impl PartialPomCourse {
 
         let description = pom["Description"].as_str().unwrap_or("").to_string();
 
-        let credits = (pom["Credits"].as_str().unwrap_or("").replace("\"","").parse::<f64>().unwrap_or(0.)) as u64 * 100;
+        let credits = parse_hundredths(pom["Credits"].as_str().unwrap_or("")).unwrap_or(0);
 
         PartialPomCourse {
             course_code,
@@ -584,50 +944,16 @@ impl PartialPomCourse {
     }
 }
 
+/// Canonical identifier for a course code like "AFRI010A AF-01", or the
+/// code without whitespace if it doesn't parse
 pub fn convert_course_code_to_identifier(code: &str) -> String {
-    let mut normalized_course_code = code.to_string();
-    normalized_course_code.retain(|c| !c.is_whitespace());
-
-    let mut other: String;
-    let section: String;
-
-    if normalized_course_code.contains("-") {
-        let mut split = normalized_course_code.split("-");
-        other = split.nth(0).unwrap().to_string();
-        section = split.nth(0).unwrap().to_string();
-    } else {
-        other = normalized_course_code;
-        section = "".to_string();
-    }
-
-    let dept = other[other.len() - 2..other.len()].to_string();
-    other = other[..other.len() - 2].to_string();
-
-    let mut split_point = 0;
-    let mut found_letter = false;
-    let mut found_num = false;
-
-    for (i, c) in other.chars().enumerate() {
-        if c.is_alphabetic() {
-            found_letter = true;
-        } else if c.is_numeric() {
-            found_num = true;
-        }
-
-        if found_letter && found_num {
-            split_point = i;
-            break;
-        }
-    }
-
-    let code = &other[..split_point];
-    let id = &other[split_point..];
-
-    if section == "" {
-        format!("{}-{}-{}", code, id, dept)
-    } else {
-        format!("{}-{}-{}-{}", code, id, dept, section)    
-    }
+    CourseIdentifier::from_code(code)
+        .map(|identifier| identifier.to_string())
+        .unwrap_or_else(|_| {
+            let mut code = code.to_string();
+            code.retain(|c| !c.is_whitespace());
+            code
+        })
 }
 
 pub fn get_rows_clean(raw_text: &String) -> Option<Vec<String>> {
@@ -670,64 +996,43 @@ pub fn group_rows_as_courses(rows: Vec<String>) -> Vec<Vec<String>> {
     courses
 }
 
-pub fn html_group_to_course(group: Vec<String>) -> Course {
-    // Get dept
-    let first_number = group[0].chars().position(|c| c.is_numeric()).unwrap();
-    let (code, second_half) = group[0].split_at(first_number);
-
-    let code = code.trim().to_string();
-
-    let mut second_half: Vec<&str> = second_half.split_whitespace().collect();
-
-    let mut id: String;
-    let mut dept: String;
-    let mut section: String;
-    // Did they forget to put a space?
-    if second_half.len() == 3 {
-        id = second_half[0].to_string();
-        id = id[..id.len() - 2].to_string();
-        dept = second_half[0][id.len()..].to_string();
-        section = second_half[2].to_string();
-    } else {
-        // Get the rest of the course info
-        id = second_half.get(0).unwrap().to_string();
-        dept = second_half.get(1).unwrap().to_string();
-        section = second_half.get(3).unwrap_or(&"typo").to_string();
+/// A course from one row of the CMC search, None if the row can't be read
+pub fn html_group_to_course(group: Vec<String>) -> Option<Course> {
+    if group.len() < 7 {
+        return None;
     }
 
-    if section == "typo" {
-        section = dept;
-        dept = format!("{}{}", id.pop().unwrap(), id.pop().unwrap());
-    }
+    // Get subject, number, college and section
+    let parsed = CourseIdentifier::from_code(&group[0]).ok()?;
+
+    let code = parsed.subject.clone();
+    let id = parsed.number_with_suffix();
+    let dept = parsed.college.clone();
+    let section = parsed.section.clone().unwrap_or_default();
 
     // Get full title of course
     let title: String = group[1]
         .split(">")
-        .nth(1)
-        .unwrap()
+        .nth(1)?
         .split("<")
-        .nth(0)
-        .unwrap()
+        .nth(0)?
         .to_string();
 
     // Get seating numbers
     let seats_remaining = group[2]
         .split("/")
-        .next()
-        .unwrap()
+        .next()?
         .trim()
         .parse::<i64>()
-        .unwrap();
+        .ok()?;
     let max_seats = group[2]
         .split("/")
-        .nth(1)
-        .unwrap()
+        .nth(1)?
         .trim()
         .split_whitespace()
-        .next()
-        .unwrap()
+        .next()?
         .parse::<i64>()
-        .unwrap();
+        .ok()?;
     let seats_taken = max_seats - seats_remaining;
 
     // Get status
@@ -742,7 +1047,7 @@ pub fn html_group_to_course(group: Vec<String>) -> Course {
     }
 
     // Get credits
-    let mut credits: u64 = (group[3].trim().parse::<f64>().unwrap_or(0.) * 100.).floor() as u64;
+    let listed_credits = group[3].trim();
 
     // Get timing(s)
     let mut timing = Vec::new();
@@ -752,30 +1057,27 @@ pub fn html_group_to_course(group: Vec<String>) -> Course {
     let mut at_hmc = false;
 
     for t in timing_list {
+        // Half-semester meetings carry their dates in the row
+        let (start_date, end_date, t) = extract_date_range(t);
+        let t = t.as_str();
+
         let mut split = t.split_whitespace();
 
-        let days: Vec<Day> = split
-            .nth(0)
-            .unwrap()
-            .chars()
-            .map(|c| Day::new_from_char(c))
-            .collect();
+        let days = parse_days(split.next()?);
 
-        let mut timing_split = split.nth(0).unwrap_or("12:00AM-12:00AM").split("-");
+        let mut timing_split = split.nth(0).unwrap_or("").split("-");
 
-        let start_time =
-            NaiveTime::parse_from_str(timing_split.nth(0).unwrap_or("12:00AM").trim(), TIME_FMT)
-                .unwrap_or(NaiveTime::from_hms(0, 0, 0));
-        let end_time =
-            NaiveTime::parse_from_str(timing_split.nth(0).unwrap_or("12:00AM").trim(), TIME_FMT)
-                .unwrap_or(NaiveTime::from_hms(0, 0, 0));
+        let times = parse_times(
+            timing_split.nth(0).unwrap_or(""),
+            timing_split.nth(0).unwrap_or(""),
+        );
 
         let mut split = t.split("/");
 
-        let mut location_string = split.nth(1).unwrap().trim().split(",");
+        let mut location_string = split.nth(1)?.trim().split(",");
 
         // Convert two char school code to school pub enum
-        let school = School::new_from_string(&location_string.nth(0).unwrap().trim()[0..2]);
+        let school = School::new_from_string(location_string.next()?.trim().get(0..2)?);
 
         if school == School::HarveyMudd {
             at_hmc = true;
@@ -795,18 +1097,9 @@ pub fn html_group_to_course(group: Vec<String>) -> Course {
             .trim_end_matches(",")
             .to_string();
 
-        let location = Location {
-            school,
-            building,
-            room,
-        };
+        let location = Location::new(school, building, room);
 
-        timing.push(CourseTiming {
-            days,
-            start_time,
-            end_time,
-            location,
-        });
+        timing.push(CourseTiming::new(days, times, location, t, (start_date, end_date)));
     }
 
     // Get instructors
@@ -833,20 +1126,18 @@ pub fn html_group_to_course(group: Vec<String>) -> Course {
         .replace("<BR>", ". ")
         .replace("..", ".");
 
-    // Create identifier
-    let identifier =
-        Course::create_identifier(code.clone(), id.clone(), dept.clone(), section.clone());
+    let identifier = parsed.to_string();
 
-    let credits_hmc: u64;
-
-    if at_hmc {
-        credits_hmc = credits;
-        credits = credits / 3;
+    let system = if at_hmc {
+        CreditSystem::HmcUnits
     } else {
-        credits_hmc = credits * 3;
-    }
+        CreditSystem::CourseCredits
+    };
+
+    let credits_hmc = exact_credits(listed_credits, system, &code);
+    let credits = CreditSystem::CourseCredits.round(credits_hmc);
 
-    Course {
+    Some(Course {
         identifier,
         id,
         title,
@@ -866,10 +1157,13 @@ pub fn html_group_to_course(group: Vec<String>) -> Course {
         prerequisites: "".to_string(),
         corequisites: "".to_string(),
         offered: "".to_string(),
+        offered_schedule: OfferedSchedule::default(),
         perm_count: 0,
         fee: 0,
+        fees: Vec::new(),
         associations: Vec::new(),
-    }
+        fulfills: Vec::new(),
+    })
 }
 
 pub fn get_term(raw_text: &String) -> Option<&str> {
@@ -900,19 +1194,29 @@ pub async fn get_all_courses() -> Result<(String, Vec<Course>)> {
     // Group rows into courses
     let html_grouped_rows = group_rows_as_courses(html_rows.unwrap());
 
-    // Convert each group of rows into a Course
+    // Convert each group of rows into a Course, skipping rows that can't be
+    // read rather than losing the whole update
     let courses: Vec<Course> = html_grouped_rows
         .into_iter()
-        .map(|x| html_group_to_course(x))
+        .filter_map(|x| {
+            let code = x.first().cloned().unwrap_or_default();
+            let course = html_group_to_course(x);
+
+            if course.is_none() {
+                warn!("Skipping CMC search row that couldn't be read: \"{}\"", code);
+            }
+
+            course
+        })
         .collect();
 
     Ok((term.unwrap().to_string(), courses))
 }
 
 pub fn merge_locations(
-    current_locations: HashMap<String, (String, String)>,
-    new_locations: HashMap<String, (String, String)>,
-) -> HashMap<String, (String, String)> {
+    current_locations: HashMap<String, Coordinates>,
+    new_locations: HashMap<String, Coordinates>,
+) -> HashMap<String, Coordinates> {
     let mut locations = current_locations;
 
     for (key, value) in new_locations {
@@ -1074,39 +1378,98 @@ pub async fn get_pom_courses(
         }
     }
 
-    let courses = find_reqs_courses(&mut courses);
+    let mut courses = find_reqs_courses(&mut courses);
+
+    for course in courses.iter_mut() {
+        course.set_fulfills(REQUIREMENTS.fulfilled_by(course.get_associations()));
+    }
 
     Ok((courses, areas))
 }
 
-pub async fn full_pomona_update() -> Result<(String, Vec<Course>, Vec<CourseArea>)> {
+async fn pomona_api_update() -> std::result::Result<(Term, Vec<Course>, Vec<CourseArea>), String> {
     // First, get the course areas from the API
-    let areas = get_areas().await.unwrap();
+    let areas = get_areas().await.map_err(|e| e.to_string())?;
 
     // Then, get the course terms
-    let terms = get_terms().await.unwrap();
+    let terms = get_terms().await.map_err(|e| e.to_string())?;
+    let term = terms.get(0).cloned().ok_or("No terms found")?;
 
     // Then, get the courses for each area
-    let (mut courses, areas) = get_pom_courses(areas, terms[0].clone()).await.unwrap();
+    let (courses, areas) = get_pom_courses(areas, term.clone())
+        .await
+        .map_err(|e| e.to_string())?;
+
+    if courses.is_empty() {
+        return Err("No courses found".to_string());
+    }
+
+    Ok((term, courses, areas))
+}
+
+pub async fn full_pomona_update() -> std::result::Result<(String, Vec<Course>, Vec<CourseArea>), String> {
+    let started = Instant::now();
+    let pomona_update = pomona_api_update().await;
+    record_result(&Source::PomonaApi, started, &pomona_update, |(_, courses, _)| courses.len()).await;
+
+    let (term, mut courses, areas) = pomona_update?;
 
     // Then, get courses from CMC url for locations and seats
+    let started = Instant::now();
     let cmc_courses = get_all_courses().await;
 
-    if let Ok(cmc_courses) = cmc_courses {
-        courses = merge_seats(&mut courses, &cmc_courses.1);
-        courses = merge_timings(&mut courses, &cmc_courses.1);
+    match cmc_courses {
+        Ok(cmc_courses) if !cmc_courses.1.is_empty() => {
+            record_success(&Source::CmcScrape, started, cmc_courses.1.len()).await;
+
+            courses = merge_seats(&mut courses, &cmc_courses.1);
+            courses = merge_timings(&mut courses, &cmc_courses.1);
+        }
+        Ok(_) => record_failure(&Source::CmcScrape, started, "No courses found".to_string()).await,
+        Err(e) => record_failure(&Source::CmcScrape, started, e.to_string()).await,
+    }
+
+    let unmatched = BUILDINGS.take_unmatched();
+
+    if !unmatched.is_empty() {
+        warn!(
+            "{} building name(s) missing from the building registry: {:?}",
+            unmatched.len(),
+            unmatched
+        );
+    }
+
+    Ok((term.Description, courses, areas))
+}
+
+/// Perm numbers for the current term, used to fill in `perm_count`
+pub async fn get_current_perm_numbers() -> std::result::Result<HashMap<String, u64>, String> {
+    let terms = get_terms().await.map_err(|e| e.to_string())?;
+    let term = terms.get(0).ok_or("No terms found")?;
+
+    get_perm_numbers(&term.Key).await.map_err(|e| e.to_string())
+}
+
+/// Maps each identifier to the first course with it. Courses whose
+/// identifiers don't parse are only matched by the exact string.
+fn index_by_identifier(courses: &Vec<Course>) -> HashMap<IdentifierKey, &Course> {
+    let mut index = HashMap::new();
+
+    for course in courses {
+        index.entry(identifier_key(course.get_identifier())).or_insert(course);
     }
 
-    Ok((terms[0].clone().Description, courses, areas))
+    index
 }
 
 pub fn merge_seats(
     courses: &mut Vec<Course>,
     other_courses: &Vec<Course>,
 ) -> Vec<Course> {
+    let other_courses = index_by_identifier(other_courses);
 
     for course in courses.iter_mut() {
-        let cmc_course = other_courses.iter().find(|x| x.get_identifier() == course.get_identifier());
+        let cmc_course = other_courses.get(&identifier_key(course.get_identifier()));
 
         if let Some(cmc_course) = cmc_course {
             course.set_seats(cmc_course.get_seats_remaining().clone(), cmc_course.get_max_seats().clone());
@@ -1120,8 +1483,10 @@ pub fn merge_timings(
     courses: &mut Vec<Course>,
     other_courses: &Vec<Course>,
 ) -> Vec<Course> {
+    let other_courses = index_by_identifier(other_courses);
+
     for course in courses.iter_mut() {
-        let cmc_course = other_courses.iter().find(|x| x.get_identifier() == course.get_identifier());
+        let cmc_course = other_courses.get(&identifier_key(course.get_identifier()));
 
         if let Some(cmc_course) = cmc_course {
             course.set_timings(cmc_course.get_timings().clone());
@@ -1132,8 +1497,16 @@ pub fn merge_timings(
 }
 
 pub fn merge_perms_into_courses(courses: Vec<Course>, perm_hashmap: HashMap<String, u64>) -> Vec<Course> {
+    let perm_hashmap: HashMap<CourseIdentifier, u64> = perm_hashmap
+        .into_iter()
+        .filter_map(|(identifier, count)| Some((identifier.parse().ok()?, count)))
+        .collect();
+
     let courses = courses.iter().map(|course| {
-        let perm_count = perm_hashmap.get(course.get_identifier()).unwrap_or(&0);
+        let perm_count = course
+            .get_course_identifier()
+            .and_then(|identifier| perm_hashmap.get(&identifier))
+            .unwrap_or(&0);
         let mut new_course = course.clone();
         new_course.perm_count = *perm_count;
         new_course
@@ -1142,62 +1515,62 @@ pub fn merge_perms_into_courses(courses: Vec<Course>, perm_hashmap: HashMap<Stri
     courses
 }
 
-pub async fn test_full_update() {
-    let course_tuple = full_pomona_update().await.unwrap();
-    println!("{:?}", course_tuple.1);
-    let all_courses = course_tuple.1;
-    let term = course_tuple.0;
-    let areas = course_tuple.2;
-
-    let terms = get_terms().await.unwrap();
-    let perm_map = get_perm_numbers(&terms[0].Key).await.unwrap();
-    println!("{:?}", perm_map);
-
-    let all_courses = merge_perms_into_courses(all_courses, perm_map);
-
-    save_course_database(all_courses.clone()).unwrap();
-    save_areas_database(areas.clone()).unwrap();
-    /*
-    let current_locations = load_locations_database().unwrap();
-
-    let new_locations = get_locations(all_courses.clone()).await;
-
-    let new_locations = merge_locations(current_locations, new_locations);
-
-    let mut writer = OpenOptions::new()
-        .create(true)
-        .write(true)
-        .open("locations.json").unwrap();
-
-    let serialized_output = serde_json::to_string(&new_locations).unwrap();
-
-    writer.write(serialized_output.as_bytes());
-
-     */
-
-    let mut all_descriptions = scrape_all_descriptions().await.unwrap();
-
-    //let mut all_descriptions = load_descriptions_database().unwrap();
-
-    let all_descriptions = find_reqs(&mut all_descriptions);
-
-    println!("Scraped {} descriptions", all_descriptions.len());
-
-    let final_courses = merge_description_and_courses(all_courses, all_descriptions);
-
-    let courses = final_courses.0;
-    let descriptions = final_courses.1;
-
-    save_course_database(courses.clone()).unwrap();
-    save_descriptions_database(descriptions.clone()).unwrap();
-
-    assert_eq!(courses, load_course_database().unwrap())
-}
-
-pub async fn test_menu_update() {
-    let menus = get_seven_day_menus().await.unwrap();
-
-    // Save them to json
-    save_menu_datebase(menus.clone()).unwrap();
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    // Slices of real caches, with expected values written by the merges as
+    // they were before they were indexed
+    const NEW_COURSES: &str = include_str!("../fixtures/merge/new_courses.json");
+    const CMC_COURSES: &str = include_str!("../fixtures/merge/cmc_courses.json");
+    const MERGE_SEATS_TIMINGS: &str = include_str!("../fixtures/merge/expected/merge_seats_timings.json");
+
+    fn timings(course: &Course) -> Value {
+        course
+            .get_timings()
+            .iter()
+            .map(|t| {
+                let t = serde_json::to_value(t).unwrap();
+                let location = &t["location"];
+
+                json!([
+                    t["days"],
+                    t["start_time"],
+                    t["end_time"],
+                    location["school"],
+                    location["building"],
+                    location["room"]
+                ])
+            })
+            .collect()
+    }
+
+    #[test]
+    fn merges_seats_and_timings_as_before() {
+        let mut courses: Vec<Course> = serde_json::from_str(NEW_COURSES).unwrap();
+        let cmc: Vec<Course> = serde_json::from_str(CMC_COURSES).unwrap();
+        let expected: Vec<Value> = serde_json::from_str(MERGE_SEATS_TIMINGS).unwrap();
+
+        let mut courses = merge_seats(&mut courses, &cmc);
+        let courses = merge_timings(&mut courses, &cmc);
+
+        let merged: Vec<Value> = courses
+            .iter()
+            .map(|c| {
+                json!([
+                    c.get_identifier(),
+                    c.get_seats_remaining(),
+                    c.get_max_seats(),
+                    c.seats_taken,
+                    timings(c)
+                ])
+            })
+            .collect();
 
+        assert_eq!(merged.len(), expected.len());
+        for (merged, expected) in merged.iter().zip(&expected) {
+            assert_eq!(merged, expected);
+        }
+    }
 }
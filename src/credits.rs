@@ -0,0 +1,120 @@
+// ----------------------------------------------------------------------------
+// Course credit across the colleges.
+//
+// HMC counts units, three to a course; Pomona, CMC, Scripps and Pitzer count
+// course credits, so a full course is 1.0, a half course 0.5 and a quarter
+// course 0.25. Each course lists credit in its own college's system, so it's
+// kept exactly as hundredths of an HMC unit (300 to a course credit), which
+// holds a hundredth of either system without rounding. Totals are added up
+// in those and only rounded once, into the viewer's home-college units.
+//
+// The four course-credit colleges name it differently (Pomona "credits",
+// Pitzer and CMC "courses", Scripps "credits") but count the same thing: a
+// full semester course is one, and they list half and quarter courses the
+// same way. The upstream API lists KGI and CGU courses in course credits
+// too, so without anything better their students get totals in those.
+// CreditSystem::of names every college, so a new one has to be decided.
+// ----------------------------------------------------------------------------
+
+use crate::course_api::{Course, School};
+use ::serde::{Deserialize, Serialize};
+
+// Hundredths of an HMC unit in one of each system's hundredths
+const PER_COURSE_CREDIT: u64 = 3;
+const PER_HMC_UNIT: u64 = 1;
+
+// PE activity courses are half a course credit, though the listings give
+// them none
+const PE_CREDITS: u64 = 150;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CreditSystem {
+    CourseCredits,
+    HmcUnits,
+}
+
+impl CreditSystem {
+    /// The system a college lists its courses in, and its students count
+    /// their credit in
+    pub fn of(school: &School) -> CreditSystem {
+        match school {
+            School::HarveyMudd => CreditSystem::HmcUnits,
+            School::Pomona | School::ClaremontMckenna | School::Scripps | School::Pitzer => {
+                CreditSystem::CourseCredits
+            }
+            // As listed, see the top of the file
+            School::Keck | School::ClaremontGraduate => CreditSystem::CourseCredits,
+            // No home college
+            School::NA => CreditSystem::CourseCredits,
+        }
+    }
+
+    fn scale(self) -> u64 {
+        match self {
+            CreditSystem::CourseCredits => PER_COURSE_CREDIT,
+            CreditSystem::HmcUnits => PER_HMC_UNIT,
+        }
+    }
+
+    /// Hundredths in this system, rounded to the nearest
+    pub fn round(self, exact: u64) -> u64 {
+        (exact + self.scale() / 2) / self.scale()
+    }
+
+    pub fn exact(self, hundredths: u64) -> u64 {
+        hundredths * self.scale()
+    }
+}
+
+/// Hundredths in a listed credit value, "1.5" is 150, read without going
+/// through a float. None if it isn't a number.
+pub fn parse_hundredths(listed: &str) -> Option<u64> {
+    let listed = listed.trim().trim_matches('"');
+    let (whole, fraction) = listed.split_once('.').unwrap_or((listed, ""));
+
+    if whole.is_empty() && fraction.is_empty() {
+        return None;
+    }
+
+    if !whole.chars().chain(fraction.chars()).all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+
+    let whole: u64 = if whole.is_empty() { 0 } else { whole.parse().ok()? };
+
+    // Past hundredths only rounds
+    let mut digits = fraction.chars().map(|c| c.to_digit(10).unwrap() as u64);
+    let tenths = digits.next().unwrap_or(0);
+    let hundredths = digits.next().unwrap_or(0);
+    let round_up = digits.next().is_some_and(|d| d >= 5);
+
+    Some(whole * 100 + tenths * 10 + hundredths + round_up as u64)
+}
+
+/// A course's exact credit from its listing, in the system of the college
+/// that offers it. PE courses listed without credit get PE_CREDITS.
+pub fn exact_credits(listed: &str, system: CreditSystem, subject: &str) -> u64 {
+    match parse_hundredths(listed).unwrap_or(0) {
+        0 if subject.trim().eq_ignore_ascii_case("PE") => PE_CREDITS,
+        hundredths => system.exact(hundredths),
+    }
+}
+
+/// Credit for a schedule, in hundredths of the home college's units
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CreditTotal {
+    pub school: School,
+    pub units: CreditSystem,
+    pub total: u64,
+}
+
+pub fn total_credits<'a>(courses: impl Iterator<Item = &'a Course>, home: &School) -> CreditTotal {
+    let units = CreditSystem::of(home);
+    let exact: u64 = courses.map(|course| course.get_exact_credits()).sum();
+
+    CreditTotal {
+        school: home.clone(),
+        units,
+        total: units.round(exact),
+    }
+}
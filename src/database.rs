@@ -1,21 +1,19 @@
+use crate::config::CONFIG;
 use crate::course_api::*;
+use crate::geo::Coordinates;
+use crate::health::SourceHealth;
 use crate::menu::SchoolMenu;
+use crate::merge_index::*;
 use crate::scrape_descriptions::*;
 use bimap::*;
 use std::fs::OpenOptions;
-use std::io::{Error, Read, Write};
+use std::io::{Error, ErrorKind, Read, Write};
 use std::collections::HashMap;
+use log::warn;
 use rand::prelude::SliceRandom;
 use rand::rngs::SmallRng;
 use rand::SeedableRng;
 
-const COURSE_DATABASE_NAME: &str = "./course_cache.json";
-const CODE_DATA_NAME: &str = "./code_data.json";
-const AREAS_DATA_NAME: &str = "./areas_data.json";
-const LOCATION_NAME: &str = "./locations.json";
-const DESCRIPTION_NAME: &str = "./descriptions.json";
-const MENU_DATABASE_NAME: &str = "./menu_cache.json";
-
 const POSSIBLE_CODE_CHARS: &'static [char] = &[
     '2', '3', '4', '6', '7', '9', 'Q', 'W', 'E', 'R', 'T', 'Y', 'U', 'P', 'A', 'D', 'F', 'G', 'H',
     'X',
@@ -23,7 +21,7 @@ const POSSIBLE_CODE_CHARS: &'static [char] = &[
 const CODE_LENGTH: u8 = 7;
 
 pub fn load_course_database() -> Result<Vec<Course>, Error> {
-    let file = OpenOptions::new().read(true).open(COURSE_DATABASE_NAME);
+    let file = OpenOptions::new().read(true).open(&CONFIG.paths.courses);
 
     if file.is_err() {
         return Ok(Vec::new());
@@ -32,7 +30,7 @@ pub fn load_course_database() -> Result<Vec<Course>, Error> {
 
         let mut data = String::new();
         file.read_to_string(&mut data)?;
-        let courses: Vec<Course> = from_slice_lenient(&data.as_bytes()).unwrap();
+        let courses: Vec<Course> = from_slice_lenient(&data.as_bytes()).map_err(invalid_data)?;
         Ok(courses)
     }
 }
@@ -41,7 +39,7 @@ pub fn save_course_database(courses: Vec<Course>) -> Result<(), Error> {
     let mut writer = OpenOptions::new()
         .create(true)
         .write(true)
-        .open(COURSE_DATABASE_NAME)?;
+        .open(&CONFIG.paths.courses)?;
 
     let serialized_output = serde_json::to_string(&courses).unwrap();
 
@@ -51,7 +49,7 @@ pub fn save_course_database(courses: Vec<Course>) -> Result<(), Error> {
 }
 
 pub fn load_areas_database() -> Result<Vec<CourseArea>, Error> {
-    let file = OpenOptions::new().read(true).open(AREAS_DATA_NAME);
+    let file = OpenOptions::new().read(true).open(&CONFIG.paths.areas);
 
     if file.is_err() {
         return Ok(Vec::new());
@@ -60,7 +58,7 @@ pub fn load_areas_database() -> Result<Vec<CourseArea>, Error> {
 
         let mut data = String::new();
         file.read_to_string(&mut data)?;
-        let areas: Vec<CourseArea> = from_slice_lenient(&data.as_bytes()).unwrap();
+        let areas: Vec<CourseArea> = from_slice_lenient(&data.as_bytes()).map_err(invalid_data)?;
         Ok(areas)
     }
 }
@@ -69,7 +67,7 @@ pub fn save_areas_database(areas: Vec<CourseArea>) -> Result<(), Error> {
     let mut writer = OpenOptions::new()
         .create(true)
         .write(true)
-        .open(AREAS_DATA_NAME)?;
+        .open(&CONFIG.paths.areas)?;
 
     let serialized_output = serde_json::to_string(&areas).unwrap();
 
@@ -79,7 +77,7 @@ pub fn save_areas_database(areas: Vec<CourseArea>) -> Result<(), Error> {
 }
 
 pub fn load_code_database() -> Result<BiHashMap<String, SharedCourseList>, Error> {
-    let file = OpenOptions::new().read(true).open(CODE_DATA_NAME);
+    let file = OpenOptions::new().read(true).open(&CONFIG.paths.codes);
 
     if file.is_err() {
         return Ok(BiHashMap::new());
@@ -88,7 +86,7 @@ pub fn load_code_database() -> Result<BiHashMap<String, SharedCourseList>, Error
 
         let mut data = String::new();
         file.read_to_string(&mut data)?;
-        let courses: BiHashMap<String, SharedCourseList> = from_slice_lenient(&data.as_bytes()).unwrap();
+        let courses: BiHashMap<String, SharedCourseList> = from_slice_lenient(&data.as_bytes()).map_err(invalid_data)?;
         Ok(courses)
     }
 }
@@ -97,7 +95,7 @@ pub fn save_code_database(code_hashmap: BiHashMap<String, SharedCourseList>) ->
     let mut writer = OpenOptions::new()
         .create(true)
         .write(true)
-        .open(CODE_DATA_NAME)?;
+        .open(&CONFIG.paths.codes)?;
 
     let serialized_output = serde_json::to_string(&code_hashmap).unwrap();
 
@@ -106,23 +104,65 @@ pub fn save_code_database(code_hashmap: BiHashMap<String, SharedCourseList>) ->
     Ok(())
 }
 
-pub fn load_locations_database() -> Result<HashMap<String, (String, String)>, Error> {
-    let file = OpenOptions::new().read(true).open(LOCATION_NAME);
+/// Reads the locations cache, returning the valid entries and a
+/// description of every entry with blank or invalid coordinates
+pub fn read_locations_database() -> Result<(HashMap<String, Coordinates>, Vec<String>), Error> {
+    let file = OpenOptions::new().read(true).open(&CONFIG.paths.locations);
 
     if file.is_err() {
-        return Ok(HashMap::new());
+        return Ok((HashMap::new(), Vec::new()));
     } else {
         let mut file = file.unwrap();
 
         let mut data = String::new();
         file.read_to_string(&mut data)?;
-        let locations: HashMap<String, (String, String)> = from_slice_lenient(&data.as_bytes()).unwrap();
-        Ok(locations)
+        let raw: HashMap<String, serde_json::Value> = from_slice_lenient(&data.as_bytes()).map_err(invalid_data)?;
+
+        let mut locations = HashMap::new();
+        let mut invalid = Vec::new();
+
+        for (key, value) in raw {
+            match serde_json::from_value::<Coordinates>(value.clone()) {
+                Ok(coordinates) => {
+                    locations.insert(key, coordinates);
+                }
+                Err(e) => invalid.push(format!("{} ({}): {}", key, value, e)),
+            }
+        }
+
+        invalid.sort();
+
+        Ok((locations, invalid))
     }
 }
 
+/// Loads the locations cache, skipping entries with invalid coordinates
+pub fn load_locations_database() -> Result<HashMap<String, Coordinates>, Error> {
+    let (locations, invalid) = read_locations_database()?;
+
+    for entry in invalid {
+        warn!("Skipping location {}", entry);
+    }
+
+    Ok(locations)
+}
+
+pub fn save_locations_database(locations: HashMap<String, Coordinates>) -> Result<(), Error> {
+    let mut writer = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(true)
+        .open(&CONFIG.paths.locations)?;
+
+    let serialized_output = serde_json::to_string(&locations).unwrap();
+
+    writer.write(serialized_output.as_bytes())?;
+
+    Ok(())
+}
+
 pub fn load_descriptions_database() -> Result<Vec<CourseDescription>, Error> {
-    let file = OpenOptions::new().read(true).open(DESCRIPTION_NAME);
+    let file = OpenOptions::new().read(true).open(&CONFIG.paths.descriptions);
 
     if file.is_err() {
         return Ok(Vec::new());
@@ -131,8 +171,12 @@ pub fn load_descriptions_database() -> Result<Vec<CourseDescription>, Error> {
 
         let mut data = String::new();
         file.read_to_string(&mut data)?;
-        let descriptions: Vec<CourseDescription> = from_slice_lenient(&data.as_bytes()).unwrap();
-        Ok(descriptions)
+        let mut descriptions: Vec<CourseDescription> = from_slice_lenient(&data.as_bytes()).map_err(invalid_data)?;
+
+        // Saved before schedules and fees were parsed, or by an older parser
+        parse_schedules(&mut descriptions);
+
+        Ok(find_fees(&mut descriptions))
     }
 }
 
@@ -140,7 +184,7 @@ pub fn save_descriptions_database(descriptions: Vec<CourseDescription>) -> Resul
     let mut writer = OpenOptions::new()
         .create(true)
         .write(true)
-        .open(DESCRIPTION_NAME)?;
+        .open(&CONFIG.paths.descriptions)?;
 
     let serialized_output = serde_json::to_string(&descriptions).unwrap();
 
@@ -149,8 +193,120 @@ pub fn save_descriptions_database(descriptions: Vec<CourseDescription>) -> Resul
     Ok(())
 }
 
+/// Catalog pages from the last refresh. Without the file every page is
+/// parsed.
+pub fn load_catalog_pages() -> Result<CatalogPages, Error> {
+    let mut file = match OpenOptions::new().read(true).open(&CONFIG.paths.catalog_pages) {
+        Ok(file) => file,
+        Err(_) => return Ok(CatalogPages::default()),
+    };
+
+    let mut data = String::new();
+    file.read_to_string(&mut data)?;
+
+    from_slice_lenient(data.as_bytes()).map_err(invalid_data)
+}
+
+pub fn save_catalog_pages(pages: &CatalogPages) -> Result<(), Error> {
+    let mut writer = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(true)
+        .open(&CONFIG.paths.catalog_pages)?;
+
+    let serialized_output = serde_json::to_string(pages).unwrap();
+
+    writer.write_all(serialized_output.as_bytes())?;
+
+    Ok(())
+}
+
+/// Geocoding health from the last geocode-locations run, which happens in
+/// its own process. None if it hasn't run.
+pub fn load_geocode_health() -> Result<Option<SourceHealth>, Error> {
+    let mut file = match OpenOptions::new().read(true).open(&CONFIG.paths.geocode_health) {
+        Ok(file) => file,
+        Err(_) => return Ok(None),
+    };
+
+    let mut data = String::new();
+    file.read_to_string(&mut data)?;
+
+    serde_json::from_str(&data).map(Some).map_err(invalid_data)
+}
+
+pub fn save_geocode_health(health: &SourceHealth) -> Result<(), Error> {
+    let mut writer = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(true)
+        .open(&CONFIG.paths.geocode_health)?;
+
+    let serialized_output = serde_json::to_string(health).unwrap();
+
+    writer.write_all(serialized_output.as_bytes())?;
+
+    Ok(())
+}
+
+/// Cafe health from the last refresh-menus run, which also happens in its
+/// own process. Keyed like the health map, empty if it hasn't run.
+pub fn load_cafe_health() -> Result<HashMap<String, SourceHealth>, Error> {
+    let mut file = match OpenOptions::new().read(true).open(&CONFIG.paths.cafe_health) {
+        Ok(file) => file,
+        Err(_) => return Ok(HashMap::new()),
+    };
+
+    let mut data = String::new();
+    file.read_to_string(&mut data)?;
+
+    serde_json::from_str(&data).map_err(invalid_data)
+}
+
+pub fn save_cafe_health(health: &HashMap<String, SourceHealth>) -> Result<(), Error> {
+    let mut writer = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(true)
+        .open(&CONFIG.paths.cafe_health)?;
+
+    let serialized_output = serde_json::to_string(health).unwrap();
+
+    writer.write_all(serialized_output.as_bytes())?;
+
+    Ok(())
+}
+
+/// Hand-written pins and blocks for description matching. The file is
+/// optional, there are no overrides without it.
+pub fn load_description_overrides() -> Result<Vec<DescriptionOverride>, Error> {
+    let file = OpenOptions::new().read(true).open(&CONFIG.paths.description_overrides);
+
+    if file.is_err() {
+        return Ok(Vec::new());
+    } else {
+        let mut file = file.unwrap();
+
+        let mut data = String::new();
+        file.read_to_string(&mut data)?;
+        let overrides: Vec<DescriptionOverride> = serde_json::from_str(&data).map_err(invalid_data)?;
+        Ok(overrides)
+    }
+}
+
+/// The overrides file and configured minimum score, for matching courses
+/// to descriptions
+pub fn load_match_rules() -> MatchRules {
+    let overrides = load_description_overrides().unwrap_or_else(|e| {
+        warn!("Ignoring description overrides: {}", e);
+        Vec::new()
+    });
+
+    MatchRules::new(overrides, CONFIG.matching.min_score)
+}
+
 pub fn load_menu_database() -> Result<HashMap<School, SchoolMenu>, Error> {
-    let file = OpenOptions::new().read(true).open(MENU_DATABASE_NAME);
+    let file = OpenOptions::new().read(true).open(&CONFIG.paths.menus);
 
     if file.is_err() {
         return Ok(HashMap::new());
@@ -159,7 +315,7 @@ pub fn load_menu_database() -> Result<HashMap<School, SchoolMenu>, Error> {
 
         let mut data = String::new();
         file.read_to_string(&mut data)?;
-        let menus: HashMap<School, SchoolMenu> = from_slice_lenient(&data.as_bytes()).unwrap();
+        let menus: HashMap<School, SchoolMenu> = from_slice_lenient(&data.as_bytes()).map_err(invalid_data)?;
         Ok(menus)
     }
 }
@@ -168,7 +324,7 @@ pub fn save_menu_datebase(menus: HashMap<School, SchoolMenu>) -> Result<(), Erro
     let mut writer = OpenOptions::new() 
         .create(true)
         .write(true)
-        .open(MENU_DATABASE_NAME)?;
+        .open(&CONFIG.paths.menus)?;
 
     let serialized_output = serde_json::to_string(&menus).unwrap();
 
@@ -179,12 +335,11 @@ pub fn save_menu_datebase(menus: HashMap<School, SchoolMenu>) -> Result<(), Erro
 
 pub fn generate_unique_code(
     shared_course_list: SharedCourseList,
-    code_hashmap: BiHashMap<String, SharedCourseList>,
-) -> (String, BiHashMap<String, SharedCourseList>) {
+    code_hashmap: &mut BiHashMap<String, SharedCourseList>,
+) -> String {
     // Check if the database already contains course list
-    if code_hashmap.contains_right(&shared_course_list) {
-        let code = code_hashmap.get_by_right(&shared_course_list).unwrap().clone();
-        return (code, code_hashmap);
+    if let Some(code) = code_hashmap.get_by_right(&shared_course_list) {
+        return code.clone();
     }
     let mut small_rng = SmallRng::from_entropy();
 
@@ -199,25 +354,22 @@ pub fn generate_unique_code(
         if code_hashmap.contains_left(&attempt) {
             continue;
         } else {
-            let mut code_hashmap = code_hashmap.clone();
-
-            code_hashmap.insert(attempt.clone(), shared_course_list.clone());
+            code_hashmap.insert(attempt.clone(), shared_course_list);
 
-            return (attempt, code_hashmap);
+            return attempt;
         }
     }
 }
 
 pub fn get_course_list(
-    code: String,
-    code_hashmap: BiHashMap<String, SharedCourseList>,
+    code: &str,
+    code_hashmap: &BiHashMap<String, SharedCourseList>,
 ) -> Option<SharedCourseList> {
-    let result = code_hashmap.get_by_left(&code);
+    code_hashmap.get_by_left(code).cloned()
+}
 
-    match result {
-        Some(result) => Some(result.clone()),
-        None => None,
-    }
+fn invalid_data(e: serde_json::Error) -> Error {
+    Error::new(ErrorKind::InvalidData, e)
 }
 
 fn from_slice_lenient<'a, T: ::serde::Deserialize<'a>>(
@@ -0,0 +1,198 @@
+// ----------------------------------------------------------------------------
+// Course fees, parsed from catalog descriptions and course notes.
+//
+// They're written in the text: "$150 course fee.", "Laboratory fee: $50.",
+// "Fee: Lab fee $75; Materials fee $75", "$50 fee to cover transportation
+// costs." Each amount in a clause that mentions a fee or charge becomes a
+// Fee in cents, typed by the words around it and kept with the clause it
+// came from. Amounts in other clauses ("an estimated cost of over $600
+// billion") aren't fees.
+// ----------------------------------------------------------------------------
+
+use ::serde::{Deserialize, Serialize};
+use lazy_static::lazy_static;
+use regex::Regex;
+
+lazy_static! {
+    // "$1,200", "$45.50", "$ 75", "$600 billion"
+    static ref AMOUNT: Regex =
+        Regex::new(r"\$\s?(\d{1,3}(?:,\d{3})+|\d+)(?:\.(\d{2}))?(\s+(?:thousand|million|billion))?").unwrap();
+    // Clauses end at a period, semicolon or line break, but not at the
+    // point in "$45.50"
+    static ref CLAUSE_END: Regex = Regex::new(r"[.;!?](?:\s+|$)|\n").unwrap();
+    static ref FEE_WORD: Regex = Regex::new(r"(?i)\b(fees?|charges?)\b").unwrap();
+    static ref LAB: Regex = Regex::new(r"(?i)\b(lab|labs|laboratory)\b").unwrap();
+    static ref MATERIALS: Regex =
+        Regex::new(r"(?i)\b(materials?|suppl(y|ies)|kits?|equipment)\b").unwrap();
+    static ref TRAVEL: Regex =
+        Regex::new(r"(?i)\b(travel|trips?|transportation|excursions?|bus)\b").unwrap();
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FeeKind {
+    Lab,
+    Materials,
+    Travel,
+    // A course or program fee, or one the text doesn't explain
+    Other,
+}
+
+impl FeeKind {
+    fn named_in(text: &str) -> Option<FeeKind> {
+        if LAB.is_match(text) {
+            Some(FeeKind::Lab)
+        } else if MATERIALS.is_match(text) {
+            Some(FeeKind::Materials)
+        } else if TRAVEL.is_match(text) {
+            Some(FeeKind::Travel)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Fee {
+    pub kind: FeeKind,
+    // $45.50 is 4550
+    pub cents: u64,
+    // The clause it was read from, "Materials fee $75"
+    pub text: String,
+}
+
+/// Every fee in a description or note, in the order they're written
+pub fn parse_fees(text: &str) -> Vec<Fee> {
+    let mut fees = Vec::new();
+    let mut start = 0;
+
+    let ends = CLAUSE_END
+        .find_iter(text)
+        .map(|m| (m.start(), m.end()))
+        .chain(std::iter::once((text.len(), text.len())));
+
+    for (end, next) in ends {
+        let clause = &text[start..end];
+        start = next;
+
+        if !FEE_WORD.is_match(clause) {
+            continue;
+        }
+
+        let amounts: Vec<_> = AMOUNT.captures_iter(clause).collect();
+
+        for (i, amount) in amounts.iter().enumerate() {
+            // "$600 billion"
+            if amount.get(3).is_some() {
+                continue;
+            }
+
+            let dollars: u64 = match amount[1].replace(',', "").parse() {
+                Ok(dollars) => dollars,
+                Err(_) => continue,
+            };
+            let cents: u64 = amount.get(2).map_or(0, |c| c.as_str().parse().unwrap_or(0));
+
+            // Too large to be a real fee
+            let cents = match dollars.checked_mul(100).and_then(|d| d.checked_add(cents)) {
+                Some(cents) => cents,
+                None => continue,
+            };
+
+            // The words after the amount ("$50 lab fee"), up to the next
+            // one, else the ones before it ("Lab fee $75")
+            let whole = amount.get(0).unwrap();
+            let after_end = amounts.get(i + 1).map_or(clause.len(), |next| next.get(0).unwrap().start());
+            let before_start = i.checked_sub(1).map_or(0, |previous| amounts[previous].get(0).unwrap().end());
+
+            let kind = FeeKind::named_in(&clause[whole.end()..after_end])
+                .or_else(|| FeeKind::named_in(&clause[before_start..whole.start()]))
+                .unwrap_or(FeeKind::Other);
+
+            fees.push(Fee {
+                kind,
+                cents,
+                text: clause.trim().to_string(),
+            });
+        }
+    }
+
+    fees
+}
+
+/// The first fee in whole dollars, what `fee` held before fees were typed
+pub fn first_fee_dollars(fees: &[Fee]) -> u64 {
+    fees.first().map_or(0, |fee| fee.cents / 100)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fee(kind: FeeKind, cents: u64, text: &str) -> Fee {
+        Fee {
+            kind,
+            cents,
+            text: text.to_string(),
+        }
+    }
+
+    #[test]
+    fn course_fees() {
+        assert_eq!(parse_fees("$150 course fee."), vec![fee(FeeKind::Other, 15000, "$150 course fee")]);
+        assert_eq!(parse_fees("Course Fee $150"), vec![fee(FeeKind::Other, 15000, "Course Fee $150")]);
+    }
+
+    #[test]
+    fn kinds_from_the_words_around_the_amount() {
+        assert_eq!(
+            parse_fees("Laboratory fee: $50. "),
+            vec![fee(FeeKind::Lab, 5000, "Laboratory fee: $50")]
+        );
+        assert_eq!(
+            parse_fees("The course has Saturday field trips. $50 fee to cover transportation costs."),
+            vec![fee(FeeKind::Travel, 5000, "$50 fee to cover transportation costs")]
+        );
+    }
+
+    #[test]
+    fn several_fees_in_a_clause() {
+        assert_eq!(
+            parse_fees("Fee: Lab fee $75; Materials fee $75"),
+            vec![
+                fee(FeeKind::Lab, 7500, "Fee: Lab fee $75"),
+                fee(FeeKind::Materials, 7500, "Materials fee $75"),
+            ]
+        );
+        assert_eq!(
+            parse_fees("A $50 lab fee and a $20 materials fee."),
+            vec![
+                fee(FeeKind::Lab, 5000, "A $50 lab fee and a $20 materials fee"),
+                fee(FeeKind::Materials, 2000, "A $50 lab fee and a $20 materials fee"),
+            ]
+        );
+    }
+
+    #[test]
+    fn thousands_and_cents() {
+        assert_eq!(parse_fees("Lab fee: $1,200."), vec![fee(FeeKind::Lab, 120000, "Lab fee: $1,200")]);
+        assert_eq!(
+            parse_fees("Materials fee $45.50."),
+            vec![fee(FeeKind::Materials, 4550, "Materials fee $45.50")]
+        );
+    }
+
+    #[test]
+    fn amounts_that_are_not_fees() {
+        assert!(parse_fees("It costs over $600 billion in fees annually.").is_empty());
+        assert!(parse_fees("TB testing is available for $10.").is_empty());
+        assert!(parse_fees("No fee.").is_empty());
+        assert!(parse_fees("Lab fee $184467440737095517.").is_empty());
+    }
+
+    #[test]
+    fn first_fee_in_dollars() {
+        assert_eq!(first_fee_dollars(&parse_fees("Materials fee $45.50.")), 45);
+        assert_eq!(first_fee_dollars(&[]), 0);
+    }
+}
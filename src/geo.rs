@@ -0,0 +1,304 @@
+// ----------------------------------------------------------------------------
+// Coordinates and the campus map.
+//
+// Coordinates are signed degrees everywhere: the locations cache, menus and
+// the building registry. The locations cache used to store them as pairs of
+// strings, which are still accepted when reading old files.
+// ----------------------------------------------------------------------------
+
+use crate::buildings::BUILDINGS;
+use crate::course_api::*;
+use crate::menu::*;
+use crate::snapshot::Snapshot;
+use ::serde::{Deserialize, Serialize};
+use chrono::Local;
+use lazy_static::lazy_static;
+use log::warn;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::convert::TryFrom;
+use std::sync::Mutex;
+
+const EARTH_RADIUS_METERS: f64 = 6_371_000.0;
+
+lazy_static! {
+    // Cafes already reported as missing from the map, so each is logged once
+    static ref UNMAPPED_CAFES: Mutex<HashSet<String>> = Mutex::new(HashSet::new());
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "RawCoordinates")]
+pub struct Coordinates {
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+impl Coordinates {
+    pub fn new(latitude: f64, longitude: f64) -> Result<Self, String> {
+        if !(-90.0..=90.0).contains(&latitude) || !(-180.0..=180.0).contains(&longitude) {
+            return Err(format!("Coordinates out of range: {}, {}", latitude, longitude));
+        }
+
+        Ok(Self { latitude, longitude })
+    }
+
+    /// Reads a latitude and longitude given either as JSON numbers or as
+    /// strings, as the dining APIs do
+    pub fn from_values(latitude: &serde_json::Value, longitude: &serde_json::Value) -> Option<Self> {
+        let parse = |value: &serde_json::Value| match value {
+            serde_json::Value::Number(n) => n.as_f64(),
+            serde_json::Value::String(s) => s.trim().parse().ok(),
+            _ => None,
+        };
+
+        Self::new(parse(latitude)?, parse(longitude)?).ok()
+    }
+
+    /// Great-circle distance in meters
+    pub fn distance_meters(&self, other: &Coordinates) -> f64 {
+        let (lat_a, lon_a) = (self.latitude.to_radians(), self.longitude.to_radians());
+        let (lat_b, lon_b) = (other.latitude.to_radians(), other.longitude.to_radians());
+
+        let h = ((lat_b - lat_a) / 2.0).sin().powi(2)
+            + lat_a.cos() * lat_b.cos() * ((lon_b - lon_a) / 2.0).sin().powi(2);
+
+        2.0 * EARTH_RADIUS_METERS * h.sqrt().asin()
+    }
+
+    /// [longitude, latitude], the order GeoJSON wants
+    pub fn to_geojson(&self) -> [f64; 2] {
+        [self.longitude, self.latitude]
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Degrees {
+    Number(f64),
+    Text(String),
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawCoordinates {
+    Typed { latitude: f64, longitude: f64 },
+    // ["34.1", "-117.7"], the old locations cache format
+    Pair(Degrees, Degrees),
+}
+
+impl TryFrom<RawCoordinates> for Coordinates {
+    type Error = String;
+
+    fn try_from(raw: RawCoordinates) -> Result<Self, Self::Error> {
+        let degrees = |d: Degrees| match d {
+            Degrees::Number(n) => Ok(n),
+            Degrees::Text(s) => s
+                .trim()
+                .parse::<f64>()
+                .map_err(|_| format!("Invalid coordinate \"{}\"", s)),
+        };
+
+        match raw {
+            RawCoordinates::Typed { latitude, longitude } => Coordinates::new(latitude, longitude),
+            RawCoordinates::Pair(lat, lon) => Coordinates::new(degrees(lat)?, degrees(lon)?),
+        }
+    }
+}
+
+/// Key of a location in the locations cache
+pub fn location_key(location: &Location) -> String {
+    format!("{:?}-{}", location.get_school(), location.get_building())
+}
+
+/// Where a location is: the locations cache first, then the building
+/// registry
+pub fn coordinates_for(
+    location: &Location,
+    locations: &HashMap<String, Coordinates>,
+) -> Option<Coordinates> {
+    locations.get(&location_key(location)).cloned().or_else(|| {
+        BUILDINGS
+            .find(location.get_school(), location.get_building())
+            .and_then(|b| b.coordinates())
+    })
+}
+
+#[derive(Debug, Serialize)]
+pub struct FeatureCollection {
+    r#type: &'static str,
+    features: Vec<Feature>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Feature {
+    r#type: &'static str,
+    geometry: Geometry,
+    properties: FeatureProperties,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type")]
+pub enum Geometry {
+    Point { coordinates: [f64; 2] },
+}
+
+#[derive(Debug, Serialize)]
+pub struct MapCourse {
+    identifier: String,
+    title: String,
+    room: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MealPeriod {
+    meal: MenuTime,
+    opens: String,
+    closes: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum FeatureProperties {
+    Building {
+        school: School,
+        name: String,
+        building_id: Option<String>,
+        courses: Vec<MapCourse>,
+    },
+    DiningHall {
+        school: School,
+        name: String,
+        // Today's meal periods
+        meals: Vec<MealPeriod>,
+    },
+}
+
+impl Feature {
+    fn point(coordinates: Coordinates, properties: FeatureProperties) -> Self {
+        Self {
+            r#type: "Feature",
+            geometry: Geometry::Point {
+                coordinates: coordinates.to_geojson(),
+            },
+            properties,
+        }
+    }
+}
+
+fn building_features(snapshot: &Snapshot) -> Vec<Feature> {
+    let mut buildings: BTreeMap<String, (Location, Vec<MapCourse>)> = BTreeMap::new();
+
+    for course in snapshot.courses.iter() {
+        for timing in course.get_timings() {
+            let location = timing.get_location();
+
+            if location.is_placeholder() {
+                continue;
+            }
+
+            let (_, courses) = buildings
+                .entry(location_key(location))
+                .or_insert_with(|| (location.clone(), Vec::new()));
+
+            for room in timing.get_rooms() {
+                let seen = courses
+                    .iter()
+                    .any(|c| &c.identifier == course.get_identifier() && c.room == room);
+
+                if !seen {
+                    courses.push(MapCourse {
+                        identifier: course.get_identifier().clone(),
+                        title: course.get_title().clone(),
+                        room,
+                    });
+                }
+            }
+        }
+    }
+
+    // Buildings with no known coordinates can't be placed on the map
+    buildings
+        .into_iter()
+        .filter_map(|(_, (location, courses))| {
+            let coordinates = coordinates_for(&location, &snapshot.locations)?;
+
+            Some(Feature::point(
+                coordinates,
+                FeatureProperties::Building {
+                    school: location.get_school().clone(),
+                    name: location.get_building().clone(),
+                    building_id: location.get_building_id().cloned(),
+                    courses,
+                },
+            ))
+        })
+        .collect()
+}
+
+fn dining_features(snapshot: &Snapshot) -> Vec<Feature> {
+    let today = Local::today().naive_local().format("%Y-%m-%d").to_string();
+
+    let mut features = Vec::new();
+
+    for (school, school_menu) in snapshot.menus.iter() {
+        for cafe in school_menu.get_cafes() {
+            let coordinates = cafe
+                .get_day_menus()
+                .iter()
+                .flat_map(|day| day.get_menus())
+                .find_map(|menu| menu.get_coordinates())
+                .or_else(|| {
+                    BUILDINGS
+                        .find(school, cafe.get_name())
+                        .and_then(|b| b.coordinates())
+                });
+
+            let coordinates = match coordinates {
+                Some(coordinates) => coordinates,
+                None => {
+                    let key = format!("{:?}-{}", school, cafe.get_name());
+
+                    if UNMAPPED_CAFES.lock().unwrap().insert(key.clone()) {
+                        warn!("Leaving {} off the map, it has no coordinates in its menus or buildings.json", key);
+                    }
+
+                    continue;
+                }
+            };
+
+            let meals = cafe
+                .get_day_menus()
+                .iter()
+                .filter(|day| day.get_date() == &today)
+                .flat_map(|day| day.get_menus())
+                .map(|menu| MealPeriod {
+                    meal: menu.get_time_slot().clone(),
+                    opens: menu.get_time_opens().clone(),
+                    closes: menu.get_time_closes().clone(),
+                })
+                .collect();
+
+            features.push(Feature::point(
+                coordinates,
+                FeatureProperties::DiningHall {
+                    school: school.clone(),
+                    name: cafe.get_name().clone(),
+                    meals,
+                },
+            ));
+        }
+    }
+
+    features
+}
+
+/// Buildings with their courses and dining halls with today's meals, as
+/// a GeoJSON FeatureCollection
+pub fn campus_map(snapshot: &Snapshot) -> FeatureCollection {
+    let mut features = building_features(snapshot);
+    features.extend(dining_features(snapshot));
+
+    FeatureCollection {
+        r#type: "FeatureCollection",
+        features,
+    }
+}
@@ -0,0 +1,113 @@
+use crate::course_api::School;
+use crate::{get_unix_timestamp, MEMORY_DATABASE};
+use ::serde::{Deserialize, Serialize};
+use std::fmt::Display;
+use std::time::Instant;
+
+/// Every upstream that the update loop pulls data from
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Source {
+    PomonaApi,
+    CmcScrape,
+    Catalog(School),
+    Cafe(String),
+    Geocoding,
+}
+
+impl Source {
+    /// Key used in the health map, formatted like the location keys
+    /// ("Catalog-HarveyMudd", "Cafe-Frank Dining Hall")
+    pub fn key(&self) -> String {
+        match self {
+            Source::PomonaApi => "PomonaApi".to_string(),
+            Source::CmcScrape => "CmcScrape".to_string(),
+            Source::Catalog(school) => format!("Catalog-{:?}", school),
+            Source::Cafe(name) => format!("Cafe-{}", name),
+            Source::Geocoding => "Geocoding".to_string(),
+        }
+    }
+
+    /// Whether a health map key belongs to a cafe
+    pub fn is_cafe_key(key: &str) -> bool {
+        key.starts_with("Cafe-")
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SourceHealth {
+    pub last_attempt: Option<u64>,
+    pub last_success: Option<u64>,
+    pub last_error: Option<String>,
+    pub last_error_time: Option<u64>,
+    pub consecutive_failures: u64,
+    pub duration_ms: u64,
+    pub item_count: u64,
+}
+
+impl SourceHealth {
+    pub fn success(&mut self, duration_ms: u64, item_count: u64) {
+        let now = get_unix_timestamp();
+
+        self.last_attempt = Some(now);
+        self.last_success = Some(now);
+        self.consecutive_failures = 0;
+        self.duration_ms = duration_ms;
+        self.item_count = item_count;
+    }
+
+    pub fn failure(&mut self, duration_ms: u64, error: String) {
+        let now = get_unix_timestamp();
+
+        self.last_attempt = Some(now);
+        self.last_error = Some(error);
+        self.last_error_time = Some(now);
+        self.consecutive_failures += 1;
+        self.duration_ms = duration_ms;
+    }
+
+    pub fn is_healthy(&self) -> bool {
+        self.consecutive_failures == 0
+    }
+}
+
+pub async fn record_success(source: &Source, started: Instant, item_count: usize) {
+    let duration_ms = started.elapsed().as_millis() as u64;
+
+    let mut lock = MEMORY_DATABASE.lock().await;
+    lock.health_cache
+        .entry(source.key())
+        .or_default()
+        .success(duration_ms, item_count as u64);
+}
+
+pub async fn record_failure(source: &Source, started: Instant, error: String) {
+    let duration_ms = started.elapsed().as_millis() as u64;
+
+    let mut lock = MEMORY_DATABASE.lock().await;
+    lock.health_cache
+        .entry(source.key())
+        .or_default()
+        .failure(duration_ms, error);
+}
+
+/// Records the outcome of a fetch, counting items with `count` on success
+pub async fn record_result<T, E: Display>(
+    source: &Source,
+    started: Instant,
+    result: &Result<T, E>,
+    count: fn(&T) -> usize,
+) {
+    match result {
+        Ok(value) => record_success(source, started, count(value)).await,
+        Err(e) => record_failure(source, started, e.to_string()).await,
+    }
+}
+
+pub async fn get_consecutive_failures(source: &Source) -> u64 {
+    let lock = MEMORY_DATABASE.lock().await;
+
+    lock.health_cache
+        .get(&source.key())
+        .map(|h| h.consecutive_failures)
+        .unwrap_or(0)
+}
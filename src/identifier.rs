@@ -0,0 +1,246 @@
+// ----------------------------------------------------------------------------
+// Course identifiers.
+//
+// Every source writes course codes differently: the Pomona API as
+// "AFRI010A AF-01" (or "PE  001  PO-01"), the CMC search as separate
+// columns, the catalogs as "AFRI 010A AF". They are all parsed into a
+// CourseIdentifier, which formats back to the canonical "AFRI-010A-AF-01"
+// and compares field by field, so "MATH-030-HM" never matches "MATH-03".
+// ----------------------------------------------------------------------------
+
+use ::serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IdentifierError(pub String);
+
+impl fmt::Display for IdentifierError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid course identifier \"{}\"", self.0)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct CourseIdentifier {
+    // "MATH"
+    pub subject: String,
+    // 30, written as "030"
+    pub number: u32,
+    // Letters (and digits) after the number, "G" in "030G", often empty
+    pub suffix: String,
+    // Two letter college or department code, "HM"
+    pub college: String,
+    // "01", None for catalog entries, which cover every section
+    pub section: Option<String>,
+}
+
+impl CourseIdentifier {
+    /// Builds an identifier from separate fields, as the CMC search lists
+    /// them: subject "MATH", number "030G", college "HM", section "01"
+    pub fn from_parts(
+        subject: &str,
+        number: &str,
+        college: &str,
+        section: &str,
+    ) -> Result<Self, IdentifierError> {
+        let invalid = || IdentifierError(format!("{}-{}-{}-{}", subject, number, college, section));
+
+        let subject = subject.trim().to_uppercase();
+        let number = number.trim().to_uppercase();
+        let college = college.trim().to_uppercase();
+        let section = section.trim();
+
+        let digits_end = number
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(number.len());
+        let (digits, suffix) = number.split_at(digits_end);
+
+        let valid = !subject.is_empty()
+            && subject.chars().all(|c| c.is_ascii_alphabetic())
+            && !digits.is_empty()
+            && suffix.chars().all(|c| c.is_ascii_alphanumeric())
+            && college.len() == 2
+            && college.chars().all(|c| c.is_ascii_alphabetic())
+            && section.chars().all(|c| c.is_ascii_alphanumeric());
+
+        if !valid {
+            return Err(invalid());
+        }
+
+        // Sections are two digits, "1" is "01"
+        let section = match section.parse::<u32>() {
+            _ if section.is_empty() => None,
+            Ok(n) => Some(format!("{:02}", n)),
+            Err(_) => Some(section.to_uppercase()),
+        };
+
+        Ok(Self {
+            subject,
+            number: digits.parse().map_err(|_| invalid())?,
+            suffix: suffix.to_string(),
+            college,
+            section,
+        })
+    }
+
+    /// Parses a course code as the Pomona API and the catalogs write it,
+    /// "AFRI010A AF-01", "PE  001  PO-01" or "AFRI 010A AF", ignoring
+    /// whitespace. The CMC search sometimes leaves out the dash, as in
+    /// "MATH 030HM 01", so a trailing number after a space is the section.
+    pub fn from_code(code: &str) -> Result<Self, IdentifierError> {
+        let invalid = || IdentifierError(code.to_string());
+
+        // "MATH 030HM 01" is "MATH 030HM-01"
+        let mut compact = match code.trim().rsplit_once(char::is_whitespace) {
+            Some((course, section))
+                if !code.contains('-') && section.chars().all(|c| c.is_ascii_digit()) =>
+            {
+                format!("{}-{}", course, section)
+            }
+            _ => code.to_string(),
+        };
+        compact.retain(|c| !c.is_whitespace());
+
+        let (course, section) = compact.split_once('-').unwrap_or((compact.as_str(), ""));
+
+        // The college is always the last two characters
+        if course.len() < 3 || !course.is_char_boundary(course.len() - 2) {
+            return Err(invalid());
+        }
+        let (course, college) = course.split_at(course.len() - 2);
+
+        let number_start = course.find(|c: char| c.is_ascii_digit()).ok_or_else(invalid)?;
+        let (subject, number) = course.split_at(number_start);
+
+        Self::from_parts(subject, number, college, section).map_err(|_| invalid())
+    }
+
+    /// "030G"
+    pub fn number_with_suffix(&self) -> String {
+        format!("{:03}{}", self.number, self.suffix)
+    }
+
+    /// The same course without a section, as the catalog lists it
+    pub fn course(&self) -> Self {
+        Self {
+            section: None,
+            ..self.clone()
+        }
+    }
+}
+
+/// What merges compare identifiers by: the parsed identifier, or the raw
+/// string for made up ones that don't parse
+pub type IdentifierKey<'a> = std::result::Result<CourseIdentifier, &'a str>;
+
+pub fn identifier_key(identifier: &str) -> IdentifierKey<'_> {
+    identifier.parse().map_err(|_| identifier)
+}
+
+impl FromStr for CourseIdentifier {
+    type Err = IdentifierError;
+
+    /// Parses the canonical "AFRI-010A-AF-01" (or "AFRI-010A-AF") form,
+    /// falling back to the upstream course code forms
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<&str> = s.trim().split('-').collect();
+
+        let canonical = match parts.as_slice() {
+            [subject, number, college] => Self::from_parts(subject, number, college, ""),
+            [subject, number, college, section] => Self::from_parts(subject, number, college, section),
+            _ => Err(IdentifierError(s.to_string())),
+        };
+
+        canonical
+            .or_else(|_| Self::from_code(s))
+            .map_err(|_| IdentifierError(s.to_string()))
+    }
+}
+
+impl fmt::Display for CourseIdentifier {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}-{}-{}", self.subject, self.number_with_suffix(), self.college)?;
+
+        if let Some(section) = &self.section {
+            write!(f, "-{}", section)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(s: &str) -> CourseIdentifier {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn parses_pomona_codes() {
+        let identifier = CourseIdentifier::from_code("AFRI010A AF-01").unwrap();
+
+        assert_eq!(identifier.subject, "AFRI");
+        assert_eq!(identifier.number, 10);
+        assert_eq!(identifier.suffix, "A");
+        assert_eq!(identifier.college, "AF");
+        assert_eq!(identifier.section, Some("01".to_string()));
+        assert_eq!(identifier.to_string(), "AFRI-010A-AF-01");
+
+        assert_eq!(CourseIdentifier::from_code("PE  001  PO-01").unwrap().to_string(), "PE-001-PO-01");
+    }
+
+    #[test]
+    fn parses_catalog_codes_without_sections() {
+        let identifier = CourseIdentifier::from_code("AFRI 010A AF").unwrap();
+
+        assert_eq!(identifier.section, None);
+        assert_eq!(identifier.to_string(), "AFRI-010A-AF");
+    }
+
+    #[test]
+    fn parses_cmc_codes_missing_the_dash() {
+        let identifier = CourseIdentifier::from_code("MATH 030HM 01").unwrap();
+
+        assert_eq!(identifier.college, "HM");
+        assert_eq!(identifier.to_string(), "MATH-030-HM-01");
+        assert_eq!(CourseIdentifier::from_code("MATH 030 HM - 02").unwrap().to_string(), "MATH-030-HM-02");
+    }
+
+    #[test]
+    fn parses_cmc_columns() {
+        let identifier = CourseIdentifier::from_parts("math", "30g", "hm", "1").unwrap();
+
+        assert_eq!(identifier.to_string(), "MATH-030G-HM-01");
+    }
+
+    #[test]
+    fn rejects_what_is_not_an_identifier() {
+        assert!(CourseIdentifier::from_parts("MATH", "G30", "HM", "01").is_err());
+        assert!(CourseIdentifier::from_parts("MATH", "030", "HMC", "01").is_err());
+        assert!(CourseIdentifier::from_code("HM").is_err());
+        assert!("Custom course".parse::<CourseIdentifier>().is_err());
+    }
+
+    #[test]
+    fn canonical_form_round_trips() {
+        for s in ["NEUR-189S-PO-01", "MATH-030-HM", "PE-001-PO-02"] {
+            assert_eq!(parse(s).to_string(), s);
+        }
+    }
+
+    #[test]
+    fn compares_whole_fields() {
+        assert_ne!(parse("MATH-030-HM").course(), parse("MATH-003-HM"));
+        assert_ne!(parse("MATH-030-HM"), parse("MATH-030G-HM"));
+        assert_eq!(parse("MATH-030-HM-02").course(), parse("MATH 030 HM"));
+    }
+
+    #[test]
+    fn keys_made_up_identifiers_by_their_text() {
+        assert_eq!(identifier_key("My course"), Err("My course"));
+        assert_eq!(identifier_key("MATH-030-HM-01"), Ok(parse("MATH-030-HM-01")));
+    }
+}
@@ -0,0 +1,350 @@
+// ----------------------------------------------------------------------------
+// One-shot maintenance jobs, run with a subcommand instead of `serve`.
+//
+// Each job reuses the same fetch/merge functions as the update loop and
+// writes its results through database.rs, so a cron job and a running
+// server always agree on the cache format.
+// ----------------------------------------------------------------------------
+
+use crate::buildings::BuildingRegistry;
+use crate::config::{Command, CONFIG};
+use crate::course_api::*;
+use crate::database::*;
+use crate::geo::Coordinates;
+use crate::health::{Source, SourceHealth};
+use crate::{get_unix_timestamp, MEMORY_DATABASE};
+use crate::locations::get_locations;
+use crate::menu::*;
+use crate::requirements::RequirementRegistry;
+use crate::scrape_descriptions::*;
+use ::serde::Serialize;
+use bimap::BiHashMap;
+use log::{info, warn};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fs::File;
+use std::io::Write;
+use std::path::PathBuf;
+
+#[derive(Serialize)]
+struct Export<'a> {
+    timestamp: u64,
+    courses: &'a Vec<Course>,
+    areas: &'a Vec<CourseArea>,
+    descriptions: &'a Vec<CourseDescription>,
+    locations: &'a HashMap<String, Coordinates>,
+    menus: &'a HashMap<School, SchoolMenu>,
+    codes: &'a BiHashMap<String, SharedCourseList>,
+}
+
+pub async fn run_job(command: Command) -> Result<(), String> {
+    match command {
+        Command::Serve => Err("serve is not a one-shot job".to_string()),
+        Command::RefreshCourses => refresh_courses().await,
+        Command::RefreshCatalog { full } => refresh_catalog(full).await,
+        Command::RefreshMenus => refresh_menus().await,
+        Command::GeocodeLocations => geocode_locations().await,
+        Command::Export { output } => export(output),
+        Command::ValidateCache => validate_cache(),
+    }
+}
+
+fn io_error(file: &str) -> impl Fn(std::io::Error) -> String + '_ {
+    move |e| format!("Could not access {}: {}", file, e)
+}
+
+async fn refresh_courses() -> Result<(), String> {
+    info!("Retrieving course info...");
+    let (term, courses, areas) = full_pomona_update().await?;
+
+    info!("Retrieving perm numbers...");
+    let perm_numbers = get_current_perm_numbers().await.unwrap_or_else(|e| {
+        warn!("Error getting perm numbers: {}", e);
+        HashMap::new()
+    });
+
+    let courses = merge_perms_into_courses(courses, perm_numbers);
+
+    // Keep descriptions and notes from the last run
+    let previous_courses = load_course_database().map_err(io_error("course cache"))?;
+    let courses = merge_courses(previous_courses, courses);
+
+    save_course_database(courses.clone()).map_err(io_error("course cache"))?;
+    save_areas_database(areas).map_err(io_error("areas cache"))?;
+
+    info!("Saved {} courses for term {}", courses.len(), term);
+
+    Ok(())
+}
+
+async fn refresh_catalog(full: bool) -> Result<(), String> {
+    info!("Retreiving description info... (may take several minutes)");
+
+    let mut pages = if full {
+        CatalogPages::default()
+    } else {
+        load_catalog_pages().map_err(io_error("catalog page cache"))?
+    };
+
+    let descriptions = scrape_all_descriptions(&mut pages).await?;
+    save_catalog_pages(&pages).map_err(io_error("catalog page cache"))?;
+
+    let courses = load_course_database().map_err(io_error("course cache"))?;
+    let (courses, descriptions) = merge_description_and_courses(courses, descriptions, &load_match_rules());
+
+    let (matched, total) = description_match_stats(&courses);
+
+    save_descriptions_database(descriptions.clone()).map_err(io_error("descriptions cache"))?;
+    save_course_database(courses).map_err(io_error("course cache"))?;
+
+    info!(
+        "Saved {} descriptions, matched to {} of {} courses",
+        descriptions.len(),
+        matched,
+        total
+    );
+
+    Ok(())
+}
+
+async fn refresh_menus() -> Result<(), String> {
+    // Carried over so consecutive failures keep counting across runs
+    let previous = load_cafe_health().map_err(io_error("cafe health"))?;
+    MEMORY_DATABASE.lock().await.health_cache.extend(previous);
+
+    let menus = get_seven_day_menus().await;
+
+    let cafes: HashMap<String, SourceHealth> = MEMORY_DATABASE
+        .lock()
+        .await
+        .health_cache
+        .iter()
+        .filter(|(key, _)| Source::is_cafe_key(key))
+        .map(|(key, health)| (key.clone(), health.clone()))
+        .collect();
+    save_cafe_health(&cafes).map_err(io_error("cafe health"))?;
+
+    let menus = menus.map_err(|e| e.to_string())?;
+
+    save_menu_datebase(menus.clone()).map_err(io_error("menu cache"))?;
+
+    info!("Saved menus for {} schools", menus.len());
+
+    Ok(())
+}
+
+async fn geocode_locations() -> Result<(), String> {
+    let courses = load_course_database().map_err(io_error("course cache"))?;
+    // Entries with blank or invalid coordinates are skipped, so they're
+    // looked up again
+    let current_locations = load_locations_database().map_err(io_error("locations cache"))?;
+
+    // Only look up courses that have a location we don't know yet
+    let missing: Vec<Course> = courses
+        .into_iter()
+        .filter(|course| {
+            course.get_timings().iter().any(|timing| {
+                let loc = timing.get_full_location();
+                !current_locations.contains_key(&format!("{:?}-{}", loc.0, loc.1))
+            })
+        })
+        .collect();
+
+    info!("Geocoding locations for {} courses...", missing.len());
+
+    // Carried over so consecutive failures keep counting across runs
+    let geocoding = Source::Geocoding.key();
+
+    if let Some(previous) = load_geocode_health().map_err(io_error("geocode health"))? {
+        MEMORY_DATABASE.lock().await.health_cache.insert(geocoding.clone(), previous);
+    }
+
+    let report = get_locations(missing).await;
+
+    if let Some(health) = MEMORY_DATABASE.lock().await.health_cache.get(&geocoding) {
+        save_geocode_health(health).map_err(io_error("geocode health"))?;
+    }
+    let locations = merge_locations(current_locations, report.locations);
+
+    save_locations_database(locations.clone()).map_err(io_error("locations cache"))?;
+
+    info!("Saved {} locations", locations.len());
+
+    for miss in &report.misses {
+        println!("Not found: {}", miss);
+    }
+
+    for unplaced in &report.unplaced {
+        println!("No coordinates in the registry: {}", unplaced);
+    }
+
+    Ok(())
+}
+
+fn export(output: Option<PathBuf>) -> Result<(), String> {
+    let courses = load_course_database().map_err(io_error("course cache"))?;
+    let areas = load_areas_database().map_err(io_error("areas cache"))?;
+    let descriptions = load_descriptions_database().map_err(io_error("descriptions cache"))?;
+    let locations = load_locations_database().map_err(io_error("locations cache"))?;
+    let menus = load_menu_database().map_err(io_error("menu cache"))?;
+    let codes = load_code_database().map_err(io_error("code cache"))?;
+
+    let export = Export {
+        timestamp: get_unix_timestamp(),
+        courses: &courses,
+        areas: &areas,
+        descriptions: &descriptions,
+        locations: &locations,
+        menus: &menus,
+        codes: &codes,
+    };
+
+    let serialized_output = serde_json::to_string(&export).map_err(|e| e.to_string())?;
+
+    match output {
+        Some(path) => {
+            let mut writer = File::create(&path)
+                .map_err(|e| format!("Could not create {}: {}", path.display(), e))?;
+
+            writer
+                .write_all(serialized_output.as_bytes())
+                .map_err(|e| format!("Could not write {}: {}", path.display(), e))?;
+        }
+        None => println!("{}", serialized_output),
+    }
+
+    Ok(())
+}
+
+fn validate_cache() -> Result<(), String> {
+    let mut problems: Vec<String> = Vec::new();
+
+    let buildings = match BuildingRegistry::load(&CONFIG.paths.buildings) {
+        Ok(buildings) => {
+            let mut seen = HashSet::new();
+
+            for building in buildings.buildings() {
+                if !seen.insert(&building.id) {
+                    problems.push(format!("Duplicate building ID {}", building.id));
+                }
+            }
+
+            println!("buildings: {}", buildings.buildings().len());
+            Some(buildings)
+        }
+        Err(e) => {
+            problems.push(format!("Building registry: {}", e));
+            None
+        }
+    };
+
+    match RequirementRegistry::load(&CONFIG.paths.requirements) {
+        Ok(requirements) => {
+            let mut seen = HashSet::new();
+
+            for requirement in requirements.requirements() {
+                if !seen.insert(&requirement.id) {
+                    problems.push(format!("Duplicate requirement ID {}", requirement.id));
+                }
+
+                if requirement.areas.is_empty() {
+                    problems.push(format!("Requirement {} has no area codes", requirement.id));
+                }
+            }
+
+            println!("requirements: {}", requirements.requirements().len());
+        }
+        Err(e) => problems.push(format!("Requirements: {}", e)),
+    }
+
+    match load_course_database() {
+        Ok(courses) => {
+            let mut seen = HashSet::new();
+            let mut unmatched = BTreeSet::new();
+
+            for course in &courses {
+                if !seen.insert(course.get_identifier()) {
+                    problems.push(format!("Duplicate course {}", course.get_identifier()));
+                }
+
+                if course.get_title().trim().is_empty() {
+                    problems.push(format!("Course {} has no title", course.get_identifier()));
+                }
+
+                for timing in course.get_timings() {
+                    let location = timing.get_location();
+
+                    if let Some(buildings) = &buildings {
+                        if !location.get_building().trim().is_empty()
+                            && buildings.find(location.get_school(), location.get_building()).is_none()
+                        {
+                            unmatched.insert(format!("{:?}-{}", location.get_school(), location.get_building()));
+                        }
+                    }
+                }
+            }
+
+            for building in unmatched {
+                problems.push(format!("Building {} is not in the building registry", building));
+            }
+
+            if courses.is_empty() {
+                problems.push("Course cache is empty".to_string());
+            }
+
+            println!("courses: {}", courses.len());
+        }
+        Err(e) => problems.push(format!("Course cache: {}", e)),
+    }
+
+    match load_areas_database() {
+        Ok(areas) => println!("areas: {}", areas.len()),
+        Err(e) => problems.push(format!("Areas cache: {}", e)),
+    }
+
+    match load_descriptions_database() {
+        Ok(descriptions) => println!("descriptions: {}", descriptions.len()),
+        Err(e) => problems.push(format!("Descriptions cache: {}", e)),
+    }
+
+    match load_catalog_pages() {
+        Ok(pages) => println!("catalog pages: {}", pages.schools.values().map(|p| p.len()).sum::<usize>()),
+        Err(e) => problems.push(format!("Catalog page cache: {}", e)),
+    }
+
+    match load_description_overrides() {
+        Ok(overrides) => println!("description overrides: {}", overrides.len()),
+        Err(e) => problems.push(format!("Description overrides: {}", e)),
+    }
+
+    match read_locations_database() {
+        Ok((locations, invalid)) => {
+            for entry in invalid {
+                problems.push(format!("Location {}", entry));
+            }
+
+            println!("locations: {}", locations.len());
+        }
+        Err(e) => problems.push(format!("Locations cache: {}", e)),
+    }
+
+    match load_menu_database() {
+        Ok(menus) => println!("menus: {} schools", menus.len()),
+        Err(e) => problems.push(format!("Menu cache: {}", e)),
+    }
+
+    match load_code_database() {
+        Ok(codes) => println!("share codes: {}", codes.len()),
+        Err(e) => problems.push(format!("Code cache: {}", e)),
+    }
+
+    if problems.is_empty() {
+        println!("All caches OK");
+        return Ok(());
+    }
+
+    for problem in &problems {
+        println!("{}", problem);
+    }
+
+    Err(format!("{} problem(s) found", problems.len()))
+}
@@ -1,36 +1,64 @@
-use csv::*;
-use std::error::Error;
-use log::{info, error};
-use reqwest::*;
+// ----------------------------------------------------------------------------
+// Geocoding for course locations.
+//
+// Locations are looked up with each GeocodingProvider in turn. The local
+// gazetteer (the building registry in buildings.json) always runs first and
+// needs no network. Nominatim is an optional fallback, enabled with
+// geocoding.nominatim_fallback, for buildings the registry doesn't know.
+//
+// Anything no provider finds is reported as a miss rather than cached with
+// blank coordinates. Buildings the registry lists without coordinates (the
+// dining halls, Inside Out, The Farm) are expected to be missing, and only
+// provider errors count against geocoding health.
+// ----------------------------------------------------------------------------
+
+use async_trait::async_trait;
+use log::{info, warn};
 use std::collections::HashMap;
+use crate::buildings::{Building, BUILDINGS};
+use crate::config::CONFIG;
 use crate::course_api::*;
+use crate::geo::*;
+use crate::health::*;
 use serde::Deserialize;
-use std::thread;
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::time::{Duration, Instant};
 
-pub async fn get_locations(courses: Vec<Course>) -> HashMap<String, (String, String)> {
-    let mut locations_pairs: HashMap<String, (String, String)> = HashMap::new();
+// Nominatim's usage policy allows one request per second
+const NOMINATIM_DELAY: Duration = Duration::from_millis(1010);
 
-    for course in courses {
-        for time in course.get_timings() {
-            let loc = time.get_full_location();
-            let loc_key = format!("{:?}-{}", loc.0, loc.1);
-
-            if !locations_pairs.contains_key(&loc_key) {
-                let (lat, lon) = get_lat_lon(loc).await.unwrap_or(("".to_string(), "".to_string()));
-                locations_pairs.insert(loc_key.clone(), (lat.clone(), lon.clone()));
-                
-                info!("Got coords for {} at {}/{}", loc_key, lat, lon);
-                thread::sleep(Duration::from_millis(1010));
-            } else {
-                info!("{} already exists", loc_key);
-            }
-        }
+#[async_trait]
+pub trait GeocodingProvider: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Latitude and longitude of a location, Ok(None) if this provider
+    /// doesn't know it
+    async fn locate(&self, location: &Location) -> Result<Option<Coordinates>, String>;
+}
+
+/// Looks buildings up in the local building registry
+pub struct Gazetteer;
+
+#[async_trait]
+impl GeocodingProvider for Gazetteer {
+    fn name(&self) -> &'static str {
+        "gazetteer"
     }
 
-    locations_pairs
+    async fn locate(&self, location: &Location) -> Result<Option<Coordinates>, String> {
+        Ok(registry_building(location).and_then(|b| b.coordinates()))
+    }
 }
 
+fn registry_building(location: &Location) -> Option<&'static Building> {
+    match location.get_building_id() {
+        Some(id) => BUILDINGS.get(id),
+        None => BUILDINGS.find(location.get_school(), location.get_building()),
+    }
+}
+
+/// Searches OpenStreetMap's Nominatim for the building name
+pub struct Nominatim;
+
 #[derive(Deserialize, Debug)]
 struct ApiResponse {
     place_id: i64,
@@ -45,36 +73,161 @@ struct ApiResponse {
     importance: f64,
 }
 
-pub async fn get_lat_lon(loc: (School, String, String)) -> std::result::Result<(String, String), Box<dyn std::error::Error>> {
-    let client = reqwest::Client::builder()
-        .user_agent("api.5scheduler.io")
-        .build()?;
+impl Nominatim {
+    async fn search(&self, query: &str) -> Result<Option<Coordinates>, String> {
+        let email = if CONFIG.geocoding.nominatim_email.is_empty() {
+            "".to_string()
+        } else {
+            format!("&email={}", CONFIG.geocoding.nominatim_email)
+        };
+
+        let url = format!(
+            "https://nominatim.openstreetmap.org/search?q={},%20Claremont%2091711&format=json{}",
+            query, email
+        );
+
+        let client = reqwest::Client::builder()
+            .user_agent("api.5scheduler.io")
+            .build()
+            .map_err(|e| e.to_string())?;
+
+        let res = client
+            .get(&url)
+            .send()
+            .await
+            .map_err(|e| e.to_string())?
+            .json::<Vec<ApiResponse>>()
+            .await
+            .map_err(|e| e.to_string());
 
-    // First try, without school name
-    let url = format!("https://nominatim.openstreetmap.org/search?q={},%20Claremont%2091711&format=json&email=edv121@outlook.com", loc.1);
-    let res = reqwest::get(&url).await?.json::<Vec<ApiResponse>>().await;
+        tokio::time::sleep(NOMINATIM_DELAY).await;
 
-    let mut redo = false;
+        let first = match res?.into_iter().next() {
+            Some(first) => first,
+            None => return Ok(None),
+        };
 
-    if res.is_ok() {
-        let res = res.unwrap();
-        if res.len() > 0 {
-            return Ok((res[0].lat.clone(), res[0].lon.clone()));
+        match (first.lat.trim().parse(), first.lon.trim().parse()) {
+            (Ok(lat), Ok(lon)) => Coordinates::new(lat, lon).map(Some),
+            _ => Err(format!("Invalid coordinates \"{}\", \"{}\"", first.lat, first.lon)),
         }
     }
+}
 
-    // Did not return, try again with school name    
-    let url = format!("https://nominatim.openstreetmap.org/search?q={:?}%20{},%20Claremont%2091711&format=json&email=edv121@outlook.com", loc.0, loc.1);
-    let res = reqwest::get(&url).await?.json::<Vec<ApiResponse>>().await;
+#[async_trait]
+impl GeocodingProvider for Nominatim {
+    fn name(&self) -> &'static str {
+        "nominatim"
+    }
 
-    if res.is_ok() {
-        let res = res.unwrap();
-        if res.len() > 0 {
-            return Ok((res[0].lat.to_string(), res[0].lon.to_string()));
-        } else {
-            return Ok(("".to_string(), "".to_string()));
+    async fn locate(&self, location: &Location) -> Result<Option<Coordinates>, String> {
+        // First try without the school name, then with it
+        if let Some(coords) = self.search(location.get_building()).await? {
+            return Ok(Some(coords));
         }
+
+        self.search(&format!("{:?}%20{}", location.get_school(), location.get_building()))
+            .await
+    }
+}
+
+/// Providers to try, in order
+pub fn geocoding_providers() -> Vec<Box<dyn GeocodingProvider>> {
+    let mut providers: Vec<Box<dyn GeocodingProvider>> = vec![Box::new(Gazetteer)];
+
+    if CONFIG.geocoding.nominatim_fallback {
+        providers.push(Box::new(Nominatim));
+    }
+
+    providers
+}
+
+pub struct GeocodeReport {
+    // "School-Building" -> coordinates, as in locations.json
+    pub locations: HashMap<String, Coordinates>,
+    // Keys of locations no provider could find
+    pub misses: Vec<String>,
+    // Keys of registry buildings listed without coordinates
+    pub unplaced: Vec<String>,
+}
+
+pub async fn get_locations(courses: Vec<Course>) -> GeocodeReport {
+    let started = Instant::now();
+    let providers = geocoding_providers();
+
+    let mut locations: HashMap<String, Coordinates> = HashMap::new();
+    let mut misses: Vec<String> = Vec::new();
+    let mut unplaced: Vec<String> = Vec::new();
+    let mut errors: Vec<String> = Vec::new();
+
+    for course in courses {
+        for time in course.get_timings() {
+            let location = time.get_location();
+            let loc_key = location_key(location);
+
+            // Placeholders ("Arranged", "Online") have nowhere to be
+            if location.is_placeholder()
+                || locations.contains_key(&loc_key)
+                || misses.contains(&loc_key)
+                || unplaced.contains(&loc_key)
+            {
+                continue;
+            }
+
+            let mut found = None;
+
+            for provider in &providers {
+                match provider.locate(location).await {
+                    Ok(Some(coords)) => {
+                        info!(
+                            "Got coords for {} at {}/{} from {}",
+                            loc_key,
+                            coords.latitude,
+                            coords.longitude,
+                            provider.name()
+                        );
+                        found = Some(coords);
+                        break;
+                    }
+                    Ok(None) => {}
+                    Err(e) => {
+                        warn!("Error geocoding {} with {}: {}", loc_key, provider.name(), e);
+                        errors.push(format!("{} ({}): {}", loc_key, provider.name(), e));
+                    }
+                }
+            }
+
+            match found {
+                Some(coords) => {
+                    locations.insert(loc_key, coords);
+                }
+                None if registry_building(location).is_some() => unplaced.push(loc_key),
+                None => misses.push(loc_key),
+            }
+        }
+    }
+
+    misses.sort();
+    unplaced.sort();
+
+    if !misses.is_empty() {
+        warn!("Could not geocode {} location(s): {:?}", misses.len(), misses);
+    }
+
+    if !unplaced.is_empty() {
+        info!("{} location(s) have no coordinates in the registry: {:?}", unplaced.len(), unplaced);
+    }
+
+    if errors.is_empty() {
+        record_success(&Source::Geocoding, started, locations.len()).await;
     } else {
-        (Err(Box::new(res.unwrap_err())))
+        record_failure(
+            &Source::Geocoding,
+            started,
+            format!("{} lookup error(s): {}", errors.len(), errors.join(", ")),
+        )
+        .await;
     }
-}
\ No newline at end of file
+
+    GeocodeReport { locations, misses, unplaced }
+}
@@ -1,5 +1,6 @@
 use actix_web::*;
 use actix_cors::*;
+use actix_web::dev::Service;
 use openssl::ssl::{SslAcceptor, SslFiletype, SslMethod};
 use regex::internal::Inst;
 
@@ -13,6 +14,7 @@ use std::{sync::Arc, thread};
 use bimap::*;
 use rand::{thread_rng, Rng};
 
+mod config;
 mod course_api;
 mod database;
 mod routes;
@@ -20,7 +22,26 @@ mod scrape_descriptions;
 mod compute_timings;
 mod locations;
 mod menu;
-
+mod health;
+mod metrics;
+mod jobs;
+mod snapshot;
+mod merge_index;
+mod rooms;
+mod buildings;
+mod walking;
+mod geo;
+mod meet_time;
+mod identifier;
+mod search;
+mod similar;
+mod offered;
+mod acalog;
+mod fees;
+mod requirements;
+mod credits;
+
+use config::*;
 use course_api::*;
 use database::*;
 use routes::*;
@@ -28,40 +49,44 @@ use scrape_descriptions::*;
 use compute_timings::*;
 use locations::*;
 use menu::*;
-
+use health::*;
+use metrics::*;
+use jobs::*;
+use snapshot::*;
+use rooms::*;
+use buildings::*;
+use walking::*;
+use geo::*;
+use merge_index::*;
+use offered::*;
+use requirements::*;
+use credits::*;
+
+// Courses, descriptions, locations and menus live in the
+// read-only snapshot (see snapshot.rs)
 pub struct MemDatabase {
-    pub course_cache: Vec<Course>,
-    pub areas_cache: Vec<CourseArea>,
-    pub last_change: u64,
     pub code_cache: BiHashMap<String, SharedCourseList>,
-    pub locations_cache: HashMap<String, (String, String)>,
-    pub descriptions_cache: Vec<CourseDescription>,
-    pub menu_cache: HashMap<School, SchoolMenu>,
-    pub term: String,
-    pub ten_minute_log: Vec<Instant>,
+    pub health_cache: HashMap<String, SourceHealth>,
+    pub metrics: Metrics,
 }
 
 impl MemDatabase {
     fn new() -> Self {
         Self {
-            course_cache: Vec::new(),
-            areas_cache: Vec::new(),
-            last_change: get_unix_timestamp(),
             code_cache: BiHashMap::new(),
-            locations_cache: HashMap::new(),
-            descriptions_cache: Vec::new(),
-            menu_cache: HashMap::new(),
-            term: "".to_string(),
-            ten_minute_log: Vec::new(),
+            health_cache: HashMap::new(),
+            metrics: Metrics::new(),
         }
     }
+}
 
-    fn add_ten_log(&mut self) {
-        let now = Instant::now();
-        self.ten_minute_log.push(now);
-        while self.ten_minute_log.len() > 0 && self.ten_minute_log[0].elapsed() > Duration::from_secs(600) {
-            self.ten_minute_log.remove(0);
-        }
+/// Records a client connection in TEN_MINUTE_LOG, dropping entries older
+/// than ten minutes
+pub fn add_ten_log() {
+    let mut log = TEN_MINUTE_LOG.lock().unwrap();
+    log.push(Instant::now());
+    while !log.is_empty() && log[0].elapsed() > Duration::from_secs(600) {
+        log.remove(0);
     }
 }
 
@@ -70,46 +95,41 @@ impl MemDatabase {
 lazy_static! {
     pub static ref MEMORY_DATABASE: Arc<Mutex<MemDatabase>> =
         Arc::new(Mutex::new(MemDatabase::new()));
-}
 
-// Debug vs release address
-#[cfg(debug_assertions)]
-const ADDRESS: &str = "127.0.0.1:8080";
-#[cfg(not(debug_assertions))]
-const ADDRESS: &str = "0.0.0.0:8080";
-
-
-// Seconds per API update
-const API_UPDATE_INTERVAL: u64 = 1200;
-const DESCRIPTION_INTERVAL_MULTIPLIER: u64 = 100;
-const MENU_INTERVAL_MULTIPLIER: u64 = 100;
+    // Connections in the last ten minutes, for /status. Kept out of
+    // MEMORY_DATABASE so stale checks never wait on it.
+    pub static ref TEN_MINUTE_LOG: std::sync::Mutex<Vec<Instant>> =
+        std::sync::Mutex::new(Vec::new());
+}
 
 pub fn get_unix_timestamp() -> u64 {
     SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
 }
 
 async fn update_loop() -> std::io::Result<()> {
-    let mut number_of_repeated_errors: u64 = 0;
     let mut time_until_description_update = 1;
     let mut time_until_menu_update = 0;
 
     loop {
         info!("Starting schedule API update...");
+        let loop_started = Instant::now();
 
         info!("Retrieving course info...");
+        let started = Instant::now();
         let course_update = full_pomona_update().await;
+        record_update_stage("courses", started, course_update.is_ok()).await;
         
-        info!("Retrieving terms...");
-        let terms = get_terms().await.unwrap();
         info!("Retrieving perm numbers...");
-        let perm_numbers = get_perm_numbers(&terms[0].Key).await.unwrap();
+        let perm_numbers = get_current_perm_numbers().await.unwrap_or_else(|e| {
+            error!("Error getting perm numbers: {}", e);
+            HashMap::new()
+        });
         
         let number_of_courses: usize;
         let mut term_update = "".to_string();
         let mut area_update = Vec::new();
 
         if course_update.is_err() {
-            number_of_repeated_errors += 1;
             number_of_courses = 0;
             error!("Error getting courses: {}", course_update.unwrap_err());
         } else {
@@ -120,40 +140,56 @@ async fn update_loop() -> std::io::Result<()> {
             area_update = course_tuple.2;
 
             if final_course_update.is_empty() {
-                number_of_repeated_errors += 1;
                 number_of_courses = 0;
                 error!("No courses found!");
             } else {
                 final_course_update = merge_perms_into_courses(final_course_update, perm_numbers);
 
-                number_of_repeated_errors = 0;
                 info!("Successfully updated courses!");
                 number_of_courses = final_course_update.len();
+
+                // Read once, for both matching descriptions and publishing
+                let match_rules = load_match_rules();
                 
                 if time_until_description_update == 0 {
                     info!("Retreiving description info... (may take several minutes)");
                 
-                    time_until_description_update = DESCRIPTION_INTERVAL_MULTIPLIER;
+                    time_until_description_update = CONFIG.update.description_interval_multiplier;
                     
-                    let course_desc_update = scrape_all_descriptions().await;
+                    let started = Instant::now();
+                    let mut catalog_pages = load_catalog_pages().unwrap_or_else(|e| {
+                        warn!("Could not load the catalog page cache, parsing every page: {}", e);
+                        CatalogPages::default()
+                    });
+                    let course_desc_update = scrape_all_descriptions(&mut catalog_pages).await;
+                    record_update_stage("descriptions", started, course_desc_update.is_ok()).await;
+
+                    if course_desc_update.is_ok() {
+                        if let Err(e) = save_catalog_pages(&catalog_pages) {
+                            error!("Could not save the catalog page cache: {}", e);
+                        }
+                    }
                     
                     if let Ok(course_desc_update) = course_desc_update {
-                        number_of_repeated_errors = 0;
+                        let merged = merge_description_and_courses(final_course_update.clone(), course_desc_update.clone(), &match_rules);
 
-                        let merged = merge_description_and_courses(final_course_update.clone(), course_desc_update.clone());
+                        // Courses now carry their descriptions, fees and offered terms
+                        final_course_update = merged.0;
 
+                        let (matched, total) = description_match_stats(&final_course_update);
                         let descriptions = merged.1;
 
                         save_descriptions_database(descriptions.clone()).unwrap();
                         save_course_database(final_course_update.clone()).unwrap();
 
+                        publish_descriptions(descriptions).await;
+
                         let mut lock = MEMORY_DATABASE.lock().await;
-                        lock.descriptions_cache = descriptions;
+                        lock.metrics.set_description_matches(matched, total);
                         drop(lock);
 
                         info!("Successfully updated descriptions!");
                     } else {
-                        number_of_repeated_errors += 1;
                         error!("Error getting descriptions: {:?}", course_desc_update.unwrap_err());
                     }
                 
@@ -163,44 +199,43 @@ async fn update_loop() -> std::io::Result<()> {
 
                 info!("Saving courses to memory...");
                 
-                let lock = MEMORY_DATABASE.lock().await;
-                let previous_courses = lock.course_cache.clone();
-                drop(lock);
+                let previous_courses = current_snapshot().courses.to_vec();
 
                 info!("Merging courses...");
+                let started = Instant::now();
                 let final_course_update = merge_courses(previous_courses, final_course_update);
+                record_update_stage("merge", started, true).await;
                 info!("Merged!");
 
-                let mut lock = MEMORY_DATABASE.lock().await;
-    
-                lock.course_cache = final_course_update;
-                lock.last_change = get_unix_timestamp();
-                lock.term = term_update.clone();
-                lock.areas_cache = area_update.clone();
-
-                drop(lock);
+                publish_courses(final_course_update, area_update, term_update.clone(), match_rules).await;
                 
                 info!("Saved courses to memory!");
                 
                 info!("Saving caches to file...");
 
+                let started = Instant::now();
+                let snapshot = current_snapshot();
                 let lock = MEMORY_DATABASE.lock().await;
-
-                let _ = save_course_database(lock.course_cache.clone());
-                let _ = save_code_database(lock.code_cache.clone());
-                let _ = save_areas_database(lock.areas_cache.clone());
-
+                let code_cache = lock.code_cache.clone();
                 drop(lock);
 
+                let saved = save_course_database(snapshot.courses.to_vec()).is_ok()
+                    & save_code_database(code_cache).is_ok()
+                    & save_areas_database(snapshot.areas.to_vec()).is_ok();
+                record_update_stage("save", started, saved).await;
+
                 info!("Saved cache to file!");
             }   
         }
 
         info!("Finished schedule update with {} courses for term {}!", number_of_courses, term_update);
+        record_update_stage("total", loop_started, number_of_courses > 0).await;
 
         // Jitter to avoid rate limiting (possibly)
         let mut jitter = thread_rng().gen_range(0..100);
 
+        let number_of_repeated_errors = get_consecutive_failures(&Source::PomonaApi).await;
+
         if number_of_repeated_errors > 1 {
             warn!("Currently at {} repeated errors...\nAdding time to update interval", number_of_repeated_errors);
             
@@ -208,7 +243,7 @@ async fn update_loop() -> std::io::Result<()> {
         }
 
         
-        thread::sleep(Duration::from_secs(API_UPDATE_INTERVAL + jitter));
+        thread::sleep(Duration::from_secs(CONFIG.update.api_update_interval + jitter));
     }
 }
 
@@ -217,17 +252,16 @@ async fn update_loop() -> std::io::Result<()> {
 /// server is run in the main thread and blocks until done.
 async fn async_main() -> std::io::Result<()> {
     info!("Loading database(s)...");
-    
-    //test_menu_update().await;
-    //test_full_update().await;
-    // Load databases if they exist
+
+    // Load databases if they exist. Descriptions go first so they are
+    // matched once, when the courses are published.
+    publish_descriptions(load_descriptions_database().unwrap()).await;
+    publish_courses(load_course_database().unwrap(), load_areas_database().unwrap(), "".to_string(), load_match_rules()).await;
+    publish_locations(load_locations_database().unwrap()).await;
+    publish_menus(load_menu_database().unwrap()).await;
+
     let mut lock = MEMORY_DATABASE.lock().await;
-    lock.course_cache = load_course_database().unwrap();
     lock.code_cache = load_code_database().unwrap();
-    lock.locations_cache = load_locations_database().unwrap();
-    lock.descriptions_cache = load_descriptions_database().unwrap();
-    lock.menu_cache = load_menu_database().unwrap();
-    lock.areas_cache = load_areas_database().unwrap();
     drop(lock);
 
     info!("Database(s) loaded!");
@@ -236,15 +270,7 @@ async fn async_main() -> std::io::Result<()> {
         let _ = update_loop().await;
     });
 
-    let mut builder =
-        SslAcceptor::mozilla_intermediate(SslMethod::tls()).unwrap();
-    builder
-        .set_private_key_file("/etc/letsencrypt/live/api.5scheduler.io/privkey.pem", SslFiletype::PEM)
-        .unwrap();
-    builder.set_certificate_chain_file("/etc/letsencrypt/live/api.5scheduler.io/fullchain.pem").unwrap();
-
-
-    HttpServer::new(|| {
+    let server = HttpServer::new(|| {
         let cors = Cors::default()
             .allow_any_origin()
             .allow_any_header()
@@ -256,21 +282,62 @@ async fn async_main() -> std::io::Result<()> {
             .wrap(cors)
             .wrap(actix_web::middleware::Compress::new(http::ContentEncoding::Gzip))
             .wrap(actix_web::middleware::Logger::default())
+            .wrap_fn(|req, srv| {
+                let started = Instant::now();
+                let method = req.method().to_string();
+                let route = req.match_pattern().unwrap_or_else(|| "unmatched".to_string());
+                let response = srv.call(req);
+
+                async move {
+                    let response = response.await?;
+                    record_request(&method, &route, response.status().as_u16(), started.elapsed());
+                    Ok(response)
+                }
+            })
             .service(update_all_courses)
             .service(update_if_stale)
             .service(get_unique_code)
             .service(get_course_list_by_code)
             .service(get_locations_database)
             .service(get_status)
+            .service(get_health)
+            .service(get_metrics)
+            .service(get_traffic)
+            .service(get_free_rooms)
+            .service(get_room_schedule)
+            .service(get_schedule_walking)
+            .service(get_map)
+            .service(get_description_matches)
+            .service(get_catalog_search)
+            .service(get_similar_courses)
+            .service(get_offered_courses)
+            .service(get_requirements)
             .service(get_full_year_catalog)
             .service(get_catalog_if_stale)
             .service(get_menus)
             .service(get_course_areas)
-    })
-    .bind_openssl(ADDRESS, builder)
-    .unwrap()
-    .run()
-    .await
+    });
+
+    let server = if CONFIG.server.tls {
+        let mut builder =
+            SslAcceptor::mozilla_intermediate(SslMethod::tls()).unwrap();
+        builder
+            .set_private_key_file(&CONFIG.server.private_key, SslFiletype::PEM)
+            .unwrap();
+        builder.set_certificate_chain_file(&CONFIG.server.certificate_chain).unwrap();
+
+        server.bind_openssl(CONFIG.server.address.as_str(), builder)
+    } else {
+        warn!("TLS disabled, serving plain HTTP");
+        server.bind(CONFIG.server.address.as_str())
+    };
+
+    info!("Listening on {}", CONFIG.server.address);
+
+    server
+        .unwrap()
+        .run()
+        .await
 }
 
 
@@ -287,6 +354,36 @@ fn main() {
 
     info!("5scheduler Server starting up...");
 
+    // Parse the command line and config file up front so mistakes
+    // are reported before anything starts
+    lazy_static::initialize(&CONFIG);
+
+    // The server and catalog refresh both scrape catalogs
+    if matches!(CLI.command, None | Some(Command::Serve) | Some(Command::RefreshCatalog { .. })) {
+        if let Err(e) = CONFIG.check_graduate_catalogs() {
+            eprintln!("Invalid configuration: {}", e);
+            exit(2);
+        }
+    }
+
+    match CLI.command.clone() {
+        None | Some(Command::Serve) => serve(),
+        Some(command) => {
+            let result = tokio::runtime::Builder::new_multi_thread()
+                .enable_all()
+                .build()
+                .unwrap()
+                .block_on(run_job(command));
+
+            if let Err(e) = result {
+                error!("{}", e);
+                exit(1);
+            }
+        }
+    }
+}
+
+fn serve() {
     let _ = actix_web::rt::System::with_tokio_rt(|| {
         tokio::runtime::Builder::new_multi_thread()
             .enable_all()
@@ -0,0 +1,294 @@
+// ----------------------------------------------------------------------------
+// Parser for the Pomona API's MeetTime strings.
+//
+// A MeetTime looks like "10:00-10:50AM. Lincoln Hall Room 1125 (MWF)": the
+// time range, often with AM/PM only on the end time, then the places the
+// class meets separated by semicolons, then the days. "TBA" stands in for
+// a time that isn't set yet, and the places can be missing entirely.
+//
+// The tests below are written by hand from the format above. The cached
+// pom_courses.json doesn't keep MeetTime, so the only values seen in real
+// data are the blank and "TBA" ones; real strings with times and rooms
+// still need to be captured from the API and added as tests.
+// ----------------------------------------------------------------------------
+
+use ::serde::{Deserialize, Serialize};
+use chrono::NaiveTime;
+use std::fmt;
+
+// Stand-ins for "no time yet"
+const NO_TIME: &[&str] = &["TBA", "TBD", "ARR", "ARRANGED"];
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MeetTimeError {
+    Empty,
+    // No "-" between the start and end times
+    MissingRange(String),
+    InvalidTime(String),
+    // Neither time says AM or PM, and they can't be 24 hour times
+    MissingMeridiem(String),
+    // Ends before it starts
+    BackwardsRange(String),
+}
+
+impl fmt::Display for MeetTimeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MeetTimeError::Empty => write!(f, "empty meeting time"),
+            MeetTimeError::MissingRange(s) => write!(f, "no time range in \"{}\"", s),
+            MeetTimeError::InvalidTime(s) => write!(f, "invalid time \"{}\"", s),
+            MeetTimeError::MissingMeridiem(s) => write!(f, "no AM/PM in \"{}\"", s),
+            MeetTimeError::BackwardsRange(s) => write!(f, "\"{}\" ends before it starts", s),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MeetTime {
+    // None for "TBA" and friends
+    pub times: Option<(NaiveTime, NaiveTime)>,
+    // "Lincoln Hall Room 1125", one per room
+    pub places: Vec<String>,
+    // "MWF", when given
+    pub days: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Meridiem {
+    AM,
+    PM,
+}
+
+impl Meridiem {
+    fn other(self) -> Self {
+        match self {
+            Meridiem::AM => Meridiem::PM,
+            Meridiem::PM => Meridiem::AM,
+        }
+    }
+}
+
+/// A clock reading like "10:50AM", "9:00" or "10PM"
+struct Clock {
+    hour: u32,
+    minute: u32,
+    meridiem: Option<Meridiem>,
+}
+
+impl Clock {
+    fn parse(text: &str) -> Result<Self, MeetTimeError> {
+        let invalid = || MeetTimeError::InvalidTime(text.to_string());
+
+        let (digits, meridiem) = if let Some(digits) = text.strip_suffix("AM") {
+            (digits, Some(Meridiem::AM))
+        } else if let Some(digits) = text.strip_suffix("PM") {
+            (digits, Some(Meridiem::PM))
+        } else {
+            (text, None)
+        };
+
+        let (hour, minute) = digits.split_once(':').unwrap_or((digits, "0"));
+        let hour: u32 = hour.parse().map_err(|_| invalid())?;
+        let minute: u32 = minute.parse().map_err(|_| invalid())?;
+
+        let valid_hour = match meridiem {
+            Some(_) => (1..=12).contains(&hour),
+            None => hour <= 23,
+        };
+
+        if !valid_hour || minute > 59 {
+            return Err(invalid());
+        }
+
+        Ok(Self {
+            hour,
+            minute,
+            meridiem,
+        })
+    }
+
+    /// Minutes since midnight, reading the hour with `meridiem` (12AM is
+    /// midnight, 12PM noon) or as a 24 hour clock if None
+    fn minutes(&self, meridiem: Option<Meridiem>) -> u32 {
+        let hour = match meridiem {
+            Some(Meridiem::AM) => self.hour % 12,
+            Some(Meridiem::PM) => self.hour % 12 + 12,
+            None => self.hour,
+        };
+
+        hour * 60 + self.minute
+    }
+}
+
+fn to_time(minutes: u32) -> NaiveTime {
+    NaiveTime::from_hms(minutes / 60, minutes % 60, 0)
+}
+
+/// Parses a range like "10:00-10:50AM" or "11:00AM-12:15PM"
+fn parse_range(text: &str) -> Result<(NaiveTime, NaiveTime), MeetTimeError> {
+    let (start, end) = text
+        .split_once('-')
+        .ok_or_else(|| MeetTimeError::MissingRange(text.to_string()))?;
+
+    let start = Clock::parse(start)?;
+    let end = Clock::parse(end)?;
+
+    let (start_minutes, end_minutes) = match (start.meridiem, end.meridiem) {
+        (Some(s), Some(e)) => (start.minutes(Some(s)), end.minutes(Some(e))),
+        // A missing AM/PM is the other time's, unless that would put the
+        // start after the end: "11:00-12:15PM" starts at 11AM
+        (None, Some(e)) => {
+            let end_minutes = end.minutes(Some(e));
+            let start_minutes = start.minutes(Some(e));
+
+            if start_minutes > end_minutes {
+                (start.minutes(Some(e.other())), end_minutes)
+            } else {
+                (start_minutes, end_minutes)
+            }
+        }
+        (Some(s), None) => {
+            let start_minutes = start.minutes(Some(s));
+            let end_minutes = end.minutes(Some(s));
+
+            if end_minutes < start_minutes {
+                (start_minutes, end.minutes(Some(s.other())))
+            } else {
+                (start_minutes, end_minutes)
+            }
+        }
+        // Only unambiguous as a 24 hour clock
+        (None, None) if start.hour > 12 || end.hour > 12 || start.hour == 0 => {
+            (start.minutes(None), end.minutes(None))
+        }
+        (None, None) => return Err(MeetTimeError::MissingMeridiem(text.to_string())),
+    };
+
+    match (start_minutes, end_minutes) {
+        // Ending at midnight, the last minute of the day
+        (s, 0) if s > 0 => Ok((to_time(s), NaiveTime::from_hms(23, 59, 59))),
+        (s, e) if e < s => Err(MeetTimeError::BackwardsRange(text.to_string())),
+        (s, e) => Ok((to_time(s), to_time(e))),
+    }
+}
+
+/// Parses a MeetTime string
+pub fn parse_meet_time(text: &str) -> Result<MeetTime, MeetTimeError> {
+    let text = text.trim();
+
+    if text.is_empty() {
+        return Err(MeetTimeError::Empty);
+    }
+
+    // Days in trailing parentheses, "(MWF)"
+    let (rest, days) = match text.rfind('(') {
+        Some(open) if text.ends_with(')') => {
+            let days = text[open + 1..text.len() - 1].trim();
+
+            (text[..open].trim(), Some(days.to_string()).filter(|d| !d.is_empty()))
+        }
+        _ => (text, None),
+    };
+
+    // The time ends at the first ".", with the places after it
+    let (time, places) = rest.split_once('.').unwrap_or((rest, ""));
+
+    let time: String = time
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect::<String>()
+        .to_uppercase();
+
+    let times = if time.is_empty() || NO_TIME.contains(&time.as_str()) {
+        None
+    } else {
+        Some(parse_range(&time)?)
+    };
+
+    let places = places
+        .split(';')
+        .map(|place| place.trim().to_string())
+        .filter(|place| !place.is_empty())
+        .collect();
+
+    Ok(MeetTime {
+        times,
+        places,
+        days,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(hour: u32, minute: u32) -> NaiveTime {
+        NaiveTime::from_hms(hour, minute, 0)
+    }
+
+    fn times(text: &str) -> (NaiveTime, NaiveTime) {
+        parse_meet_time(text).unwrap().times.unwrap()
+    }
+
+    #[test]
+    fn blank_and_tba() {
+        assert_eq!(parse_meet_time(""), Err(MeetTimeError::Empty));
+        assert_eq!(parse_meet_time("   "), Err(MeetTimeError::Empty));
+        assert_eq!(parse_meet_time("TBA"), Ok(MeetTime::default()));
+    }
+
+    #[test]
+    fn reads_time_places_and_days() {
+        let parsed = parse_meet_time("10:00-10:50AM. Lincoln Hall Room 1125 (MWF)").unwrap();
+
+        assert_eq!(parsed.times, Some((time(10, 0), time(10, 50))));
+        assert_eq!(parsed.places, vec!["Lincoln Hall Room 1125"]);
+        assert_eq!(parsed.days, Some("MWF".to_string()));
+    }
+
+    #[test]
+    fn keeps_every_place() {
+        let parsed = parse_meet_time("01:15-04:15PM. Seaver North Room 104; Seaver North Room 126 (F)").unwrap();
+
+        assert_eq!(parsed.places, vec!["Seaver North Room 104", "Seaver North Room 126"]);
+    }
+
+    #[test]
+    fn places_and_days_are_optional() {
+        let parsed = parse_meet_time("02:45-04:00PM (TR)").unwrap();
+        assert!(parsed.places.is_empty());
+        assert_eq!(parsed.days, Some("TR".to_string()));
+
+        let parsed = parse_meet_time("10:00-10:50AM").unwrap();
+        assert!(parsed.places.is_empty());
+        assert_eq!(parsed.days, None);
+    }
+
+    #[test]
+    fn shares_the_meridiem() {
+        assert_eq!(times("01:15-02:30PM"), (time(13, 15), time(14, 30)));
+        // Unless that would start after the end
+        assert_eq!(times("11:00-12:15PM"), (time(11, 0), time(12, 15)));
+        assert_eq!(times("11:00AM-12:15PM"), (time(11, 0), time(12, 15)));
+        assert_eq!(times("10:00 AM - 10:50 AM"), (time(10, 0), time(10, 50)));
+    }
+
+    #[test]
+    fn reads_24_hour_times() {
+        assert_eq!(times("13:15-14:30"), (time(13, 15), time(14, 30)));
+    }
+
+    #[test]
+    fn ends_at_midnight() {
+        assert_eq!(times("09:00PM-12:00AM"), (time(21, 0), NaiveTime::from_hms(23, 59, 59)));
+    }
+
+    #[test]
+    fn rejects_bad_ranges() {
+        assert!(matches!(parse_meet_time("10:00-10:50. Room 1"), Err(MeetTimeError::MissingMeridiem(_))));
+        assert!(matches!(parse_meet_time("10:50-10:00AM"), Err(MeetTimeError::BackwardsRange(_))));
+        assert!(matches!(parse_meet_time("10:00AM (MWF)"), Err(MeetTimeError::MissingRange(_))));
+        assert!(matches!(parse_meet_time("25:00-26:00"), Err(MeetTimeError::InvalidTime(_))));
+        assert!(matches!(parse_meet_time("10:00-10:50XM"), Err(MeetTimeError::InvalidTime(_))));
+    }
+}
@@ -1,11 +1,16 @@
+use crate::config::CONFIG;
 use crate::course_api::*;
+use crate::geo::Coordinates;
+use crate::health::*;
 use crate::reqwest_get_ignore_ssl;
 use crate::scrape_descriptions::*;
 use crate::School::*;
 use std::collections::HashMap;
+use std::future::Future;
 use std::fs::File;
 use std::io::BufReader;
 use std::io::Read;
+use std::time::Instant;
 
 use ::serde::*;
 use chrono::*;
@@ -13,7 +18,7 @@ use escaper::*;
 use log::info;
 use serde_json::Value;
 
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 
 pub struct SchoolMenu {
     school: School,
@@ -37,7 +42,7 @@ impl SchoolMenu {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 
 pub struct Cafe {
     name: String,
@@ -56,6 +61,14 @@ impl Cafe {
         }
     }
 
+    pub fn get_name(&self) -> &String {
+        &self.name
+    }
+
+    pub fn get_day_menus(&self) -> &Vec<DayMenu> {
+        &self.day_menus
+    }
+
     pub fn add_menus(&mut self, menus: Vec<DayMenu>) {
         self.day_menus.extend(menus);
     }
@@ -65,7 +78,7 @@ impl Cafe {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 
 pub struct DayMenu {
     date: String,
@@ -80,6 +93,14 @@ impl DayMenu {
         }
     }
 
+    pub fn get_date(&self) -> &String {
+        &self.date
+    }
+
+    pub fn get_menus(&self) -> &Vec<Menu> {
+        &self.menus
+    }
+
     pub fn add_menus(&mut self, menus: Vec<Menu>) {
         self.menus.extend(menus);
     }
@@ -89,7 +110,7 @@ impl DayMenu {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 
 pub struct Menu {
     date: String,
@@ -98,32 +119,67 @@ pub struct Menu {
     time_opens: String,
     time_closes: String,
     stations: Vec<Station>,
-    lat: u64,  // Multiplied by 1e7
-    long: u64, // Multiplied by 1e7
+    #[serde(default)]
+    coordinates: Option<Coordinates>,
+    // What /getMenus sent before coordinates, in millionths of a degree,
+    // kept for existing clients. Computed as it always was, so negative
+    // longitudes come out as 0.
+    #[serde(default)]
+    lat: u64,
+    #[serde(default)]
+    long: u64,
     notes: String,
 }
 
 impl Menu {
-    pub fn create_base_menu(date: String, description: String, lat: u64, long: u64) -> Self {
-        Self {
+    pub fn create_base_menu(date: String, description: String, coordinates: Option<Coordinates>) -> Self {
+        let mut menu = Self {
             date,
             description,
             time_slot: MenuTime::NA,
             time_opens: "".to_string(),
             time_closes: "".to_string(),
             stations: Vec::new(),
-            lat,
-            long,
+            coordinates: None,
+            lat: 0,
+            long: 0,
             notes: "".to_string(),
+        };
+
+        menu.set_coordinates(coordinates);
+        menu
+    }
+
+    pub fn set_coordinates(&mut self, coordinates: Option<Coordinates>) {
+        self.coordinates = coordinates;
+
+        if let Some(coordinates) = coordinates {
+            self.lat = (coordinates.latitude * 1_000_000.0) as u64;
+            self.long = (coordinates.longitude * 1_000_000.0) as u64;
         }
     }
 
+    pub fn get_time_slot(&self) -> &MenuTime {
+        &self.time_slot
+    }
+
+    pub fn get_time_opens(&self) -> &String {
+        &self.time_opens
+    }
+
+    pub fn get_time_closes(&self) -> &String {
+        &self.time_closes
+    }
+
+    pub fn get_coordinates(&self) -> Option<Coordinates> {
+        self.coordinates
+    }
+
     pub fn create_base_menus(
         num: usize,
         date: &NaiveDate,
         description: String,
-        lat: u64,
-        long: u64,
+        coordinates: Option<Coordinates>,
     ) -> Vec<Self> {
         let mut menus: Vec<Self> = Vec::new();
         let mut iter_date = date.clone();
@@ -132,8 +188,7 @@ impl Menu {
             let menu = Self::create_base_menu(
                 iter_date.format("%Y-%m-%d").to_string(),
                 description.clone(),
-                lat,
-                long,
+                coordinates,
             );
             menus.push(menu);
             // Increment date
@@ -498,19 +553,6 @@ TCCS:
     ACC Cafe: ID: 1524
 */
 
-// Cafe IDs or URLs
-const MCCONNELL: &str = "219";
-const PIT_STOP: &str = "220";
-const SHAKEDOWN: &str = "NA";
-const MALLOT: &str = "2253";
-const COLLINS: &str = "50";
-const HUB: &str = "51";
-const HUB_1: &str = "52";
-const HOCH: &str = "https://menus.sodexomyway.com/BiteMenu/MenuOnly?menuId=15258&locationId=13147001&whereami=http://hmc.sodexomyway.com/dining-near-me/hoch-shanahan-dining-commons";
-const FRANK: &str = "https://my.pomona.edu/eatec/Frank.json";
-const FRARY: &str = "https://my.pomona.edu/eatec/Frary.json";
-const OLDENBORG: &str = "https://my.pomona.edu/eatec/Oldenborg.json";
-
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum MenuError {
     InvalidDate,
@@ -520,6 +562,25 @@ pub enum MenuError {
     ErrorParsingMenu,
 }
 
+impl std::fmt::Display for MenuError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+/// Waits on a single cafe's menus, recording how it went
+async fn track_cafe<F>(cafe: &Cafe, fetch: F) -> Result<(Vec<DayMenu>, Vec<Meal>), MenuError>
+where
+    F: Future<Output = Result<(Vec<DayMenu>, Vec<Meal>), MenuError>>,
+{
+    let started = Instant::now();
+    let result = fetch.await;
+
+    record_result(&Source::Cafe(cafe.name.clone()), started, &result, |(menus, _)| menus.len()).await;
+
+    result
+}
+
 pub async fn get_seven_day_menus() -> Result<HashMap<School, SchoolMenu>, MenuError>
 {
     let today = Local::today().naive_local();
@@ -555,6 +616,11 @@ pub async fn get_seven_day_menus() -> Result<HashMap<School, SchoolMenu>, MenuEr
     Ok(menus)
 }
 
+// Hoch-Shanahan, home of Hoch and Jay's Place
+fn hoch_coordinates() -> Option<Coordinates> {
+    Coordinates::new(34.1057862, -117.7098119).ok()
+}
+
 pub async fn get_hmc_menus(
     start_date: NaiveDate,
     days_to_get: usize,
@@ -564,12 +630,14 @@ pub async fn get_hmc_menus(
     // Get Hoch
     info!("Getting menus for Hoch");
     let mut hoch_cafe: Cafe = Cafe::new("Hoch Dining Hall".to_string(), "".to_string());
-    let (menus, to_go_meals) = get_sodexomyway_menus(
-        days_to_get,
-        HOCH,
-        &start_date,
-        (34.1057862 * 1_000_000_f64) as u64,
-        (-117.7098119 * 1_000_000_f64) as u64,
+    let (menus, to_go_meals) = track_cafe(
+        &hoch_cafe,
+        get_sodexomyway_menus(
+            days_to_get,
+            &CONFIG.cafes.hoch,
+            &start_date,
+            hoch_coordinates(),
+        ),
     )
     .await?;
     hoch_cafe.add_menus(menus);
@@ -581,11 +649,13 @@ pub async fn get_hmc_menus(
     let mut jays_place: Cafe = Cafe::new("Jay's Place".to_string(), "".to_string());
     // Have to manually create a to-go item list
     // from a json file
-    let (menus, to_go_meals) = get_json_file_menus(
-        "jay_place_menus.json",
-        (34.1057862 * 1_000_000_f64) as u64,
-        (-117.7098119 * 1_000_000_f64) as u64,
-    ).unwrap();
+    let (menus, to_go_meals) = track_cafe(&jays_place, async {
+        get_json_file_menus(
+            &CONFIG.paths.jay_place_menus,
+            hoch_coordinates(),
+        )
+    })
+    .await?;
 
     jays_place.add_menus(menus);
     jays_place.add_to_go_meals(to_go_meals);
@@ -604,8 +674,11 @@ pub async fn get_pitzer_menus(
     // Get McConnell
     info!("Getting menus for McConnell");
     let mut mcconnell_cafe: Cafe = Cafe::new("McConnell Dining Hall".to_string(), "".to_string());
-    let (menus, to_go_meals) =
-        get_cafebonappetit_menus(days_to_get, MCCONNELL, &start_date).await?;
+    let (menus, to_go_meals) = track_cafe(
+        &mcconnell_cafe,
+        get_cafebonappetit_menus(days_to_get, &CONFIG.cafes.mcconnell, &start_date),
+    )
+    .await?;
     mcconnell_cafe.add_menus(menus);
     mcconnell_cafe.add_to_go_meals(to_go_meals);
 
@@ -614,7 +687,11 @@ pub async fn get_pitzer_menus(
     // Get Pit Stop
     info!("Getting menus for Pit Stop");
     let mut pit_stop_cafe: Cafe = Cafe::new("Pit Stop Cafe".to_string(), "".to_string());
-    let (meals, to_go_meals) = get_cafebonappetit_menus(days_to_get, PIT_STOP, &start_date).await?;
+    let (meals, to_go_meals) = track_cafe(
+        &pit_stop_cafe,
+        get_cafebonappetit_menus(days_to_get, &CONFIG.cafes.pit_stop, &start_date),
+    )
+    .await?;
     pit_stop_cafe.add_menus(meals);
     pit_stop_cafe.add_to_go_meals(to_go_meals);
 
@@ -632,7 +709,11 @@ pub async fn get_scripps_menus(
     // Get Mallot
     info!("Getting menus for Mallot");
     let mut mallot_cafe: Cafe = Cafe::new("Mallot Dining Hall".to_string(), "".to_string());
-    let (menus, to_go_meals) = get_cafebonappetit_menus(days_to_get, MALLOT, &start_date).await?;
+    let (menus, to_go_meals) = track_cafe(
+        &mallot_cafe,
+        get_cafebonappetit_menus(days_to_get, &CONFIG.cafes.mallot, &start_date),
+    )
+    .await?;
     mallot_cafe.add_menus(menus);
     mallot_cafe.add_to_go_meals(to_go_meals);
 
@@ -650,7 +731,11 @@ pub async fn get_cmc_menus(
     // Get Collins
     info!("Getting menus for Collins");
     let mut collins_cafe: Cafe = Cafe::new("Collins Dining Hall".to_string(), "".to_string());
-    let (menus, to_go_meals) = get_cafebonappetit_menus(days_to_get, COLLINS, &start_date).await?;
+    let (menus, to_go_meals) = track_cafe(
+        &collins_cafe,
+        get_cafebonappetit_menus(days_to_get, &CONFIG.cafes.collins, &start_date),
+    )
+    .await?;
     collins_cafe.add_menus(menus);
     collins_cafe.add_to_go_meals(to_go_meals);
 
@@ -659,7 +744,11 @@ pub async fn get_cmc_menus(
     // Get Hub
     info!("Getting menus for The Hub");
     let mut hub_cafe: Cafe = Cafe::new("The Hub".to_string(), "".to_string());
-    let (menus, to_go_meals) = get_cafebonappetit_menus(days_to_get, HUB, &start_date).await?;
+    let (menus, to_go_meals) = track_cafe(
+        &hub_cafe,
+        get_cafebonappetit_menus(days_to_get, &CONFIG.cafes.hub, &start_date),
+    )
+    .await?;
     hub_cafe.add_menus(menus);
     hub_cafe.add_to_go_meals(to_go_meals);
 
@@ -675,7 +764,8 @@ pub async fn get_pomona_menus(start_date: NaiveDate, days_to_get: usize) -> Resu
     // Get Frank
     info!("Getting menus for Frank");
     let mut frank_cafe: Cafe = Cafe::new("Frank Dining Hall".to_string(), "".to_string());
-    let (menus, to_go_meals) = get_eatec_menu(days_to_get, FRANK, &start_date).await?;
+    let (menus, to_go_meals) =
+        track_cafe(&frank_cafe, get_eatec_menu(days_to_get, &CONFIG.cafes.frank, &start_date)).await?;
     frank_cafe.add_menus(menus);
     frank_cafe.add_to_go_meals(to_go_meals);
 
@@ -684,7 +774,8 @@ pub async fn get_pomona_menus(start_date: NaiveDate, days_to_get: usize) -> Resu
     // Get Frary
     info!("Getting menus for Frary");
     let mut frary_cafe: Cafe = Cafe::new("Frary Dining Hall".to_string(), "".to_string());
-    let (menus, to_go_meals) = get_eatec_menu(days_to_get, FRARY, &start_date).await?;
+    let (menus, to_go_meals) =
+        track_cafe(&frary_cafe, get_eatec_menu(days_to_get, &CONFIG.cafes.frary, &start_date)).await?;
     frary_cafe.add_menus(menus);
     frary_cafe.add_to_go_meals(to_go_meals);
 
@@ -693,7 +784,11 @@ pub async fn get_pomona_menus(start_date: NaiveDate, days_to_get: usize) -> Resu
     // Get Oldenborg
     info!("Getting menus for Oldenborg");
     let mut oldenborg_cafe: Cafe = Cafe::new("Oldenborg Dining Hall".to_string(), "".to_string());
-    let (menus, to_go_meals) = get_eatec_menu(days_to_get, OLDENBORG, &start_date).await?;
+    let (menus, to_go_meals) = track_cafe(
+        &oldenborg_cafe,
+        get_eatec_menu(days_to_get, &CONFIG.cafes.oldenborg, &start_date),
+    )
+    .await?;
     oldenborg_cafe.add_menus(menus);
     oldenborg_cafe.add_to_go_meals(to_go_meals);
 
@@ -740,15 +835,8 @@ pub async fn get_cafebonappetit_menus(
 
     let notes = pretty_parse_html(&description);
 
-    let lat = (json.get("latitude").unwrap().to_string())
-        .parse::<f64>()
-        .unwrap_or(0.0);
-    let long = (json.get("longitude").unwrap().to_string())
-        .parse::<f64>()
-        .unwrap_or(0.0);
-
-    let lat: u64 = (lat * 1_000_000.0) as u64;
-    let long: u64 = (long * 1_000_000.0) as u64;
+    // Given as strings, and sometimes missing
+    let coordinates = Coordinates::from_values(&json["latitude"], &json["longitude"]);
 
     // Create url to build request off of
     let mut url = format!(
@@ -815,7 +903,7 @@ pub async fn get_cafebonappetit_menus(
     // Create the base seven menus
     let mut menus = Vec::new();
     let base_menu =
-        Menu::create_base_menu(start_date.format("%Y-%m-%d").to_string(), notes, lat, long);
+        Menu::create_base_menu(start_date.format("%Y-%m-%d").to_string(), notes, coordinates);
 
     // Get the menu for each day
     for (index, day) in days.iter().enumerate() {
@@ -888,8 +976,7 @@ pub async fn get_sodexomyway_menus(
     num_days: usize,
     menu_url: &str,
     start_date: &NaiveDate,
-    lat: u64,
-    long: u64,
+    coordinates: Option<Coordinates>,
 ) -> Result<(Vec<DayMenu>, Vec<Meal>), MenuError> {
     let first_half = format!("{}&startDate={}", menu_url, start_date.format("%m/%d/%Y"));
 
@@ -999,7 +1086,7 @@ pub async fn get_sodexomyway_menus(
         // Get the dayparts
 
         let dayparts = day["dayParts"].as_array().unwrap();
-        let base_menu = Menu::create_base_menu(date.clone(), "".to_string(), lat, long);
+        let base_menu = Menu::create_base_menu(date.clone(), "".to_string(), coordinates);
 
         for part in dayparts {
             let mut new_menu = base_menu.clone();
@@ -1127,7 +1214,7 @@ pub async fn get_eatec_menu(num_days: usize, menu_url: &str, start_date: &NaiveD
 
     let mut menus: Vec<DayMenu> = Vec::new();
     let mut day_menu: DayMenu = DayMenu::new(current_date.format("%Y-%m-%d").to_string());
-    let mut menu: Menu = Menu::create_base_menu(current_date.format("%Y-%m-%d").to_string(), "".to_string(), 0, 0);
+    let mut menu: Menu = Menu::create_base_menu(current_date.format("%Y-%m-%d").to_string(), "".to_string(), None);
 
     for json_station in &json_menu_final {
         // If it's closed, skip the day
@@ -1135,7 +1222,7 @@ pub async fn get_eatec_menu(num_days: usize, menu_url: &str, start_date: &NaiveD
             current_date = current_date.succ();
 
             day_menu = DayMenu::new(current_date.format("%Y-%m-%d").to_string());
-            menu = Menu::create_base_menu(current_date.format("%Y-%m-%d").to_string(), "".to_string(), 0, 0);
+            menu = Menu::create_base_menu(current_date.format("%Y-%m-%d").to_string(), "".to_string(), None);
 
             println!("Closed on {}", current_date.format("%Y-%m-%d"));
             println!("{:?}", json_station);
@@ -1179,7 +1266,7 @@ pub async fn get_eatec_menu(num_days: usize, menu_url: &str, start_date: &NaiveD
 
             day_menu = DayMenu::new(current_date.format("%Y-%m-%d").to_string());
 
-            menu = Menu::create_base_menu(current_date.format("%Y-%m-%d").to_string(), "".to_string(), 0, 0);
+            menu = Menu::create_base_menu(current_date.format("%Y-%m-%d").to_string(), "".to_string(), None);
 
         } else if json_station["@mealperiodname"].as_str().unwrap() != current_station {
             if menu.time_slot != MenuTime::NA {
@@ -1188,7 +1275,7 @@ pub async fn get_eatec_menu(num_days: usize, menu_url: &str, start_date: &NaiveD
 
             current_station = json_station["@mealperiodname"].as_str().unwrap();
 
-            menu = Menu::create_base_menu(current_date.format("%Y-%m-%d").to_string(), "".to_string(), 0, 0);
+            menu = Menu::create_base_menu(current_date.format("%Y-%m-%d").to_string(), "".to_string(), None);
         }
 
         menu.parse_set_timeslot(current_station);
@@ -1202,7 +1289,7 @@ pub async fn get_eatec_menu(num_days: usize, menu_url: &str, start_date: &NaiveD
     Ok((menus, Vec::new()))
 }
 
-pub fn get_json_file_menus(file_path: &str, lat: u64, long: u64) -> Result<(Vec<DayMenu>, Vec<Meal>), MenuError> {
+pub fn get_json_file_menus(file_path: &str, coordinates: Option<Coordinates>) -> Result<(Vec<DayMenu>, Vec<Meal>), MenuError> {
     let file = File::open(file_path);
 
     if file.is_err() {
@@ -1217,7 +1304,40 @@ pub fn get_json_file_menus(file_path: &str, lat: u64, long: u64) -> Result<(Vec<
 
     reader.read_to_string(&mut contents);
 
-    let json = serde_json::from_str::<(Vec<DayMenu>, Vec<Meal>)>(&contents).unwrap();
+    let (mut menus, to_go_meals) = serde_json::from_str::<(Vec<DayMenu>, Vec<Meal>)>(&contents).unwrap();
+
+    for menu in menus.iter_mut().flat_map(|day| day.menus.iter_mut()) {
+        if menu.coordinates.is_none() {
+            menu.set_coordinates(coordinates);
+        }
+    }
+
+    Ok((menus, to_go_meals))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn menus_still_send_lat_and_long() {
+        let coordinates = Coordinates::new(34.5, -117.75).ok();
+        let menu = Menu::create_base_menu("2021-09-25".to_string(), "".to_string(), coordinates);
+
+        let json = serde_json::to_value(&menu).unwrap();
+
+        assert_eq!(json["lat"], 34_500_000);
+        assert_eq!(json["long"], 0);
+        assert_eq!(json["coordinates"]["latitude"], 34.5);
+        assert_eq!(json["coordinates"]["longitude"], -117.75);
+    }
 
-    Ok(json)
-}
\ No newline at end of file
+    #[test]
+    fn reads_menus_cached_before_coordinates() {
+        let json = r#"{"date":"2021-09-25","description":"","time_slot":"Lunch","time_opens":"","time_closes":"","stations":[],"lat":0,"long":0,"notes":""}"#;
+
+        let menu: Menu = serde_json::from_str(json).unwrap();
+
+        assert_eq!(menu.get_coordinates(), None);
+    }
+}
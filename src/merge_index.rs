@@ -0,0 +1,687 @@
+// ----------------------------------------------------------------------------
+// Lookup indexes for merging courses and catalog descriptions.
+//
+// The merges used to scan (and clone) every description for every course,
+// which made a full merge take minutes. These indexes answer the same
+// questions with hash lookups. Courses are matched to descriptions by
+// parsed CourseIdentifiers, so "MATH-030-HM" no longer matches "MATH-03" the
+// way the old substring checks did. Merging the catalogs still goes by
+// substrings, answered by a SubstringIndex.
+// ----------------------------------------------------------------------------
+
+use crate::course_api::*;
+use crate::identifier::*;
+use crate::scrape_descriptions::CourseDescription;
+use ::serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::iter;
+
+type Trigram = (char, char, char);
+
+/// Trigrams built the same way as rust_fuzzy_search, so scores match
+/// `fuzzy_search_best_n`
+fn trigrams(s: &str) -> Vec<Trigram> {
+    let it_1 = iter::once(' ').chain(iter::once(' ')).chain(s.chars());
+    let it_2 = iter::once(' ').chain(s.chars());
+    let it_3 = s.chars().chain(iter::once(' '));
+
+    it_1.zip(it_2)
+        .zip(it_3)
+        .map(|((a, b), c)| (a, b, c))
+        .collect()
+}
+
+/// Precomputed trigrams for a list of titles
+pub struct FuzzyTitleIndex {
+    len: usize,
+    postings: HashMap<Trigram, Vec<usize>>,
+}
+
+impl FuzzyTitleIndex {
+    pub fn new<'a>(titles: impl Iterator<Item = &'a str>) -> Self {
+        let mut postings: HashMap<Trigram, Vec<usize>> = HashMap::new();
+        let mut len = 0;
+
+        for (i, title) in titles.enumerate() {
+            let unique: HashSet<Trigram> = trigrams(title).into_iter().collect();
+
+            for trigram in unique {
+                postings.entry(trigram).or_default().push(i);
+            }
+
+            len += 1;
+        }
+
+        Self { len, postings }
+    }
+
+    /// Position and score of the best match for `query` among the titles
+    /// `allowed` accepts, picked like `fuzzy_search_best_n(query, titles, 1)`:
+    /// highest score, earliest title on ties. None only if no title is
+    /// allowed.
+    pub fn best(&self, query: &str, allowed: impl Fn(usize) -> bool) -> Option<(usize, f32)> {
+        let query_trigrams = trigrams(query);
+
+        // Every title's score has the same denominator (the query
+        // length), so comparing the raw trigram hits is enough
+        let mut hits = vec![0u32; self.len];
+
+        for trigram in &query_trigrams {
+            if let Some(titles) = self.postings.get(trigram) {
+                for i in titles {
+                    hits[*i] += 1;
+                }
+            }
+        }
+
+        let mut best: Option<usize> = None;
+        for (i, count) in hits.iter().enumerate() {
+            if allowed(i) && best.map_or(true, |b| *count > hits[b]) {
+                best = Some(i);
+            }
+        }
+
+        best.map(|i| (i, hits[i] as f32 / query_trigrams.len() as f32))
+    }
+}
+
+/// Finds the strings that contain a query, as `str::contains` would, by
+/// indexing every substring of each string added. Only meant for short
+/// strings like identifiers.
+#[derive(Default)]
+pub struct SubstringIndex {
+    len: usize,
+    by_substring: HashMap<String, Vec<usize>>,
+}
+
+impl SubstringIndex {
+    pub fn push(&mut self, text: &str) {
+        let index = self.len;
+        self.len += 1;
+
+        let bounds: Vec<usize> = text
+            .char_indices()
+            .map(|(i, _)| i)
+            .chain(iter::once(text.len()))
+            .collect();
+
+        let mut seen = HashSet::new();
+
+        for (i, start) in bounds.iter().enumerate() {
+            for end in &bounds[i + 1..] {
+                let substring = &text[*start..*end];
+
+                if seen.insert(substring) {
+                    self.by_substring.entry(substring.to_string()).or_default().push(index);
+                }
+            }
+        }
+    }
+
+    /// Positions of the strings containing `query`, in the order they were
+    /// added. Every string contains the empty one.
+    pub fn containing(&self, query: &str) -> Vec<usize> {
+        if query.is_empty() {
+            return (0..self.len).collect();
+        }
+
+        self.by_substring.get(query).cloned().unwrap_or_default()
+    }
+}
+
+/// An identifier without its section, for matching sections of a course
+/// to its catalog entry
+pub fn catalog_key(identifier: &str) -> IdentifierKey<'_> {
+    identifier_key(identifier).map(|identifier| identifier.course())
+}
+
+/// The descriptions a course may match: its own college's plus any
+/// without a source
+struct SchoolCatalog<'a> {
+    // Indexes into the full description list, in order
+    entries: Vec<usize>,
+    by_lowercase_title: HashMap<String, usize>,
+    by_title: HashMap<&'a str, usize>,
+    // Keyed without sections, as the catalogs list courses
+    by_identifier: HashMap<IdentifierKey<'a>, Vec<usize>>,
+    fuzzy: FuzzyTitleIndex,
+}
+
+impl<'a> SchoolCatalog<'a> {
+    fn new(descriptions: &'a [CourseDescription], school: &Option<School>) -> Self {
+        let entries: Vec<usize> = descriptions
+            .iter()
+            .enumerate()
+            .filter(|(_, x)| Some(x.source.clone()) == *school || x.source == School::NA)
+            .map(|(i, _)| i)
+            .collect();
+
+        let mut by_lowercase_title = HashMap::new();
+        let mut by_title = HashMap::new();
+        let mut by_identifier: HashMap<IdentifierKey, Vec<usize>> = HashMap::new();
+
+        for i in &entries {
+            let desc = &descriptions[*i];
+
+            by_lowercase_title.entry(desc.title.to_lowercase()).or_insert(*i);
+            by_title.entry(desc.title.as_str()).or_insert(*i);
+            by_identifier
+                .entry(catalog_key(&desc.identifier))
+                .or_default()
+                .push(*i);
+        }
+
+        let fuzzy = FuzzyTitleIndex::new(entries.iter().map(|i| descriptions[*i].title.as_str()));
+
+        Self {
+            entries,
+            by_lowercase_title,
+            by_title,
+            by_identifier,
+            fuzzy,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MatchMethod {
+    // Pinned in the overrides file
+    Override,
+    ExactTitle,
+    // Same course in the catalog, the closest title if it lists several
+    Identifier,
+    // Closest title in the course's college's catalog
+    Fuzzy,
+}
+
+/// A description picked for a course. Scores run from 0 to 1, the share
+/// of the course title's trigrams found in the description's title; only
+/// fuzzy matches can score low enough to be rejected.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DescriptionMatch {
+    pub index: usize,
+    pub method: MatchMethod,
+    pub score: f32,
+}
+
+impl DescriptionMatch {
+    fn new(index: usize, method: MatchMethod, score: f32) -> Self {
+        Self {
+            index,
+            method,
+            score,
+        }
+    }
+
+    pub fn accepted(&self, min_score: f32) -> bool {
+        self.method != MatchMethod::Fuzzy || self.score >= min_score
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OverrideAction {
+    // Always use this description for the course
+    Pin,
+    // Never use this description for the course
+    Block,
+}
+
+/// A hand-made decision about one course and one catalog description,
+/// read from the description overrides file
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DescriptionOverride {
+    // Course identifier, the section is ignored
+    pub course: String,
+    // Catalog identifier of the description
+    pub description: String,
+    // Narrows `description` down when several entries share it
+    #[serde(default)]
+    pub title: Option<String>,
+    pub action: OverrideAction,
+    // Why, for whoever edits the file next
+    #[serde(default)]
+    pub note: String,
+}
+
+/// Everything that decides which matches are kept
+#[derive(Clone, Debug, Default)]
+pub struct MatchRules {
+    pub overrides: Vec<DescriptionOverride>,
+    // Fuzzy matches scoring lower are dropped
+    pub min_score: f32,
+}
+
+impl MatchRules {
+    pub fn new(overrides: Vec<DescriptionOverride>, min_score: f32) -> Self {
+        Self {
+            overrides,
+            min_score,
+        }
+    }
+}
+
+/// A course and the description it matched (or would have, when not
+/// accepted)
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MatchReport {
+    pub course: String,
+    pub course_title: String,
+    pub description: String,
+    pub description_title: String,
+    pub method: MatchMethod,
+    pub score: f32,
+    // False if the score is below the minimum, leaving the course without
+    // a description
+    pub accepted: bool,
+}
+
+/// Every course's match, worst first, built once per update for the
+/// matches report
+#[derive(Clone, Debug, Default)]
+pub struct DescriptionMatches {
+    pub min_score: f32,
+    pub reports: Vec<MatchReport>,
+}
+
+impl DescriptionMatches {
+    pub fn new(courses: &[Course], descriptions: &[CourseDescription], rules: &MatchRules) -> Self {
+        let mut index = DescriptionIndex::new(descriptions, rules);
+
+        let mut reports: Vec<MatchReport> = courses
+            .iter()
+            .filter_map(|course| index.report(course))
+            .collect();
+        reports.sort_by(|a, b| a.score.partial_cmp(&b.score).unwrap());
+
+        Self {
+            min_score: rules.min_score,
+            reports,
+        }
+    }
+
+    /// Matches scoring below a threshold, worst first
+    pub fn below(&self, score: f32) -> impl Iterator<Item = &MatchReport> {
+        self.reports.iter().take_while(move |report| report.score < score)
+    }
+}
+
+/// Index over a description list for matching courses to descriptions
+pub struct DescriptionIndex<'a> {
+    descriptions: &'a [CourseDescription],
+    schools: HashMap<Option<School>, SchoolCatalog<'a>>,
+    by_title: HashMap<&'a str, Vec<usize>>,
+    min_score: f32,
+    pinned: HashMap<IdentifierKey<'a>, usize>,
+    blocked: HashSet<(IdentifierKey<'a>, usize)>,
+}
+
+impl<'a> DescriptionIndex<'a> {
+    pub fn new(descriptions: &'a [CourseDescription], rules: &'a MatchRules) -> Self {
+        let mut by_title: HashMap<&str, Vec<usize>> = HashMap::new();
+
+        for (i, desc) in descriptions.iter().enumerate() {
+            by_title.entry(desc.title.as_str()).or_default().push(i);
+        }
+
+        let mut pinned = HashMap::new();
+        let mut blocked = HashSet::new();
+
+        for rule in &rules.overrides {
+            let course = catalog_key(&rule.course);
+            let description = catalog_key(&rule.description);
+
+            let targets = descriptions.iter().enumerate().filter(|(_, desc)| {
+                catalog_key(&desc.identifier) == description
+                    && rule.title.as_ref().map_or(true, |title| title == &desc.title)
+            });
+
+            match rule.action {
+                OverrideAction::Pin => {
+                    if let Some((i, _)) = targets.into_iter().next() {
+                        pinned.insert(course, i);
+                    }
+                }
+                OverrideAction::Block => {
+                    for (i, _) in targets {
+                        blocked.insert((course.clone(), i));
+                    }
+                }
+            }
+        }
+
+        Self {
+            descriptions,
+            schools: HashMap::new(),
+            by_title,
+            min_score: rules.min_score,
+            pinned,
+            blocked,
+        }
+    }
+
+    /// Index of the description for `course`, see `find_description`
+    /// for the matching rules
+    pub fn find(&mut self, course: &Course) -> Option<DescriptionMatch> {
+        let min_score = self.min_score;
+
+        self.candidate(course).filter(|m| m.accepted(min_score))
+    }
+
+    /// The best description for `course`, even if it scores below the
+    /// minimum
+    pub fn candidate(&mut self, course: &Course) -> Option<DescriptionMatch> {
+        let course_key = catalog_key(course.get_identifier());
+
+        if let Some(i) = self.pinned.get(&course_key) {
+            return Some(DescriptionMatch::new(*i, MatchMethod::Override, 1.0));
+        }
+
+        let blocked = &self.blocked;
+        let allowed = |i: usize| !blocked.contains(&(course_key.clone(), i));
+
+        let descriptions = self.descriptions;
+        let catalog = self
+            .schools
+            .entry(course.get_school())
+            .or_insert_with_key(|school| SchoolCatalog::new(descriptions, school));
+
+        let mut course_title = course.get_title().to_lowercase().replace("&", "and");
+
+        if course_title.contains(" - ") {
+            course_title = course_title.split("-").collect::<Vec<&str>>()[1]
+                .trim()
+                .to_string();
+        }
+
+        // Check for exact match first
+        if let Some(i) = catalog.by_lowercase_title.get(&course_title) {
+            if allowed(*i) {
+                return Some(DescriptionMatch::new(*i, MatchMethod::ExactTitle, 1.0));
+            }
+        }
+
+        // Then all descriptions for the same course
+        let matching_identifiers: Vec<usize> = catalog
+            .by_identifier
+            .get(&course_key)
+            .map(|v| v.iter().cloned().filter(|i| allowed(*i)).collect())
+            .unwrap_or_default();
+
+        let (best, method) = match matching_identifiers.len() {
+            0 => {
+                let best = catalog
+                    .fuzzy
+                    .best(&course_title, |i| allowed(catalog.entries[i]))
+                    .map(|(i, score)| (catalog.entries[i], score));
+
+                (best, MatchMethod::Fuzzy)
+            }
+            1 => {
+                return Some(DescriptionMatch::new(
+                    matching_identifiers[0],
+                    MatchMethod::Identifier,
+                    1.0,
+                ))
+            }
+            _ => {
+                let fuzzy = FuzzyTitleIndex::new(
+                    matching_identifiers
+                        .iter()
+                        .map(|i| descriptions[*i].title.as_str()),
+                );
+
+                let best = fuzzy
+                    .best(&course_title, |_| true)
+                    .map(|(i, score)| (matching_identifiers[i], score));
+
+                (best, MatchMethod::Identifier)
+            }
+        };
+
+        // The best title may belong to several descriptions, take the first
+        best.map(|(i, score)| {
+            let first = catalog
+                .by_title
+                .get(descriptions[i].title.as_str())
+                .cloned()
+                .filter(|first| allowed(*first))
+                .unwrap_or(i);
+
+            DescriptionMatch::new(first, method, score)
+        })
+    }
+
+    /// The best description for `course` and how it was picked, for
+    /// reviewing matches
+    pub fn report(&mut self, course: &Course) -> Option<MatchReport> {
+        let found = self.candidate(course)?;
+        let description = &self.descriptions[found.index];
+
+        Some(MatchReport {
+            course: course.get_identifier().clone(),
+            course_title: course.get_title().clone(),
+            description: description.identifier.clone(),
+            description_title: description.title.clone(),
+            method: found.method,
+            score: found.score,
+            accepted: found.accepted(self.min_score),
+        })
+    }
+
+    /// First description with this exact title and identifier
+    pub fn position(&self, title: &str, identifier: &str) -> Option<usize> {
+        self.by_title
+            .get(title)?
+            .iter()
+            .find(|i| self.descriptions[**i].identifier == identifier)
+            .cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn course(identifier: &str, title: &str, school: &str) -> Course {
+        serde_json::from_value(serde_json::json!({
+            "identifier": identifier,
+            "id": "",
+            "code": "",
+            "dept": "",
+            "section": "",
+            "title": title,
+            "max_seats": 0,
+            "seats_taken": 0,
+            "seats_remaining": 0,
+            "credits": 100,
+            "credits_hmc": 300,
+            "status": "Open",
+            "timing": [{
+                "days": ["NA"],
+                "start_time": "00:00:00",
+                "end_time": "00:00:00",
+                "location": { "school": school, "building": "", "room": "" }
+            }],
+            "instructors": [],
+            "notes": "",
+            "description": "",
+            "prerequisites": "",
+            "corequisites": "",
+            "offered": "",
+            "perm_count": 0,
+            "fee": 0,
+            "associations": []
+        }))
+        .unwrap()
+    }
+
+    fn description(identifier: &str, title: &str, source: School) -> CourseDescription {
+        CourseDescription::new(
+            title.to_string(),
+            identifier.to_string(),
+            String::new(),
+            source,
+            100,
+            Vec::new(),
+            String::new(),
+            String::new(),
+            String::new(),
+            false,
+        )
+    }
+
+    fn catalog() -> Vec<CourseDescription> {
+        vec![
+            description("MATH-060-HM", "Linear Algebra", School::HarveyMudd),
+            description("MATH-003-HM", "Calculus", School::HarveyMudd),
+            description("ECON-167-PO", "Econometrics", School::Pomona),
+            description("ANTH-012-PZ", "Native Americans and Their Environments", School::Pitzer),
+            description("WRIT-001-HM", "writing and research", School::HarveyMudd),
+            description("CORE-001-NA", "Critical Inquiry", School::NA),
+        ]
+    }
+
+    fn rules(overrides: Vec<DescriptionOverride>) -> MatchRules {
+        MatchRules::new(overrides, 0.6)
+    }
+
+    fn rule(course: &str, description: &str, action: OverrideAction) -> DescriptionOverride {
+        DescriptionOverride {
+            course: course.to_string(),
+            description: description.to_string(),
+            title: None,
+            action,
+            note: String::new(),
+        }
+    }
+
+    #[test]
+    fn catalog_keys_drop_the_section() {
+        assert_eq!(catalog_key("MATH-060-HM-02"), catalog_key("MATH-060-HM"));
+        assert_ne!(catalog_key("MATH-030-HM"), catalog_key("MATH-003-HM"));
+    }
+
+    #[test]
+    fn substring_index_finds_what_contains() {
+        let mut index = SubstringIndex::default();
+        index.push("MATH-030-HM");
+        index.push("MATH-003-HM");
+        index.push("ECON-167-PO");
+
+        assert_eq!(index.containing("MATH-0"), vec![0, 1]);
+        assert_eq!(index.containing("030"), vec![0]);
+        assert_eq!(index.containing("HM-"), Vec::<usize>::new());
+        assert_eq!(index.containing(""), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn fuzzy_index_prefers_the_earliest_on_ties() {
+        let index = FuzzyTitleIndex::new(["abc", "xyz", "abc"].iter().copied());
+
+        assert_eq!(index.best("abc", |_| true), Some((0, 1.0)));
+        assert_eq!(index.best("abc", |i| i != 0), Some((2, 1.0)));
+        assert_eq!(index.best("abc", |_| false), None);
+    }
+
+    #[test]
+    fn exact_titles_first() {
+        let descriptions = catalog();
+        let rules = rules(Vec::new());
+        let mut index = DescriptionIndex::new(&descriptions, &rules);
+
+        let found = index.find(&course("MATH-999-HM-01", "LINEAR ALGEBRA", "HarveyMudd")).unwrap();
+        assert_eq!((found.index, found.method, found.score), (0, MatchMethod::ExactTitle, 1.0));
+
+        // "&" is read as "and"
+        let found = index.find(&course("WRIT-002-HM-01", "Writing & Research", "HarveyMudd")).unwrap();
+        assert_eq!((found.index, found.method), (4, MatchMethod::ExactTitle));
+
+        // Titles after " - " are what's compared
+        let found = index.find(&course("MATH-003-HM-01", "MATH 003 - Calculus", "HarveyMudd")).unwrap();
+        assert_eq!((found.index, found.method), (1, MatchMethod::ExactTitle));
+    }
+
+    #[test]
+    fn then_the_same_course() {
+        let descriptions = catalog();
+        let rules = rules(Vec::new());
+        let mut index = DescriptionIndex::new(&descriptions, &rules);
+
+        let found = index.find(&course("MATH-060-HM-02", "Matrices", "HarveyMudd")).unwrap();
+        assert_eq!((found.index, found.method, found.score), (0, MatchMethod::Identifier, 1.0));
+    }
+
+    #[test]
+    fn identifiers_match_whole_numbers() {
+        let descriptions = catalog();
+        let rules = rules(Vec::new());
+        let mut index = DescriptionIndex::new(&descriptions, &rules);
+
+        // MATH-030 isn't MATH-003, so this falls through to a fuzzy match
+        let found = index.candidate(&course("MATH-030-HM-01", "Probability", "HarveyMudd")).unwrap();
+        assert_eq!(found.method, MatchMethod::Fuzzy);
+        assert!(!found.accepted(0.6));
+    }
+
+    #[test]
+    fn fuzzy_matches_need_the_minimum_score() {
+        let descriptions = catalog();
+        let rules = rules(Vec::new());
+        let mut index = DescriptionIndex::new(&descriptions, &rules);
+
+        let close = course("WRIT-005-HM-01", "writing and research ii", "HarveyMudd");
+        let found = index.find(&close).unwrap();
+        assert_eq!((found.index, found.method), (4, MatchMethod::Fuzzy));
+        assert!(found.score >= 0.6 && found.score < 1.0);
+
+        let far = course("PHYS-051-HM-01", "Electromagnetic Theory", "HarveyMudd");
+        assert_eq!(index.find(&far), None);
+
+        let report = index.report(&far).unwrap();
+        assert_eq!(report.method, MatchMethod::Fuzzy);
+        assert!(!report.accepted);
+    }
+
+    #[test]
+    fn only_the_courses_own_college_or_none() {
+        let descriptions = catalog();
+        let rules = rules(Vec::new());
+        let mut index = DescriptionIndex::new(&descriptions, &rules);
+
+        // Econometrics is in Pomona's catalog, not HMC's
+        let found = index.candidate(&course("ECON-101-HM-01", "Econometrics", "HarveyMudd")).unwrap();
+        assert_ne!(found.index, 2);
+
+        // Descriptions without a college match anywhere
+        let found = index.find(&course("CORE-001-PO-01", "Critical Inquiry", "Pomona")).unwrap();
+        assert_eq!((found.index, found.method), (5, MatchMethod::ExactTitle));
+    }
+
+    #[test]
+    fn pins_and_blocks() {
+        let descriptions = catalog();
+        let rules = rules(vec![
+            rule("PHYS-051-HM", "MATH-060-HM", OverrideAction::Pin),
+            rule("MATH-060-HM", "MATH-060-HM", OverrideAction::Block),
+        ]);
+        let mut index = DescriptionIndex::new(&descriptions, &rules);
+
+        let found = index.find(&course("PHYS-051-HM-01", "Electromagnetic Theory", "HarveyMudd")).unwrap();
+        assert_eq!((found.index, found.method), (0, MatchMethod::Override));
+
+        let found = index.candidate(&course("MATH-060-HM-01", "Linear Algebra", "HarveyMudd")).unwrap();
+        assert_ne!(found.index, 0);
+    }
+
+    #[test]
+    fn finds_positions_by_title_and_identifier() {
+        let descriptions = catalog();
+        let rules = rules(Vec::new());
+        let index = DescriptionIndex::new(&descriptions, &rules);
+
+        assert_eq!(index.position("Calculus", "MATH-003-HM"), Some(1));
+        assert_eq!(index.position("Calculus", "MATH-030-HM"), None);
+    }
+}
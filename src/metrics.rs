@@ -0,0 +1,272 @@
+// ----------------------------------------------------------------------------
+// Prometheus metrics, rendered by hand in the text exposition format.
+//
+// Request metrics are recorded by a middleware in main.rs into
+// REQUEST_METRICS, which has its own lock so requests never wait on
+// MEMORY_DATABASE. Update loop metrics are recorded by update_loop itself,
+// and everything else (cache sizes, source health) is read from the current
+// snapshot and MemDatabase when /metrics is scraped.
+// ----------------------------------------------------------------------------
+
+use crate::health::SourceHealth;
+use crate::snapshot::current_snapshot;
+use crate::{MemDatabase, MEMORY_DATABASE};
+use lazy_static::lazy_static;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// Upper bounds (seconds) for request latency buckets
+const REQUEST_BUCKETS: &[f64] = &[0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+#[derive(Clone, Debug)]
+pub struct Histogram {
+    bucket_counts: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    pub fn new() -> Self {
+        Self {
+            bucket_counts: vec![0; REQUEST_BUCKETS.len()],
+            sum: 0.0,
+            count: 0,
+        }
+    }
+
+    pub fn observe(&mut self, value: f64) {
+        for (i, bound) in REQUEST_BUCKETS.iter().enumerate() {
+            if value <= *bound {
+                self.bucket_counts[i] += 1;
+            }
+        }
+
+        self.sum += value;
+        self.count += 1;
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct StageMetrics {
+    pub last_duration: Duration,
+    pub successes: u64,
+    pub failures: u64,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct RequestMetrics {
+    // (method, route, status) -> count
+    pub requests: HashMap<(String, String, u16), u64>,
+    // (method, route) -> latency
+    pub latencies: HashMap<(String, String), Histogram>,
+}
+
+impl RequestMetrics {
+    pub fn record(&mut self, method: &str, route: &str, status: u16, elapsed: Duration) {
+        *self
+            .requests
+            .entry((method.to_string(), route.to_string(), status))
+            .or_insert(0) += 1;
+
+        self.latencies
+            .entry((method.to_string(), route.to_string()))
+            .or_insert_with(Histogram::new)
+            .observe(elapsed.as_secs_f64());
+    }
+}
+
+lazy_static! {
+    // Only held to record or copy, never across an await
+    pub static ref REQUEST_METRICS: Mutex<RequestMetrics> = Mutex::new(RequestMetrics::default());
+}
+
+#[derive(Clone, Debug)]
+pub struct Metrics {
+    pub update_stages: HashMap<String, StageMetrics>,
+    pub description_matches: u64,
+    pub description_courses: u64,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self {
+            update_stages: HashMap::new(),
+            description_matches: 0,
+            description_courses: 0,
+        }
+    }
+
+    pub fn record_stage(&mut self, stage: &str, elapsed: Duration, success: bool) {
+        let stage = self
+            .update_stages
+            .entry(stage.to_string())
+            .or_insert(StageMetrics {
+                last_duration: Duration::from_secs(0),
+                successes: 0,
+                failures: 0,
+            });
+
+        stage.last_duration = elapsed;
+
+        if success {
+            stage.successes += 1;
+        } else {
+            stage.failures += 1;
+        }
+    }
+
+    pub fn set_description_matches(&mut self, matched: usize, total: usize) {
+        self.description_matches = matched as u64;
+        self.description_courses = total as u64;
+    }
+}
+
+pub async fn record_update_stage(stage: &str, started: Instant, success: bool) {
+    let mut lock = MEMORY_DATABASE.lock().await;
+    lock.metrics.record_stage(stage, started.elapsed(), success);
+}
+
+pub fn record_request(method: &str, route: &str, status: u16, elapsed: Duration) {
+    // A panic while recording only loses that request's metrics
+    let mut metrics = match REQUEST_METRICS.lock() {
+        Ok(metrics) => metrics,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+
+    metrics.record(method, route, status, elapsed);
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn write_header(out: &mut String, name: &str, help: &str, kind: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+fn write_source_metrics(out: &mut String, health: &HashMap<String, SourceHealth>) {
+    let mut sources: Vec<(&String, &SourceHealth)> = health.iter().collect();
+    sources.sort_by(|a, b| a.0.cmp(b.0));
+
+    write_header(out, "fivec_source_up", "Whether the last fetch from a source succeeded", "gauge");
+    for (source, h) in &sources {
+        let _ = writeln!(out, "fivec_source_up{{source=\"{}\"}} {}", escape_label(source), h.is_healthy() as u8);
+    }
+
+    write_header(out, "fivec_source_consecutive_failures", "Failed fetches from a source since its last success", "gauge");
+    for (source, h) in &sources {
+        let _ = writeln!(out, "fivec_source_consecutive_failures{{source=\"{}\"}} {}", escape_label(source), h.consecutive_failures);
+    }
+
+    write_header(out, "fivec_source_duration_seconds", "Duration of the last fetch from a source", "gauge");
+    for (source, h) in &sources {
+        let _ = writeln!(out, "fivec_source_duration_seconds{{source=\"{}\"}} {}", escape_label(source), h.duration_ms as f64 / 1000.0);
+    }
+
+    write_header(out, "fivec_source_items", "Items returned by the last successful fetch from a source", "gauge");
+    for (source, h) in &sources {
+        let _ = writeln!(out, "fivec_source_items{{source=\"{}\"}} {}", escape_label(source), h.item_count);
+    }
+
+    write_header(out, "fivec_source_last_success_timestamp_seconds", "Unix time of the last successful fetch from a source", "gauge");
+    for (source, h) in &sources {
+        if let Some(last_success) = h.last_success {
+            let _ = writeln!(out, "fivec_source_last_success_timestamp_seconds{{source=\"{}\"}} {}", escape_label(source), last_success);
+        }
+    }
+}
+
+/// Renders every metric in the Prometheus text format
+pub fn render_metrics(db: &MemDatabase) -> String {
+    let mut out = String::new();
+    let metrics = &db.metrics;
+    let snapshot = current_snapshot();
+
+    let request_metrics = match REQUEST_METRICS.lock() {
+        Ok(metrics) => metrics.clone(),
+        Err(poisoned) => poisoned.into_inner().clone(),
+    };
+
+    // Requests
+    let mut requests: Vec<(&(String, String, u16), &u64)> = request_metrics.requests.iter().collect();
+    requests.sort();
+
+    write_header(&mut out, "fivec_http_requests_total", "HTTP requests served", "counter");
+    for ((method, route, status), count) in requests {
+        let _ = writeln!(
+            out,
+            "fivec_http_requests_total{{method=\"{}\",route=\"{}\",status=\"{}\"}} {}",
+            method,
+            escape_label(route),
+            status,
+            count
+        );
+    }
+
+    let mut latencies: Vec<(&(String, String), &Histogram)> = request_metrics.latencies.iter().collect();
+    latencies.sort_by(|a, b| a.0.cmp(b.0));
+
+    write_header(&mut out, "fivec_http_request_duration_seconds", "HTTP request latency", "histogram");
+    for ((method, route), histogram) in latencies {
+        let labels = format!("method=\"{}\",route=\"{}\"", method, escape_label(route));
+
+        for (bound, count) in REQUEST_BUCKETS.iter().zip(histogram.bucket_counts.iter()) {
+            let _ = writeln!(out, "fivec_http_request_duration_seconds_bucket{{{},le=\"{}\"}} {}", labels, bound, count);
+        }
+
+        let _ = writeln!(out, "fivec_http_request_duration_seconds_bucket{{{},le=\"+Inf\"}} {}", labels, histogram.count);
+        let _ = writeln!(out, "fivec_http_request_duration_seconds_sum{{{}}} {}", labels, histogram.sum);
+        let _ = writeln!(out, "fivec_http_request_duration_seconds_count{{{}}} {}", labels, histogram.count);
+    }
+
+    // Update loop
+    let mut stages: Vec<(&String, &StageMetrics)> = metrics.update_stages.iter().collect();
+    stages.sort_by(|a, b| a.0.cmp(b.0));
+
+    write_header(&mut out, "fivec_update_stage_duration_seconds", "Duration of the last run of an update loop stage", "gauge");
+    for (stage, m) in &stages {
+        let _ = writeln!(out, "fivec_update_stage_duration_seconds{{stage=\"{}\"}} {}", stage, m.last_duration.as_secs_f64());
+    }
+
+    write_header(&mut out, "fivec_update_stage_runs_total", "Update loop stage runs by outcome", "counter");
+    for (stage, m) in &stages {
+        let _ = writeln!(out, "fivec_update_stage_runs_total{{stage=\"{}\",outcome=\"success\"}} {}", stage, m.successes);
+        let _ = writeln!(out, "fivec_update_stage_runs_total{{stage=\"{}\",outcome=\"failure\"}} {}", stage, m.failures);
+    }
+
+    write_header(&mut out, "fivec_last_change_timestamp_seconds", "Unix time the course cache last changed", "gauge");
+    let _ = writeln!(out, "fivec_last_change_timestamp_seconds {}", snapshot.last_change);
+
+    write_source_metrics(&mut out, &db.health_cache);
+
+    // Caches
+    let menu_count: usize = snapshot.menus.values().map(|m| m.get_cafes().len()).sum();
+
+    write_header(&mut out, "fivec_cache_entries", "Entries held in each in-memory cache", "gauge");
+    let _ = writeln!(out, "fivec_cache_entries{{cache=\"courses\"}} {}", snapshot.courses.len());
+    let _ = writeln!(out, "fivec_cache_entries{{cache=\"descriptions\"}} {}", snapshot.descriptions.len());
+    let _ = writeln!(out, "fivec_cache_entries{{cache=\"menus\"}} {}", menu_count);
+    let _ = writeln!(out, "fivec_cache_entries{{cache=\"areas\"}} {}", snapshot.areas.len());
+    let _ = writeln!(out, "fivec_cache_entries{{cache=\"locations\"}} {}", snapshot.locations.len());
+    let _ = writeln!(out, "fivec_cache_entries{{cache=\"share_codes\"}} {}", db.code_cache.len());
+
+    // Description matching
+    write_header(&mut out, "fivec_description_matched_courses", "Courses given a catalog description on the last merge", "gauge");
+    let _ = writeln!(out, "fivec_description_matched_courses {}", metrics.description_matches);
+
+    write_header(&mut out, "fivec_description_match_ratio", "Fraction of courses given a catalog description on the last merge", "gauge");
+    let ratio = if metrics.description_courses == 0 {
+        0.0
+    } else {
+        metrics.description_matches as f64 / metrics.description_courses as f64
+    };
+    let _ = writeln!(out, "fivec_description_match_ratio {}", ratio);
+
+    out
+}
@@ -0,0 +1,450 @@
+// ----------------------------------------------------------------------------
+// When courses are offered, parsed from the catalogs' "Offered" text.
+//
+// The catalogs write it freehand: "Each semester.", "Spring, alternate
+// years", "Offered alternate years; next offered fall 2022.", "Last offered
+// spring 2019.", "not offered 2025-26". OfferedSchedule keeps what can be
+// read from that (how often, which seasons, which semesters are named) so
+// the planner can ask whether a course is likely to run in a future term.
+// ----------------------------------------------------------------------------
+
+use ::serde::{Deserialize, Serialize};
+use lazy_static::lazy_static;
+use regex::Regex;
+
+lazy_static! {
+    // "fall 2022"
+    static ref SEMESTER: Regex = Regex::new(r"\b(fall|spring|summer)\s*(\d{4})\b").unwrap();
+    // "fa20", "sp 21"
+    static ref SEMESTER_CODE: Regex = Regex::new(r"\b(fa|sp|su)\s?(\d{2})\b").unwrap();
+    // "2022-23", "2022-2023"
+    static ref ACADEMIC_YEAR: Regex = Regex::new(r"\b(\d{4})\s*-\s*(\d{2}|\d{4})\b").unwrap();
+    static ref SEASON: Regex = Regex::new(r"\b(fall|spring|summer)\b").unwrap();
+}
+
+// Phrases for each frequency, checked in this order
+const ONCE: &[&str] = &["one-time", "one time"];
+const OCCASIONALLY: &[&str] = &[
+    "every 1-2 years",
+    "every 2-3 years",
+    "every 3-4 years",
+    "every few years",
+    "every three semesters",
+    "occasional",
+    "as announced",
+];
+const EVERY_THIRD_YEAR: &[&str] = &["every third year", "every three years"];
+const ALTERNATE_YEARS: &[&str] = &[
+    "alternate year",
+    "alternating year",
+    "alternate fall",
+    "alternate spring",
+    "every other year",
+    "every other fall",
+    "every other spring",
+    "every two years",
+    "every 2 years",
+];
+const EVERY_YEAR: &[&str] = &[
+    "every other semester",
+    "every year",
+    "each year",
+    "annually",
+    "yearly",
+    "every fall",
+    "each fall",
+    "every spring",
+    "each spring",
+    "every summer",
+];
+const EVERY_SEMESTER: &[&str] = &[
+    "each semester",
+    "every semester",
+    "both semesters",
+    "most semesters",
+    "fall and spring",
+    "spring and fall",
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Season {
+    Spring,
+    Summer,
+    Fall,
+}
+
+impl Season {
+    fn from_word(word: &str) -> Option<Season> {
+        match word {
+            "fall" | "fa" | "f" => Some(Season::Fall),
+            "spring" | "sp" | "s" => Some(Season::Spring),
+            "summer" | "su" => Some(Season::Summer),
+            _ => None,
+        }
+    }
+}
+
+/// A semester like fall 2025. Ordered by date.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Semester {
+    pub year: i32,
+    pub season: Season,
+}
+
+impl Semester {
+    pub fn new(season: Season, year: i32) -> Self {
+        Self { year, season }
+    }
+
+    /// Parses a semester from a query string: "fall-2025", "Fall 2025",
+    /// "FA25" or "fa2025"
+    pub fn from_query(s: &str) -> Option<Semester> {
+        let s = s.trim().to_lowercase();
+        let split = s.find(|c: char| c.is_ascii_digit())?;
+        let (season, year) = s.split_at(split);
+
+        let season = Season::from_word(season.trim_end_matches(['-', ' ', '_']))?;
+
+        let year = match year.len() {
+            2 => 2000 + year.parse::<i32>().ok()?,
+            4 => year.parse::<i32>().ok()?,
+            _ => return None,
+        };
+
+        Some(Semester::new(season, year))
+    }
+
+    /// The academic year this semester is part of, by the year it starts:
+    /// 2025 for fall 2025 and spring 2026
+    pub fn academic_year(&self) -> i32 {
+        match self.season {
+            Season::Fall => self.year,
+            Season::Spring | Season::Summer => self.year - 1,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Frequency {
+    EverySemester,
+    EveryYear,
+    AlternateYears,
+    EveryThirdYear,
+    // Irregularly, or "every 2-3 years"
+    Occasionally,
+    Once,
+    // The catalog doesn't say
+    Unknown,
+}
+
+impl Default for Frequency {
+    fn default() -> Self {
+        Frequency::Unknown
+    }
+}
+
+impl Frequency {
+    /// Years between offerings, for the ones on a fixed cycle
+    fn cycle(&self) -> Option<i32> {
+        match self {
+            Frequency::AlternateYears => Some(2),
+            Frequency::EveryThirdYear => Some(3),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Likelihood {
+    Likely,
+    // The catalog doesn't say enough either way
+    Possible,
+    Unlikely,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct OfferedSchedule {
+    pub frequency: Frequency,
+    // Empty if the catalog doesn't say
+    pub seasons: Vec<Season>,
+    // Semesters named as (next) offered, oldest first
+    pub semesters: Vec<Semester>,
+    // Academic years named as offered, by the year they start: 2022 for
+    // "next offered 2022-23"
+    pub years: Vec<i32>,
+    pub last_offered: Option<Semester>,
+    // Academic years named as not offered
+    pub not_offered: Vec<i32>,
+}
+
+fn push_unique<T: PartialEq>(list: &mut Vec<T>, value: T) {
+    if !list.contains(&value) {
+        list.push(value);
+    }
+}
+
+fn semesters_in(clause: &str) -> Vec<Semester> {
+    let full = SEMESTER.captures_iter(clause).filter_map(|c| {
+        Some(Semester::new(Season::from_word(&c[1])?, c[2].parse().ok()?))
+    });
+
+    let codes = SEMESTER_CODE.captures_iter(clause).filter_map(|c| {
+        Some(Semester::new(Season::from_word(&c[1])?, 2000 + c[2].parse::<i32>().ok()?))
+    });
+
+    full.chain(codes).collect()
+}
+
+fn academic_years_in(clause: &str) -> Vec<i32> {
+    ACADEMIC_YEAR
+        .captures_iter(clause)
+        .filter_map(|c| c[1].parse().ok())
+        .collect()
+}
+
+/// Reads an "Offered" text from a catalog
+pub fn parse_offered(text: &str) -> OfferedSchedule {
+    let text = text.to_lowercase().replace("offfered", "offered");
+    let mut schedule = OfferedSchedule::default();
+
+    for clause in text.split([';', '.']) {
+        let semesters = semesters_in(clause);
+
+        if clause.contains("last offered") {
+            schedule.last_offered = semesters.into_iter().max().or(schedule.last_offered);
+        } else if clause.contains("not offered") {
+            for year in academic_years_in(clause) {
+                push_unique(&mut schedule.not_offered, year);
+            }
+
+            for semester in semesters {
+                push_unique(&mut schedule.not_offered, semester.academic_year());
+            }
+        } else {
+            for semester in semesters {
+                push_unique(&mut schedule.semesters, semester);
+            }
+
+            for year in academic_years_in(clause) {
+                push_unique(&mut schedule.years, year);
+            }
+
+            for season in SEASON.captures_iter(clause) {
+                if let Some(season) = Season::from_word(&season[1]) {
+                    push_unique(&mut schedule.seasons, season);
+                }
+            }
+        }
+    }
+
+    let says = |phrases: &[&str]| phrases.iter().any(|phrase| text.contains(phrase));
+
+    schedule.frequency = if says(ONCE) {
+        Frequency::Once
+    } else if says(OCCASIONALLY) {
+        Frequency::Occasionally
+    } else if says(EVERY_THIRD_YEAR) {
+        Frequency::EveryThirdYear
+    } else if says(ALTERNATE_YEARS) {
+        Frequency::AlternateYears
+    } else if says(EVERY_YEAR) {
+        Frequency::EveryYear
+    } else if says(EVERY_SEMESTER) {
+        Frequency::EverySemester
+    } else if !schedule.seasons.is_empty() && schedule.semesters.is_empty() {
+        // Just "Spring": every spring
+        Frequency::EveryYear
+    } else {
+        Frequency::Unknown
+    };
+
+    match (schedule.frequency, schedule.last_offered) {
+        // "Each semester. Fall 2022." still means spring too
+        (Frequency::EverySemester, _) => {
+            push_unique(&mut schedule.seasons, Season::Fall);
+            push_unique(&mut schedule.seasons, Season::Spring);
+        }
+        // "Offered alternate years; last offered spring 2020."
+        (frequency, Some(last)) if frequency.cycle().is_some() && schedule.seasons.is_empty() => {
+            schedule.seasons.push(last.season);
+        }
+        _ => {}
+    }
+
+    schedule.seasons.sort_unstable();
+    schedule.semesters.sort_unstable();
+    schedule.years.sort_unstable();
+    schedule.not_offered.sort_unstable();
+
+    schedule
+}
+
+impl OfferedSchedule {
+    /// Whether the course is likely to be offered in `semester`
+    pub fn offered_in(&self, semester: &Semester) -> Likelihood {
+        if self.not_offered.contains(&semester.academic_year()) {
+            return Likelihood::Unlikely;
+        }
+
+        if self.semesters.contains(semester) {
+            return Likelihood::Likely;
+        }
+
+        if self.frequency == Frequency::Once {
+            return Likelihood::Unlikely;
+        }
+
+        let in_season = if self.seasons.is_empty() {
+            semester.season != Season::Summer || self.frequency == Frequency::Unknown
+        } else {
+            self.seasons.contains(&semester.season)
+        };
+
+        if !in_season {
+            return Likelihood::Unlikely;
+        }
+
+        if let Some(cycle) = self.frequency.cycle() {
+            // The most recent year we know it ran (or will run) in
+            let anchor = self
+                .semesters
+                .iter()
+                .map(|s| s.academic_year())
+                .chain(self.years.iter().cloned())
+                .max()
+                .or_else(|| self.last_offered.map(|s| s.academic_year()));
+
+            return match anchor {
+                Some(anchor) if (semester.academic_year() - anchor).rem_euclid(cycle) == 0 => {
+                    Likelihood::Likely
+                }
+                Some(_) => Likelihood::Unlikely,
+                None => Likelihood::Possible,
+            };
+        }
+
+        match self.frequency {
+            Frequency::EverySemester | Frequency::EveryYear => Likelihood::Likely,
+            // Only "Last offered spring 2019."
+            Frequency::Unknown if self.last_offered.is_some() && self.semesters.is_empty() => {
+                Likelihood::Unlikely
+            }
+            _ => Likelihood::Possible,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fall(year: i32) -> Semester {
+        Semester::new(Season::Fall, year)
+    }
+
+    fn spring(year: i32) -> Semester {
+        Semester::new(Season::Spring, year)
+    }
+
+    #[test]
+    fn reads_semester_queries() {
+        for query in ["fall-2026", "Fall 2026", "FA26", "fa2026"] {
+            assert_eq!(Semester::from_query(query), Some(fall(2026)));
+        }
+
+        assert_eq!(Semester::from_query("winter 2026"), None);
+        assert_eq!(Semester::from_query("fall 226"), None);
+    }
+
+    #[test]
+    fn academic_year_starts_in_fall() {
+        assert_eq!(fall(2025).academic_year(), 2025);
+        assert_eq!(spring(2026).academic_year(), 2025);
+    }
+
+    #[test]
+    fn every_semester() {
+        let schedule = parse_offered("Each semester.");
+
+        assert_eq!(schedule.frequency, Frequency::EverySemester);
+        assert_eq!(schedule.seasons, vec![Season::Spring, Season::Fall]);
+    }
+
+    #[test]
+    fn a_season_alone_is_every_year() {
+        let schedule = parse_offered("Spring");
+
+        assert_eq!(schedule.frequency, Frequency::EveryYear);
+        assert_eq!(schedule.seasons, vec![Season::Spring]);
+    }
+
+    #[test]
+    fn alternate_years_from_the_next_offering() {
+        let schedule = parse_offered("Offered alternate years; next offered fall 2022.");
+
+        assert_eq!(schedule.frequency, Frequency::AlternateYears);
+        assert_eq!(schedule.seasons, vec![Season::Fall]);
+        assert_eq!(schedule.semesters, vec![fall(2022)]);
+
+        assert_eq!(schedule.offered_in(&fall(2024)), Likelihood::Likely);
+        assert_eq!(schedule.offered_in(&fall(2023)), Likelihood::Unlikely);
+        assert_eq!(schedule.offered_in(&spring(2025)), Likelihood::Unlikely);
+    }
+
+    #[test]
+    fn alternate_years_from_the_last_offering() {
+        let schedule = parse_offered("Offered alternate years; last offered spring 2020.");
+
+        assert_eq!(schedule.seasons, vec![Season::Spring]);
+        assert_eq!(schedule.last_offered, Some(spring(2020)));
+        assert_eq!(schedule.offered_in(&spring(2022)), Likelihood::Likely);
+        assert_eq!(schedule.offered_in(&spring(2023)), Likelihood::Unlikely);
+    }
+
+    #[test]
+    fn academic_years_offered_and_not() {
+        let schedule = parse_offered("Every other year; next offered 2022-23. Not offered spring 2024.");
+
+        assert_eq!(schedule.frequency, Frequency::AlternateYears);
+        assert_eq!(schedule.years, vec![2022]);
+        assert_eq!(schedule.not_offered, vec![2023]);
+
+        let schedule = parse_offered("Each spring; not offered 2025-26.");
+
+        assert_eq!(schedule.frequency, Frequency::EveryYear);
+        assert_eq!(schedule.not_offered, vec![2025]);
+        assert_eq!(schedule.offered_in(&spring(2026)), Likelihood::Unlikely);
+        assert_eq!(schedule.offered_in(&spring(2027)), Likelihood::Likely);
+        assert_eq!(schedule.offered_in(&fall(2026)), Likelihood::Unlikely);
+    }
+
+    #[test]
+    fn last_offered_only() {
+        let schedule = parse_offered("Last offfered spring 2019.");
+
+        assert_eq!(schedule.frequency, Frequency::Unknown);
+        assert_eq!(schedule.last_offered, Some(spring(2019)));
+        assert_eq!(schedule.offered_in(&spring(2027)), Likelihood::Unlikely);
+    }
+
+    #[test]
+    fn one_time_offerings() {
+        let schedule = parse_offered("One-time only FA20");
+
+        assert_eq!(schedule.frequency, Frequency::Once);
+        assert_eq!(schedule.semesters, vec![fall(2020)]);
+        assert_eq!(schedule.offered_in(&fall(2020)), Likelihood::Likely);
+        assert_eq!(schedule.offered_in(&fall(2026)), Likelihood::Unlikely);
+    }
+
+    #[test]
+    fn irregular_and_unknown() {
+        assert_eq!(parse_offered("Every 2-3 years").frequency, Frequency::Occasionally);
+        assert_eq!(parse_offered("Every third year").frequency, Frequency::EveryThirdYear);
+
+        let schedule = parse_offered("");
+
+        assert_eq!(schedule, OfferedSchedule::default());
+        assert_eq!(schedule.offered_in(&fall(2026)), Likelihood::Possible);
+    }
+}
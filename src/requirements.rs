@@ -0,0 +1,103 @@
+// ----------------------------------------------------------------------------
+// Graduation requirements, loaded from requirements.json.
+//
+// The Pomona API tags courses with area codes, kept as their associations.
+// Some codes are departments ("MATH") and some are requirements at one of
+// the colleges ("1A1" is Pomona's Area 1, "4HSA" is HMC's HSA), whichever
+// college teaches the course. requirements.json lists the requirements and
+// the codes that fulfill them, and each course's `fulfills` holds the IDs
+// of the ones it counts for.
+// ----------------------------------------------------------------------------
+
+use crate::config::CONFIG;
+use crate::course_api::School;
+use ::serde::{Deserialize, Serialize};
+use lazy_static::lazy_static;
+use log::error;
+use std::collections::HashMap;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Requirement {
+    // "pomona-area-1"
+    pub id: String,
+    // The college whose requirement it is
+    pub school: School,
+    // "Breadth area", "General education", "HSA"...
+    pub category: String,
+    pub name: String,
+    // Area codes that fulfill it
+    pub areas: Vec<String>,
+}
+
+pub struct RequirementRegistry {
+    requirements: Vec<Requirement>,
+    by_area: HashMap<String, Vec<usize>>,
+}
+
+impl RequirementRegistry {
+    pub fn new(requirements: Vec<Requirement>) -> Self {
+        let mut by_area: HashMap<String, Vec<usize>> = HashMap::new();
+
+        for (i, requirement) in requirements.iter().enumerate() {
+            for area in &requirement.areas {
+                by_area.entry(area.clone()).or_default().push(i);
+            }
+        }
+
+        Self {
+            requirements,
+            by_area,
+        }
+    }
+
+    pub fn load(path: &str) -> Result<Self, String> {
+        let data = std::fs::read_to_string(path)
+            .map_err(|e| format!("Could not read {}: {}", path, e))?;
+
+        let requirements: Vec<Requirement> =
+            serde_json::from_str(&data).map_err(|e| format!("Could not parse {}: {}", path, e))?;
+
+        Ok(Self::new(requirements))
+    }
+
+    pub fn requirements(&self) -> &Vec<Requirement> {
+        &self.requirements
+    }
+
+    /// A college's requirements, in file order
+    pub fn for_school(&self, school: &School) -> impl Iterator<Item = &Requirement> {
+        let school = school.clone();
+
+        self.requirements.iter().filter(move |r| r.school == school)
+    }
+
+    /// IDs of the requirements a course with these area codes fulfills, in
+    /// file order
+    pub fn fulfilled_by(&self, areas: &[String]) -> Vec<String> {
+        let mut found: Vec<usize> = areas
+            .iter()
+            .filter_map(|area| self.by_area.get(area))
+            .flatten()
+            .cloned()
+            .collect();
+
+        found.sort_unstable();
+        found.dedup();
+
+        found
+            .into_iter()
+            .map(|i| self.requirements[i].id.clone())
+            .collect()
+    }
+}
+
+lazy_static! {
+    pub static ref REQUIREMENTS: RequirementRegistry =
+        match RequirementRegistry::load(&CONFIG.paths.requirements) {
+            Ok(registry) => registry,
+            Err(e) => {
+                error!("{}, courses will not be tagged with requirements", e);
+                RequirementRegistry::new(Vec::new())
+            }
+        };
+}
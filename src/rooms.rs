@@ -0,0 +1,146 @@
+// ----------------------------------------------------------------------------
+// Room occupancy, built from where and when every course meets.
+//
+// Only rooms that host at least one class are known, so "free" means no
+// class is scheduled there in the window, not that the room is unlocked.
+// ----------------------------------------------------------------------------
+
+use crate::course_api::*;
+use ::serde::{Deserialize, Serialize};
+use chrono::NaiveDate;
+use std::collections::BTreeMap;
+
+// Rooms that are really "no room yet" placeholders
+const PLACEHOLDER_ROOMS: &[&str] = &["TBA", "ARR"];
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RoomMeeting {
+    pub identifier: String,
+    pub title: String,
+    pub day: Day,
+    pub start_time: String,
+    pub end_time: String,
+    // Set when the class doesn't meet the whole term
+    pub start_date: Option<NaiveDate>,
+    pub end_date: Option<NaiveDate>,
+    #[serde(skip)]
+    start: u32,
+    #[serde(skip)]
+    end: u32,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Room {
+    pub school: School,
+    pub building: String,
+    pub room: String,
+    // Sorted by day, then start time
+    pub meetings: Vec<RoomMeeting>,
+}
+
+impl RoomMeeting {
+    fn runs_on(&self, date: NaiveDate) -> bool {
+        self.start_date.is_none_or(|start| start <= date) && self.end_date.is_none_or(|end| date <= end)
+    }
+}
+
+impl Room {
+    /// Whether any class meets here on `day` between minutes `from` and
+    /// `to`. Classes that don't meet the whole term only count if they're
+    /// running on `date`.
+    pub fn is_busy(&self, day: &Day, from: u32, to: u32, date: NaiveDate) -> bool {
+        self.meetings
+            .iter()
+            .any(|m| &m.day == day && m.start < to && m.end > from && m.runs_on(date))
+    }
+}
+
+fn is_placeholder(location: &Location, room: &str) -> bool {
+    let room = room.trim();
+
+    location.is_placeholder() || room.is_empty() || PLACEHOLDER_ROOMS.contains(&room)
+}
+
+fn room_key(building: &str, room: &str) -> (String, String) {
+    (building.trim().to_lowercase(), room.trim().to_lowercase())
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct RoomIndex {
+    rooms: BTreeMap<(String, String), Room>,
+}
+
+impl RoomIndex {
+    pub fn new(courses: &[Course]) -> Self {
+        let mut rooms: BTreeMap<(String, String), Room> = BTreeMap::new();
+
+        for course in courses {
+            for timing in course.get_timings() {
+                let location = timing.get_location();
+
+                // Meetings without a time can't occupy a room
+                if !timing.has_time() {
+                    continue;
+                }
+
+                for room_name in timing.get_rooms() {
+                    if is_placeholder(location, &room_name) {
+                        continue;
+                    }
+
+                    let room = rooms
+                        .entry(room_key(location.get_building(), &room_name))
+                        .or_insert_with(|| Room {
+                            school: location.get_school().clone(),
+                            building: location.get_building().trim().to_string(),
+                            room: room_name.trim().to_string(),
+                            meetings: Vec::new(),
+                        });
+
+                    let (start_date, end_date) = timing.get_date_range();
+
+                    for day in timing.get_days() {
+                        if day == Day::NA {
+                            continue;
+                        }
+
+                        room.meetings.push(RoomMeeting {
+                            identifier: course.get_identifier().clone(),
+                            title: course.get_title().clone(),
+                            day,
+                            start_time: timing.get_start_time_str(),
+                            end_time: timing.get_end_time_str(),
+                            start_date,
+                            end_date,
+                            start: timing.get_start_time_index(),
+                            end: timing.get_end_time_index(),
+                        });
+                    }
+                }
+            }
+        }
+
+        for room in rooms.values_mut() {
+            room.meetings.sort_by_key(|m| (m.day.to_index(), m.start, m.end));
+        }
+
+        Self { rooms }
+    }
+
+    pub fn get(&self, building: &str, room: &str) -> Option<&Room> {
+        self.rooms.get(&room_key(building, room))
+    }
+
+    /// Rooms (optionally in one building) with no class on `day` between
+    /// minutes `from` and `to` as of `date`, sorted by building then room
+    pub fn free(&self, building: Option<&str>, day: &Day, from: u32, to: u32, date: NaiveDate) -> Vec<&Room> {
+        let building = building.map(|b| b.trim().to_lowercase());
+
+        self.rooms
+            .iter()
+            .filter(|((b, _), _)| building.as_ref().map_or(true, |building| b == building))
+            .map(|(_, room)| room)
+            .filter(|room| !room.is_busy(day, from, to, date))
+            .collect()
+    }
+}
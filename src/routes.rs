@@ -2,68 +2,110 @@ use crate::*;
 use actix_web::*;
 use openssl::stack::Stack;
 use ::serde::{Deserialize, Serialize};
+use chrono::{Local, NaiveDate, NaiveTime, Timelike};
+
+// Results per catalog search, by default and at most
+const SEARCH_LIMIT: usize = 20;
+const MAX_SEARCH_LIMIT: usize = 100;
+// Similar courses returned by default
+const SIMILAR_LIMIT: usize = 10;
 
 #[derive(Debug, Serialize, Deserialize)]
-struct ReturnCourses {
-    timestamp: u64,
-    courses: Vec<Course>,
-    term: String,
+struct ReturnCourseList {
+    code: String,
+    courses: SharedCourseList,
+    // In the viewer's home-college units
+    credits: CreditTotal,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CourseListQuery {
+    home: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Status {
+    alive: bool,
+    seconds_since_last_connection: Option<u64>,
+    ten_minute_total: u64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TrafficQuery {
+    school: Option<String>,
+    building: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
+struct TrafficDay {
+    day: Day,
+    // Index is the minute of the day, so starting[600] is 10:00
+    starting: Vec<i64>,
+    ending: Vec<i64>,
+}
 
-struct ReturnCatalog {
+#[derive(Debug, Serialize, Deserialize)]
+struct Traffic {
     timestamp: u64,
-    catalog: Vec<CourseDescription>,
+    school: Option<School>,
+    building: Option<String>,
+    days: Vec<TrafficDay>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FreeRoomsQuery {
+    building: Option<String>,
+    day: String,
+    from: String,
+    to: String,
+    // "YYYY-MM-DD", today if not given
+    date: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
-struct ReturnCourseList {
-    code: String,
-    courses: SharedCourseList,
+struct FreeRoom {
+    school: School,
+    building: String,
+    room: String,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
-struct Status {
-    alive: bool,
-    seconds_since_last_connection: u64,
-    ten_minute_total: u64,
+struct FreeRooms {
+    timestamp: u64,
+    day: Day,
+    from: String,
+    to: String,
+    date: NaiveDate,
+    rooms: Vec<FreeRoom>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Health {
+    timestamp: u64,
+    healthy: bool,
+    sources: HashMap<String, SourceHealth>,
 }
 
 /// A simple cache for courses
 /// @returns all courses in the cache for all schools at current term
 #[get("/fullUpdate")]
-pub async fn update_all_courses(path: web::Path<()>) -> HttpResponse {
-    let lock = MEMORY_DATABASE.lock().await;
-
-    let courses = lock.course_cache.clone();
-    let last_change = lock.last_change.clone();
-    let term = lock.term.clone();
-
-    drop(lock);
-
-    HttpResponse::Ok().json(ReturnCourses{ timestamp:last_change, courses, term})
+pub async fn update_all_courses(req: HttpRequest, path: web::Path<()>) -> HttpResponse {
+    current_snapshot().courses_body.respond(&req)
 }
 
 /// Since the course cache updates only occasionally, this endpoint is used to
 /// update only if the local cache is out of date.
 #[get("/updateIfStale/{unix_timestamp_seconds}")]
-pub async fn update_if_stale(path: web::Path<u64>) -> HttpResponse {
+pub async fn update_if_stale(req: HttpRequest, path: web::Path<u64>) -> HttpResponse {
     let unix_timestamp_seconds = path.into_inner();
 
-    let mut lock = MEMORY_DATABASE.lock().await;
-    
-    lock.add_ten_log();
-
-    if &lock.last_change != &unix_timestamp_seconds {
-        info!("Serving course update!");
-        let courses = lock.course_cache.clone();
-        let last_change = lock.last_change.clone();
-        let term = lock.term.clone();
+    add_ten_log();
 
-        drop(lock);
+    let snapshot = current_snapshot();
 
-        HttpResponse::Ok().json(ReturnCourses{ timestamp:last_change, courses, term})
+    if snapshot.last_change != unix_timestamp_seconds {
+        info!("Serving course update!");
+        snapshot.courses_body.respond(&req)
     } else {
         info!("No course update needed!");
         HttpResponse::Ok().json("No update needed")
@@ -81,17 +123,9 @@ pub async fn get_unique_code(post: web::Json<Vec<Vec<Course>>>,) -> HttpResponse
 
     let shared_course_list = SharedCourseList::new(local_courses, custom_courses);
 
-    let lock = MEMORY_DATABASE.lock().await;
-
-    let code_cache = lock.code_cache.clone();
-
-    drop(lock);
-
-    let (code, updated_code_cache) = generate_unique_code(shared_course_list, code_cache);
-
     let mut lock = MEMORY_DATABASE.lock().await;
 
-    lock.code_cache = updated_code_cache;
+    let code = generate_unique_code(shared_course_list, &mut lock.code_cache);
 
     drop(lock);
 
@@ -99,74 +133,564 @@ pub async fn get_unique_code(post: web::Json<Vec<Vec<Course>>>,) -> HttpResponse
 }
 
 #[get("/getCourseListByCode/{code}")]
-pub async fn get_course_list_by_code(path: web::Path<String>) -> HttpResponse {
+pub async fn get_course_list_by_code(path: web::Path<String>, query: web::Query<CourseListQuery>) -> HttpResponse {
     let code = path.into_inner().to_uppercase();
+
+    // Without a home college, totals are in course credits
+    let home = match &query.home {
+        Some(home) => match School::from_query(home) {
+            Some(school) => school,
+            None => return HttpResponse::BadRequest().json("Invalid school"),
+        },
+        None => School::NA,
+    };
     
     let lock = MEMORY_DATABASE.lock().await;
 
-    let code_cache = lock.code_cache.clone();
+    let result = get_course_list(&code, &lock.code_cache);
 
     drop(lock);
 
-    let result = get_course_list(code.clone(), code_cache);
-
     match result {
-        Some(result) => HttpResponse::Ok().json(ReturnCourseList { code, courses: result }),
+        Some(result) => {
+            let credits = total_credits(result.courses(), &home);
+
+            HttpResponse::Ok().json(ReturnCourseList { code, courses: result, credits })
+        }
         None => HttpResponse::Ok().json("Invalid code"),
     }
 }
 
 #[get("/getLocations")]
-pub async fn get_locations_database(_path: web::Path<()>) -> HttpResponse {
-    let lock = MEMORY_DATABASE.lock().await;
-
-    let locations = lock.locations_cache.clone();
-
-    drop(lock);
-
-    HttpResponse::Ok().json(locations)
+pub async fn get_locations_database(req: HttpRequest, _path: web::Path<()>) -> HttpResponse {
+    current_snapshot().locations_body.respond(&req)
 }
 
 #[get("/status")]
 pub async fn get_status(_path: web::Path<()>) -> HttpResponse {
-    let lock = MEMORY_DATABASE.lock().await;
+    let log = TEN_MINUTE_LOG.lock().unwrap();
 
     HttpResponse::Ok().json(Status {
         alive: true,
-        seconds_since_last_connection: Instant::now().duration_since(*lock.ten_minute_log.last().unwrap()).as_secs(),
-        ten_minute_total: lock.ten_minute_log.len() as u64,
+        seconds_since_last_connection: log.last().map(|last| last.elapsed().as_secs()),
+        ten_minute_total: log.len() as u64,
     })
 }
 
-#[get("/fullYearCatalog")]
-pub async fn get_full_year_catalog(_path: web::Path<()>) -> HttpResponse {
+/// Per-source health of every upstream the update loop pulls from
+#[get("/health")]
+pub async fn get_health(_path: web::Path<()>) -> HttpResponse {
+    let lock = MEMORY_DATABASE.lock().await;
+
+    let mut sources = lock.health_cache.clone();
+
+    drop(lock);
+
+    // Geocoding runs in the geocode-locations job, which saves its outcome
+    match load_geocode_health() {
+        Ok(Some(geocoding)) => {
+            sources.insert(Source::Geocoding.key(), geocoding);
+        }
+        Ok(None) => {}
+        Err(e) => warn!("Could not read geocode health: {}", e),
+    }
+
+    // So do menus, in refresh-menus
+    match load_cafe_health() {
+        Ok(cafes) => sources.extend(cafes),
+        Err(e) => warn!("Could not read cafe health: {}", e),
+    }
+
+    let healthy = sources.values().all(|source| source.is_healthy());
+
+    HttpResponse::Ok().json(Health {
+        timestamp: get_unix_timestamp(),
+        healthy,
+        sources,
+    })
+}
+
+/// Prometheus metrics in the text exposition format
+#[get("/metrics")]
+pub async fn get_metrics(_path: web::Path<()>) -> HttpResponse {
     let lock = MEMORY_DATABASE.lock().await;
 
-    let year_catalog = lock.descriptions_cache.clone();
-    let last_change = lock.last_change.clone();
+    let body = render_metrics(&lock);
 
     drop(lock);
 
-    HttpResponse::Ok().json(ReturnCatalog { timestamp: last_change, catalog: year_catalog })
+    HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(body)
+}
+
+/// How many students start or end class at each minute of the week,
+/// optionally only at one school and/or building
+#[get("/traffic")]
+pub async fn get_traffic(query: web::Query<TrafficQuery>) -> HttpResponse {
+    let query = query.into_inner();
+
+    let school = match &query.school {
+        Some(s) => match School::from_query(s) {
+            Some(school) => Some(school),
+            None => return HttpResponse::BadRequest().json("Invalid school"),
+        },
+        None => None,
+    };
+
+    let snapshot = current_snapshot();
+
+    let (starting, ending) = compute_timings(&snapshot.courses, |location| {
+        school.as_ref().map_or(true, |s| location.get_school() == s)
+            && query
+                .building
+                .as_ref()
+                .map_or(true, |b| location.get_building().eq_ignore_ascii_case(b.trim()))
+    });
+
+    let week = [
+        Day::Monday,
+        Day::Tuesday,
+        Day::Wednesday,
+        Day::Thursday,
+        Day::Friday,
+        Day::Saturday,
+        Day::Sunday,
+    ];
+
+    let days = week
+        .iter()
+        .zip(starting.into_iter().zip(ending.into_iter()))
+        .map(|(day, (starting, ending))| TrafficDay {
+            day: day.clone(),
+            starting,
+            ending,
+        })
+        .collect();
+
+    HttpResponse::Ok().json(Traffic {
+        timestamp: snapshot.last_change,
+        school,
+        building: query.building,
+        days,
+    })
+}
+
+// "HH:MM" to minute of the day
+fn parse_minute(time: &str) -> Option<u32> {
+    NaiveTime::parse_from_str(time.trim(), "%H:%M")
+        .ok()
+        .map(|t| t.hour() * 60 + t.minute())
+}
+
+/// Rooms with no class scheduled on a day between two times
+#[get("/rooms/free")]
+pub async fn get_free_rooms(query: web::Query<FreeRoomsQuery>) -> HttpResponse {
+    let query = query.into_inner();
+
+    let day = match Day::from_query(&query.day) {
+        Some(day) => day,
+        None => return HttpResponse::BadRequest().json("Invalid day"),
+    };
+
+    let (from, to) = match (parse_minute(&query.from), parse_minute(&query.to)) {
+        (Some(from), Some(to)) if from < to => (from, to),
+        _ => return HttpResponse::BadRequest().json("Invalid time range"),
+    };
+
+    // Half-semester classes only take up a room while they're running
+    let date = match &query.date {
+        Some(date) => match NaiveDate::parse_from_str(date.trim(), "%Y-%m-%d") {
+            Ok(date) => date,
+            Err(_) => return HttpResponse::BadRequest().json("Invalid date"),
+        },
+        None => Local::today().naive_local(),
+    };
+
+    let snapshot = current_snapshot();
+
+    let rooms = snapshot
+        .rooms
+        .free(query.building.as_deref(), &day, from, to, date)
+        .into_iter()
+        .map(|room| FreeRoom {
+            school: room.school.clone(),
+            building: room.building.clone(),
+            room: room.room.clone(),
+        })
+        .collect();
+
+    HttpResponse::Ok().json(FreeRooms {
+        timestamp: snapshot.last_change,
+        day,
+        from: query.from,
+        to: query.to,
+        date,
+        rooms,
+    })
+}
+
+/// Every class that meets in a room, over the week
+#[get("/rooms/{building}/{room}")]
+pub async fn get_room_schedule(path: web::Path<(String, String)>) -> HttpResponse {
+    let (building, room) = path.into_inner();
+
+    match current_snapshot().rooms.get(&building, &room) {
+        Some(room) => HttpResponse::Ok().json(room),
+        None => HttpResponse::NotFound().json("Unknown room"),
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Walking {
+    transitions: Vec<Transition>,
+    // Transitions whose walk is longer than the gap between classes
+    impossible: usize,
+}
+
+/// Distance and walking time between consecutive classes in a schedule
+#[post("/schedule/walking")]
+pub async fn get_schedule_walking(post: web::Json<Vec<Course>>) -> HttpResponse {
+    let snapshot = current_snapshot();
+
+    let transitions = walking_transitions(&post, &snapshot.locations);
+    let impossible = transitions
+        .iter()
+        .filter(|t| t.feasible == Some(false))
+        .count();
+
+    HttpResponse::Ok().json(Walking {
+        transitions,
+        impossible,
+    })
+}
+
+/// Buildings with their courses and dining halls with today's meals, for
+/// dropping into a map
+#[get("/map.geojson")]
+pub async fn get_map(_path: web::Path<()>) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("application/geo+json")
+        .json(campus_map(&current_snapshot()))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MatchesQuery {
+    below: Option<f32>,
+}
+
+#[derive(Debug, Serialize)]
+struct Matches<'a> {
+    timestamp: u64,
+    min_score: f32,
+    below: f32,
+    matches: Vec<&'a MatchReport>,
+}
+
+/// Course to description matches scoring below a threshold, worst first,
+/// for deciding what to pin or block in the overrides file
+#[get("/descriptions/matches")]
+pub async fn get_description_matches(query: web::Query<MatchesQuery>) -> HttpResponse {
+    let below = query.below.unwrap_or(CONFIG.matching.review_score);
+
+    if !(0.0..=1.0).contains(&below) {
+        return HttpResponse::BadRequest().json("Invalid score");
+    }
+
+    let snapshot = current_snapshot();
+    let matches = &snapshot.description_matches;
+
+    HttpResponse::Ok().json(Matches {
+        timestamp: snapshot.last_change,
+        min_score: matches.min_score,
+        below,
+        matches: matches.below(below).collect(),
+    })
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CatalogSearchQuery {
+    q: String,
+    source: Option<String>,
+    currently_offered: Option<bool>,
+    limit: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+struct CatalogResult<'a> {
+    score: f32,
+    #[serde(flatten)]
+    description: &'a CourseDescription,
+}
+
+#[derive(Debug, Serialize)]
+struct CatalogSearch<'a> {
+    timestamp: u64,
+    query: String,
+    // Matches before the limit
+    total: usize,
+    results: Vec<CatalogResult<'a>>,
+}
+
+/// Catalog descriptions matching a search, best first
+#[get("/catalog/search")]
+pub async fn get_catalog_search(query: web::Query<CatalogSearchQuery>) -> HttpResponse {
+    let query = query.into_inner();
+
+    if query.q.trim().is_empty() {
+        return HttpResponse::BadRequest().json("Invalid query");
+    }
+
+    let source = match &query.source {
+        Some(s) => match School::from_query(s) {
+            Some(school) => Some(school),
+            None => return HttpResponse::BadRequest().json("Invalid school"),
+        },
+        None => None,
+    };
+
+    let limit = query.limit.unwrap_or(SEARCH_LIMIT).min(MAX_SEARCH_LIMIT);
+
+    let snapshot = current_snapshot();
+    let descriptions = &snapshot.descriptions;
+
+    let found = snapshot.catalog_index.search(&query.q, |i| {
+        let desc = &descriptions[i];
+
+        source.as_ref().map_or(true, |s| desc.get_source() == s)
+            && query
+                .currently_offered
+                .map_or(true, |offered| desc.currently_offered == offered)
+    });
+
+    let results = found
+        .iter()
+        .take(limit)
+        .map(|(i, score)| CatalogResult {
+            score: *score,
+            description: &descriptions[*i],
+        })
+        .collect();
+
+    HttpResponse::Ok().json(CatalogSearch {
+        timestamp: snapshot.last_change,
+        query: query.q.clone(),
+        total: found.len(),
+        results,
+    })
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SimilarQuery {
+    limit: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+struct SimilarCourses<'a> {
+    timestamp: u64,
+    identifier: String,
+    title: String,
+    source: School,
+    similar: Vec<CatalogResult<'a>>,
+}
+
+/// Currently offered courses at other colleges whose catalog descriptions
+/// are closest to a course's
+#[get("/course/{identifier}/similar")]
+pub async fn get_similar_courses(
+    path: web::Path<String>,
+    query: web::Query<SimilarQuery>,
+) -> HttpResponse {
+    let identifier = path.into_inner();
+    let key = catalog_key(&identifier);
+    let limit = query.limit.unwrap_or(SIMILAR_LIMIT).min(MAX_SEARCH_LIMIT);
+
+    let snapshot = current_snapshot();
+    let descriptions = &snapshot.descriptions;
+
+    // The catalog entry, or else this term's course
+    let catalog_entry = descriptions
+        .iter()
+        .position(|desc| catalog_key(&desc.identifier) == key);
+
+    let (title, source, vector) = match catalog_entry {
+        Some(i) => (
+            descriptions[i].title.clone(),
+            descriptions[i].get_source().clone(),
+            snapshot.similarity.vector(i).cloned().unwrap_or_default(),
+        ),
+        None => match snapshot
+            .courses
+            .iter()
+            .find(|course| catalog_key(course.get_identifier()) == key)
+        {
+            Some(course) => (
+                course.get_title().clone(),
+                course.get_school().unwrap_or(School::NA),
+                snapshot
+                    .similarity
+                    .vector_for(course.get_title(), &course.get_description()),
+            ),
+            None => return HttpResponse::NotFound().json("Unknown course"),
+        },
+    };
+
+    let similar = snapshot
+        .similarity
+        .nearest(&vector, |i| {
+            let desc = &descriptions[i];
+
+            desc.currently_offered
+                && desc.get_source() != &source
+                && desc.get_source() != &School::NA
+                && catalog_key(&desc.identifier) != key
+        })
+        .into_iter()
+        .take(limit)
+        .map(|(i, score)| CatalogResult {
+            score,
+            description: &descriptions[i],
+        })
+        .collect();
+
+    HttpResponse::Ok().json(SimilarCourses {
+        timestamp: snapshot.last_change,
+        identifier,
+        title,
+        source,
+        similar,
+    })
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OfferedQuery {
+    semester: String,
+    source: Option<String>,
+    // Include courses the catalog says too little about to rule out
+    possible: Option<bool>,
+}
+
+#[derive(Debug, Serialize)]
+struct OfferedResult<'a> {
+    likelihood: Likelihood,
+    #[serde(flatten)]
+    description: &'a CourseDescription,
+}
+
+#[derive(Debug, Serialize)]
+struct OfferedCourses<'a> {
+    timestamp: u64,
+    semester: Semester,
+    courses: Vec<OfferedResult<'a>>,
+}
+
+/// Catalog courses likely to be offered in a semester, going by the
+/// catalogs' "Offered" text
+#[get("/catalog/offered")]
+pub async fn get_offered_courses(query: web::Query<OfferedQuery>) -> HttpResponse {
+    let query = query.into_inner();
+
+    let semester = match Semester::from_query(&query.semester) {
+        Some(semester) => semester,
+        None => return HttpResponse::BadRequest().json("Invalid semester"),
+    };
+
+    let source = match &query.source {
+        Some(s) => match School::from_query(s) {
+            Some(school) => Some(school),
+            None => return HttpResponse::BadRequest().json("Invalid school"),
+        },
+        None => None,
+    };
+
+    let possible = query.possible.unwrap_or(false);
+
+    let snapshot = current_snapshot();
+
+    let courses = snapshot
+        .descriptions
+        .iter()
+        .filter(|desc| source.as_ref().map_or(true, |s| desc.get_source() == s))
+        .map(|desc| OfferedResult {
+            likelihood: desc.offered_schedule.offered_in(&semester),
+            description: desc,
+        })
+        .filter(|result| match result.likelihood {
+            Likelihood::Likely => true,
+            Likelihood::Possible => possible,
+            Likelihood::Unlikely => false,
+        })
+        .collect();
+
+    HttpResponse::Ok().json(OfferedCourses {
+        timestamp: snapshot.last_change,
+        semester,
+        courses,
+    })
+}
+
+#[derive(Debug, Serialize)]
+struct RequirementCourses<'a> {
+    #[serde(flatten)]
+    requirement: &'a Requirement,
+    // This term's courses that fulfill it
+    courses: Vec<&'a String>,
+}
+
+#[derive(Debug, Serialize)]
+struct SchoolRequirements<'a> {
+    timestamp: u64,
+    school: School,
+    requirements: Vec<RequirementCourses<'a>>,
+}
+
+/// A college's requirements from requirements.json, each with the courses
+/// that fulfill it this term
+#[get("/requirements/{school}")]
+pub async fn get_requirements(path: web::Path<String>) -> HttpResponse {
+    let school = match School::from_query(&path.into_inner()) {
+        Some(school) => school,
+        None => return HttpResponse::BadRequest().json("Invalid school"),
+    };
+
+    let snapshot = current_snapshot();
+
+    let requirements = REQUIREMENTS
+        .for_school(&school)
+        .map(|requirement| RequirementCourses {
+            requirement,
+            courses: snapshot
+                .courses
+                .iter()
+                .filter(|course| course.get_fulfills().contains(&requirement.id))
+                .map(|course| course.get_identifier())
+                .collect(),
+        })
+        .collect();
+
+    HttpResponse::Ok().json(SchoolRequirements {
+        timestamp: snapshot.last_change,
+        school,
+        requirements,
+    })
+}
+
+#[get("/fullYearCatalog")]
+pub async fn get_full_year_catalog(req: HttpRequest, _path: web::Path<()>) -> HttpResponse {
+    current_snapshot().catalog_body.respond(&req)
 }
 
 #[get("/getCatalogIfStale/{unix_timestamp_seconds}")]
-pub async fn get_catalog_if_stale(path: web::Path<u64>) -> HttpResponse {
+pub async fn get_catalog_if_stale(req: HttpRequest, path: web::Path<u64>) -> HttpResponse {
     let unix_timestamp_seconds = path.into_inner();
 
-    let mut lock = MEMORY_DATABASE.lock().await;
-    
-    lock.add_ten_log();
-    
-    
-    if &lock.last_change != &unix_timestamp_seconds {
-        info!("Serving catalog update!");
-        let year_catalog = lock.descriptions_cache.clone();
-        let timestamp = lock.last_change.clone();
+    add_ten_log();
 
-        drop(lock);
+    let snapshot = current_snapshot();
 
-        HttpResponse::Ok().json(ReturnCatalog{ timestamp: timestamp, catalog: year_catalog })
+    if snapshot.last_change != unix_timestamp_seconds {
+        info!("Serving catalog update!");
+        snapshot.catalog_body.respond(&req)
     } else {
         info!("No catalog update needed!");
         HttpResponse::Ok().json("No update needed")
@@ -174,23 +698,57 @@ pub async fn get_catalog_if_stale(path: web::Path<u64>) -> HttpResponse {
 }
 
 #[get("/getMenus")]
-pub async fn get_menus(_path: web::Path<()>) -> HttpResponse {
-    let lock = MEMORY_DATABASE.lock().await;
+pub async fn get_menus(req: HttpRequest, _path: web::Path<()>) -> HttpResponse {
+    current_snapshot().menus_body.respond(&req)
+}
 
-    let menus = lock.menu_cache.clone();
+#[get("/getCourseAreas")]
+pub async fn get_course_areas(req: HttpRequest, _path: web::Path<()>) -> HttpResponse {
+    current_snapshot().areas_body.respond(&req)
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test;
+    use serde_json::{json, Value};
 
-    drop(lock);
+    const NEW_COURSES: &str = include_str!("../fixtures/merge/new_courses.json");
 
-    HttpResponse::Ok().json(menus)
-}
+    // A course credit at Pomona, then one and four HMC units
+    async fn share(code: &str) {
+        let courses: Vec<Course> = serde_json::from_str(NEW_COURSES).unwrap();
+        let pick = |identifier: &str| courses.iter().find(|c| c.get_identifier() == identifier).unwrap().clone();
 
-#[get("/getCourseAreas")]
-pub async fn get_course_areas(_path: web::Path<()>) -> HttpResponse {
-    let lock = MEMORY_DATABASE.lock().await;
+        let list = SharedCourseList::new(
+            vec![pick("ECON-167-PO-01"), pick("PHYS-050-HM-02")],
+            vec![pick("MATH-019-HM-01")],
+        );
 
-    let course_areas = lock.areas_cache.clone();
+        MEMORY_DATABASE.lock().await.code_cache.insert(code.to_string(), list);
+    }
 
-    drop(lock);
+    async fn get_json(uri: &str) -> Value {
+        let app = test::init_service(App::new().service(get_course_list_by_code)).await;
+        let req = test::TestRequest::get().uri(uri).to_request();
+
+        test::read_body_json(test::call_service(&app, req).await).await
+    }
+
+    #[tokio::test]
+    async fn course_list_totals_credit_in_home_college_units() {
+        share("CREDITS").await;
 
-    HttpResponse::Ok().json(course_areas)
-}
\ No newline at end of file
+        let credits = get_json("/getCourseListByCode/credits?home=hm").await["credits"].clone();
+        assert_eq!(credits, json!({ "school": "HarveyMudd", "units": "HmcUnits", "total": 800 }));
+
+        for (home, school) in [("po", "Pomona"), ("CMC", "ClaremontMckenna"), ("Scripps", "Scripps"), ("pz", "Pitzer")] {
+            let credits = get_json(&format!("/getCourseListByCode/CREDITS?home={}", home)).await["credits"].clone();
+            assert_eq!(credits, json!({ "school": school, "units": "CourseCredits", "total": 267 }));
+        }
+
+        let credits = get_json("/getCourseListByCode/CREDITS").await["credits"].clone();
+        assert_eq!(credits, json!({ "school": "NA", "units": "CourseCredits", "total": 267 }));
+
+        assert_eq!(get_json("/getCourseListByCode/CREDITS?home=mit").await, json!("Invalid school"));
+    }
+}
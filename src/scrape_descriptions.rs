@@ -1,6 +1,7 @@
 // ----------------------------------------------------------------------------
 // This file contains the code for scraping the descriptions from all
-// 5 different colleges. The 2 graduate universities are not scraped.
+// 5 different colleges, and from KGI and CGU once their catalogs are
+// configured (catalog.keck, catalog.claremont_graduate).
 //
 // This will take a lot of time to do.
 //
@@ -8,10 +9,19 @@
 // that actually updates EVERYTHING.
 // ----------------------------------------------------------------------------
 
+use crate::acalog::*;
+use crate::config::CONFIG;
+use crate::credits::*;
 use crate::course_api::*;
+use crate::fees::*;
+use crate::health::*;
+use crate::identifier::*;
+use crate::merge_index::*;
+use crate::offered::*;
 use crate::School::*;
 
 use ::serde::*;
+use async_trait::async_trait;
 use escaper::*;
 use lazy_static::lazy_static;
 use log::{error, info, warn};
@@ -19,10 +29,13 @@ use regex::Regex;
 use reqwest::*;
 use rust_fuzzy_search::*;
 use std::ascii::AsciiExt;
+use std::collections::hash_map::DefaultHasher;
 use std::collections::HashMap;
 use std::error::Error;
 use std::f32::consts::PI;
+use std::hash::{Hash, Hasher};
 use std::ops::Index;
+use std::time::Instant;
 
 // Remove HTML while preserving what is inside of
 // links/bolded
@@ -61,10 +74,14 @@ pub struct CourseDescription {
     pub credits: u64,
     pub instructors: Vec<String>,
     pub offered: String,
+    #[serde(default)]
+    pub offered_schedule: OfferedSchedule,
     pub prerequisites: String,
     pub corequisites: String,
     pub currently_offered: bool,
     pub fee: u64,
+    #[serde(default)]
+    pub fees: Vec<Fee>,
 }
 
 impl CourseDescription {
@@ -80,6 +97,8 @@ impl CourseDescription {
         corequisites: String,
         currently_offered: bool,
     ) -> CourseDescription {
+        let offered_schedule = parse_offered(&offered);
+
         CourseDescription {
             title,
             identifier,
@@ -88,10 +107,12 @@ impl CourseDescription {
             credits,
             instructors,
             offered,
+            offered_schedule,
             prerequisites,
             corequisites,
             currently_offered,
             fee: 0,
+            fees: Vec::new(),
         }
     }
 
@@ -111,8 +132,10 @@ impl CourseDescription {
         &self.source
     }
 
-    pub fn set_fee(&mut self, fee: u64) {
-        self.fee = fee;
+    /// Also sets `fee` to the first one in whole dollars
+    pub fn set_fees(&mut self, fees: Vec<Fee>) {
+        self.fee = first_fee_dollars(&fees);
+        self.fees = fees;
     }
 
     pub fn get_fee(&self) -> u64 {
@@ -120,6 +143,113 @@ impl CourseDescription {
     }
 }
 
+/// A catalog entry as read from a print page or the Acalog API, before
+/// it's cleaned up
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CatalogEntry {
+    // "AFRI 010A AF"
+    pub code: String,
+    pub title: String,
+    pub description: String,
+    pub credits: String,
+    // As listed, separated by instructor_separator
+    pub instructors: String,
+    pub offered: String,
+    pub prerequisites: String,
+    pub corequisites: String,
+}
+
+/// Whether an entry only says to see another college's catalog, like the
+/// cross-listings in the CMC, Pomona and Pitzer catalogs
+pub fn points_elsewhere(text: &str) -> bool {
+    let text = text.to_lowercase();
+
+    text.contains("see") && text.contains("catalog") && text.contains("college")
+}
+
+/// College code for catalogs that list courses without one: "IST 303"
+/// rather than "AFRI 010A AF"
+pub fn catalog_college(style: &School) -> Option<&'static str> {
+    match style {
+        Keck => Some("KG"),
+        ClaremontGraduate => Some("CG"),
+        _ => None,
+    }
+}
+
+/// What a school's catalog separates instructors with
+pub fn instructor_separator(style: &School) -> char {
+    match style {
+        HarveyMudd => ',',
+        _ => ';',
+    }
+}
+
+/// Catalog titles with entities decoded, "&" written out as "and" and
+/// quotes straightened, the way course titles are written
+pub fn clean_title(title: &str) -> String {
+    let title = match decode_html(title) {
+        Err(_) => title.to_string(),
+        Ok(s) => s,
+    };
+
+    let title = title
+        .replace('&', "and")
+        .replace(['\u{2018}', '\u{2019}'], "'")
+        .replace(['\u{201c}', '\u{201d}'], "\"")
+        .replace('\u{a0}', " ");
+
+    RE_SPACES.replace_all(&title, " ").trim().to_string()
+}
+
+/// Text with HTML removed, entities decoded and spaces collapsed
+fn clean_text(s: &str) -> String {
+    let s = RE_HTML.replace_all(s, "").to_string();
+
+    // Remove HTML entities
+    let s = match decode_html(&s) {
+        Err(_) => s,
+        Ok(s) => s,
+    };
+
+    // Replace all multiple spaces (including decoded &nbsp;) with a
+    // single one
+    RE_SPACES.replace_all(&s, " ").trim().to_string()
+}
+
+impl CatalogEntry {
+    /// Cleans the entry up into a description. Print pages and the API both
+    /// go through here, so a course reads the same whichever was used.
+    pub fn into_description(self, style: School) -> CourseDescription {
+        let credits = parse_hundredths(&self.credits).unwrap_or(0);
+
+        let instructors = self
+            .instructors
+            .split(instructor_separator(&style))
+            .map(pretty_parse_html)
+            .filter(|name| !name.is_empty())
+            .collect();
+
+        let code = match catalog_college(&style) {
+            Some(college) if !self.code.trim_end().ends_with(college) => format!("{} {}", self.code, college),
+            _ => self.code,
+        };
+
+        CourseDescription::new(
+            clean_title(&self.title),
+            convert_course_code_to_identifier(&code),
+            clean_text(&self.description),
+            style,
+            credits,
+            instructors,
+            pretty_parse_html(&self.offered),
+            clean_text(&self.prerequisites),
+            clean_text(&self.corequisites),
+            false,
+        )
+    }
+}
+
 // Serves only HMC classes
 pub fn hmc_url(page_num: u64) -> String {
     format!("https://catalog.hmc.edu/content.php?filter[27]=-1&filter[29]=&filter[course_type]=-1&filter[keyword]=&filter[32]=1&filter[cpage]={}&cur_cat_oid=18&expand=1&navoid=892&print=1#acalog_template_course_filter", page_num)
@@ -145,28 +275,25 @@ pub fn pitzer_url(page_num: u64) -> String {
     format!("https://catalog.pitzer.edu/content.php?filter[27]=-1&filter[29]=&filter[course_type]=-1&filter[keyword]=&filter[32]=1&filter[cpage]={}&cur_cat_oid=17&expand=1&navoid=1376&print=1&filter[exact_match]=1#acalog_template_course_filter", page_num)
 }
 
-pub fn between(source: &str, start: &str, end: &str) -> String {
-    let start_pos = &source[source.find(start).unwrap() + start.len()..];
+// The graduate schools' catalog IDs come from the config
+pub fn kgi_url(page_num: u64) -> String {
+    let catalog = &CONFIG.catalog.keck;
 
-    start_pos[..start_pos.find(end).unwrap_or(start_pos.len())]
-        .trim()
-        .to_string()
+    format!("https://catalog.kgi.edu/content.php?filter[27]=-1&filter[29]=&filter[course_type]=-1&filter[keyword]=&filter[32]=1&filter[cpage]={}&cur_cat_oid={}&expand=1&navoid={}&print=1#acalog_template_course_filter", page_num, catalog.catalog, catalog.navoid)
 }
 
-pub fn between_multiple(source: &str, start: &str, end: Vec<&str>) -> String {
-    let start_pos = &source[source.find(start).unwrap() + start.len()..];
-
-    let mut end_pos = start_pos.len();
+pub fn cgu_url(page_num: u64) -> String {
+    let catalog = &CONFIG.catalog.claremont_graduate;
 
-    for e in end {
-        let end_pos_temp = start_pos.find(e).unwrap_or(start_pos.len());
+    format!("https://catalog.cgu.edu/content.php?filter[27]=-1&filter[29]=&filter[course_type]=-1&filter[keyword]=&filter[32]=1&filter[cpage]={}&cur_cat_oid={}&expand=1&navoid={}&print=1#acalog_template_course_filter", page_num, catalog.catalog, catalog.navoid)
+}
 
-        if end_pos_temp < end_pos {
-            end_pos = end_pos_temp;
-        }
-    }
+pub fn between(source: &str, start: &str, end: &str) -> String {
+    let start_pos = &source[source.find(start).unwrap() + start.len()..];
 
-    start_pos[..end_pos].trim().to_string()
+    start_pos[..start_pos.find(end).unwrap_or(start_pos.len())]
+        .trim()
+        .to_string()
 }
 
 pub fn extract_description(html: String, style: School) -> Result<Vec<CourseDescription>> {
@@ -181,10 +308,7 @@ pub fn extract_description(html: String, style: School) -> Result<Vec<CourseDesc
 
     for (index, line) in start_indexes {
         // Skip lines that don't contain a course description
-        if line.to_lowercase().contains("see")
-            && line.to_lowercase().contains("catalog")
-            && line.to_lowercase().contains("college")
-        {
+        if points_elsewhere(line) {
             continue;
         }
 
@@ -205,8 +329,6 @@ pub fn extract_description(html: String, style: School) -> Result<Vec<CourseDesc
             .split(" -")
             .nth(1)
             .unwrap()
-            .replace("&", "and")
-            .replace("#8217;", "'")
             .to_string();
 
         identifier.retain(|c| !c.is_whitespace());
@@ -217,16 +339,14 @@ pub fn extract_description(html: String, style: School) -> Result<Vec<CourseDesc
             return Ok(return_vec);
         }
 
-        identifier = convert_course_code_to_identifier(&identifier);
-
         // Guaranteed to exist
         let description;
 
         // Not guaranteed to exist
         let mut when_offered = String::new();
-        let mut credits: f64 = 0.0;
+        let mut credits = String::new();
         let mut prerequisites = String::new();
-        let mut instructors = Vec::new();
+        let mut instructors = String::new();
         let mut corequisites = String::new();
 
         if &style == &HarveyMudd {
@@ -242,18 +362,13 @@ pub fn extract_description(html: String, style: School) -> Result<Vec<CourseDesc
                     .trim()
                     .to_string();
 
-                credits = between(split_line[1], "<strong>Credit(s):</strong> ", "<br>")
-                    .parse::<f64>()
-                    .unwrap_or(0.0);
+                credits = between(split_line[1], "<strong>Credit(s):</strong> ", "<br>");
 
                 instructors = between(
                     split_line[1],
                     "<br><br><strong>Instructor(s):</strong>",
                     "<br><br>",
-                )
-                .split(",")
-                .map(|x| x.trim().to_string())
-                .collect::<Vec<String>>();
+                );
 
                 if split_line[1].contains("<strong>Offered:</strong>") {
                     when_offered = between(split_line[1], "<strong>Offered:</strong>", "<br><br>");
@@ -293,9 +408,7 @@ pub fn extract_description(html: String, style: School) -> Result<Vec<CourseDesc
                 }
 
                 if target.contains("<strong>Course Credit:</strong>") {
-                    credits = between(target, "<strong>Course Credit:</strong>", "<br>")
-                        .parse::<f64>()
-                        .unwrap_or(0.0);
+                    credits = between(target, "<strong>Course Credit:</strong>", "<br>");
                 }
 
                 if target.contains("<strong>Offered:</strong>") {
@@ -334,9 +447,7 @@ pub fn extract_description(html: String, style: School) -> Result<Vec<CourseDesc
                 }
             }
 
-            credits = between(&target, "<br>Credit: ", "<br>")
-                .parse::<f64>()
-                .unwrap_or(0.0);
+            credits = between(&target, "<br>Credit: ", "<br>");
 
             when_offered = between(&target, "<br><br>Offered: ", "<br><br>");
         } else if &style == &Pomona {
@@ -369,15 +480,10 @@ pub fn extract_description(html: String, style: School) -> Result<Vec<CourseDesc
                         split_line[1],
                         "<br><strong>Instructor(s):</strong>",
                         "<br><strong>Credit:</strong>",
-                    )
-                    .split(";")
-                    .map(|x| x.trim().to_string())
-                    .collect::<Vec<String>>();
+                    );
                 }
 
-                credits = between(split_line[1], "<br><strong>Credit:</strong>", "<br><br>")
-                    .parse::<f64>()
-                    .unwrap_or(0.0);
+                credits = between(split_line[1], "<br><strong>Credit:</strong>", "<br><br>");
                 ();
             } else {
                 description = String::from("");
@@ -395,50 +501,48 @@ pub fn extract_description(html: String, style: School) -> Result<Vec<CourseDesc
                     .trim()
                     .to_string();
             }
-        } else {
-            description = String::from("");
-        }
-        let description = RE_HTML.replace_all(&description, "").to_string();
-        let prerequisites = RE_HTML.replace_all(&prerequisites, "").to_string();
-        let corequisites = RE_HTML.replace_all(&corequisites, "").to_string();
-
-        // Replace all multiple spaces with a single one
-        let description = RE_SPACES.replace_all(&description, " ").to_string();
-        let prerequisites = RE_SPACES.replace_all(&prerequisites, " ").to_string();
-        let corequisites = RE_SPACES.replace_all(&corequisites, " ").to_string();
-
-        // Remove HTML entities
-        let description = match decode_html(&description) {
-            Err(_) => description,
-            Ok(s) => s,
-        };
+        } else if catalog_college(&style).is_some() {
+            // Acalog's own layout: the description, then each field as
+            // "<strong>Title:</strong> value"
+            let body = split_line[1].split("<hr>").nth(1).unwrap_or("");
+            let mut fields = body.split("<strong>");
+
+            let mut entry = CatalogEntry {
+                code: identifier,
+                title,
+                description: fields.next().unwrap_or("").to_string(),
+                ..CatalogEntry::default()
+            };
 
-        let prerequisites = match decode_html(&prerequisites) {
-            Err(_) => prerequisites,
-            Ok(s) => s,
-        };
+            for field in fields {
+                if let Some((field_title, value)) = field.split_once("</strong>") {
+                    if let Some(part) = entry_part(&mut entry, field_title) {
+                        *part = RE_HTML.replace_all(value, " ").to_string();
+                    }
+                }
+            }
 
-        let corequisites = match decode_html(&corequisites) {
-            Err(_) => corequisites,
-            Ok(s) => s,
-        };
+            info!("[{}] [{}]", entry.title, entry.code);
 
+            return_vec.push(entry.into_description(style.clone()));
+            continue;
+        } else {
+            description = String::from("");
+        }
         info!("[{}] [{}]", title, identifier);
 
-        let credits = (credits * 100.0) as u64;
-
-        return_vec.push(CourseDescription::new(
+        let entry = CatalogEntry {
+            code: identifier,
             title,
-            identifier,
-            description.to_string(),
-            style.clone(),
+            description,
             credits,
             instructors,
-            when_offered,
+            offered: when_offered,
             prerequisites,
             corequisites,
-            false,
-        ));
+        };
+
+        return_vec.push(entry.into_description(style.clone()));
     }
 
     Ok(return_vec)
@@ -454,10 +558,14 @@ pub async fn reqwest_get_ignore_ssl(url: &str) -> Result<reqwest::Response> {
         .await
 }
 
+/// Every course on the print pages, stopping at the first empty page. A
+/// page that can't be read fails the whole school, so it keeps its last
+/// descriptions instead of losing every page after that one.
 pub async fn scrape_url(
     url_fn: fn(u64) -> String,
     style: School,
-) -> Result<Vec<CourseDescription>> {
+    pages: &mut SchoolPages,
+) -> std::result::Result<Vec<CourseDescription>, String> {
     let mut return_vec = Vec::new();
     let mut page_num = 1;
     let mut continue_scraping = true;
@@ -465,23 +573,22 @@ pub async fn scrape_url(
     while continue_scraping {
         info!("Scraping page {}", page_num);
         let response = reqwest_get_ignore_ssl(&url_fn(page_num))
-            .await?
+            .await
+            .map_err(|e| e.to_string())?
             .text()
-            .await?;
+            .await
+            .map_err(|e| e.to_string())?;
 
-        let extracted_pairs = extract_description(response, style.clone());
+        let mut extracted_pairs = pages
+            .read(&format!("print/{}", page_num), &response, |html| {
+                extract_description(html.to_string(), style.clone()).map_err(|e| e.to_string())
+            })
+            .map_err(|e| format!("Could not read page {}: {}", page_num, e))?;
 
-        if extracted_pairs.is_err() {
-            error!("Error! {}", extracted_pairs.err().unwrap());
+        if extracted_pairs.is_empty() {
             continue_scraping = false;
         } else {
-            let mut extracted_pairs = extracted_pairs.unwrap();
-
-            if extracted_pairs.len() == 0 {
-                continue_scraping = false;
-            } else {
-                return_vec.append(&mut extracted_pairs);
-            }
+            return_vec.append(&mut extracted_pairs);
         }
 
         page_num += 1;
@@ -489,30 +596,231 @@ pub async fn scrape_url(
     Ok(return_vec)
 }
 
+/// Print view of each school's catalog
+pub fn print_url(style: &School) -> Option<fn(u64) -> String> {
+    match style {
+        HarveyMudd => Some(hmc_url),
+        ClaremontMckenna => Some(cmc_url),
+        Pomona => Some(pomona_url),
+        Scripps => Some(scripps_url),
+        Pitzer => Some(pitzer_url),
+        Keck if CONFIG.catalog.keck.is_set() => Some(kgi_url),
+        ClaremontGraduate if CONFIG.catalog.claremont_graduate.is_set() => Some(cgu_url),
+        _ => None,
+    }
+}
+
+/// A catalog page as of the last refresh: a hash of its contents and the
+/// descriptions read from it
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CatalogPage {
+    pub hash: u64,
+    pub descriptions: Vec<CourseDescription>,
+}
+
+// Bump whenever the page parsers change what they read, so pages saved by an
+// older parser are parsed again instead of reused
+const PARSER_VERSION: u32 = 1;
+
+// Only used to tell whether a page changed, so it doesn't need to be stable
+// across Rust versions; if it changes, every page is parsed again once.
+fn page_hash(text: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    PARSER_VERSION.hash(&mut hasher);
+    text.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Every school's catalog pages from the last refresh, saved between runs
+/// (paths.catalog_pages) so pages that haven't changed aren't parsed again
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct CatalogPages {
+    pub schools: HashMap<School, HashMap<String, CatalogPage>>,
+}
+
+impl CatalogPages {
+    /// Starts reading a school's catalog against its pages from last time
+    pub fn school(&self, style: &School) -> SchoolPages {
+        SchoolPages {
+            previous: self.schools.get(style).cloned().unwrap_or_default(),
+            read: HashMap::new(),
+            parsed: 0,
+        }
+    }
+
+    /// Descriptions from a school's pages last time, in page order
+    pub fn descriptions(&self, style: &School) -> Vec<CourseDescription> {
+        let mut pages: Vec<(&String, &CatalogPage)> = self.schools.get(style).into_iter().flatten().collect();
+
+        pages.sort_by_key(|(key, _)| page_order(key));
+
+        pages
+            .into_iter()
+            .flat_map(|(_, page)| page.descriptions.clone())
+            .collect()
+    }
+
+    /// Replaces a school's pages with the ones read this time, dropping
+    /// pages that are gone
+    pub fn set_school(&mut self, style: School, pages: SchoolPages) {
+        self.schools.insert(style, pages.read);
+    }
+}
+
+// "print/12" sorts after "print/2"
+fn page_order(key: &str) -> (&str, u64) {
+    let (source, page) = key.rsplit_once('/').unwrap_or((key, ""));
+
+    (source, page.parse().unwrap_or(0))
+}
+
+/// The pages of one school's catalog as they're read
+pub struct SchoolPages {
+    previous: HashMap<String, CatalogPage>,
+    read: HashMap<String, CatalogPage>,
+    // Pages that were new or had changed
+    pub parsed: usize,
+}
+
+impl SchoolPages {
+    /// Descriptions on a page, reusing last time's if the page is the same
+    pub fn read<F>(&mut self, key: &str, text: &str, parse: F) -> std::result::Result<Vec<CourseDescription>, String>
+    where
+        F: FnOnce(&str) -> std::result::Result<Vec<CourseDescription>, String>,
+    {
+        let hash = page_hash(text);
+
+        let descriptions = match self.previous.get(key) {
+            Some(page) if page.hash == hash => page.descriptions.clone(),
+            _ => {
+                self.parsed += 1;
+                parse(text)?
+            }
+        };
+
+        self.read.insert(
+            key.to_string(),
+            CatalogPage {
+                hash,
+                descriptions: descriptions.clone(),
+            },
+        );
+
+        Ok(descriptions)
+    }
+
+    pub fn len(&self) -> usize {
+        self.read.len()
+    }
+}
+
+#[async_trait]
+pub trait CatalogSource: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Every course in a school's catalog, reading each page through
+    /// `pages`
+    async fn fetch(
+        &self,
+        style: &School,
+        pages: &mut SchoolPages,
+    ) -> std::result::Result<Vec<CourseDescription>, String>;
+}
+
+/// Scrapes the catalog's print view, a page of courses at a time
+pub struct PrintPages;
+
+#[async_trait]
+impl CatalogSource for PrintPages {
+    fn name(&self) -> &'static str {
+        "print pages"
+    }
+
+    async fn fetch(
+        &self,
+        style: &School,
+        pages: &mut SchoolPages,
+    ) -> std::result::Result<Vec<CourseDescription>, String> {
+        let url_fn = print_url(style).ok_or_else(|| format!("No catalog for {:?}", style))?;
+
+        scrape_url(url_fn, style.clone(), pages).await
+    }
+}
+
+/// Sources to try, in order
+pub fn catalog_sources() -> Vec<Box<dyn CatalogSource>> {
+    let mut sources: Vec<Box<dyn CatalogSource>> = Vec::new();
+
+    if !CONFIG.catalog.acalog_key.is_empty() {
+        sources.push(Box::new(AcalogApi));
+    }
+
+    sources.push(Box::new(PrintPages));
+
+    sources
+}
+
+/// Reads a single school's catalog from the first source that has it,
+/// parsing only the pages that changed since `previous`, and records how it
+/// went
+pub async fn scrape_catalog(
+    style: School,
+    previous: &CatalogPages,
+) -> std::result::Result<(Vec<CourseDescription>, SchoolPages), String> {
+    let started = Instant::now();
+    let mut result = Err(format!("No catalog sources for {:?}", style));
+
+    for source in catalog_sources() {
+        let mut pages = previous.school(&style);
+
+        result = match source.fetch(&style, &mut pages).await {
+            Ok(descs) if descs.is_empty() => Err(format!("No courses in {}", source.name())),
+            Ok(descs) => {
+                info!(
+                    "Read {} {:?} courses from {}, parsing {} of {} pages",
+                    descs.len(),
+                    style,
+                    source.name(),
+                    pages.parsed,
+                    pages.len()
+                );
+
+                Ok((descs, pages))
+            }
+            Err(e) => Err(format!("Could not read {}: {}", source.name(), e)),
+        };
+
+        match &result {
+            Ok(_) => break,
+            Err(e) => warn!("{:?} catalog: {}", style, e),
+        }
+    }
+
+    record_result(&Source::Catalog(style), started, &result, |(descs, _)| descs.len()).await;
+
+    result
+}
+
+/// Merges the catalogs. A listing is folded into an earlier one when more
+/// than one earlier listing's identifier contains its identifier: the first
+/// listing whose title contains its title, else the first of those. Any
+/// other listing is kept as its own entry.
 pub fn merge_descriptions(schools_vec: Vec<Vec<CourseDescription>>) -> Vec<CourseDescription> {
     let mut return_map: Vec<CourseDescription> = Vec::new();
+    let mut identifiers = SubstringIndex::default();
     let schools_vec_flat = schools_vec.into_iter().flatten();
 
     for course_desc in schools_vec_flat {
         // First search for all indexes for identifier
-        let mut indexes: Vec<usize> = Vec::new();
-        for (i, course) in return_map.iter().enumerate() {
-            if course.identifier.contains(&course_desc.identifier) {
-                indexes.push(i);
-            }
-        }
+        let indexes = identifiers.containing(&course_desc.identifier);
 
         if indexes.len() > 1 {
             let title_find = return_map
                 .iter()
                 .position(|r| r.title.contains(&course_desc.title));
 
-            let index_to_set = if let Some(index) = title_find {
-                index
-            } else {
-                indexes.remove(0)
-            };
-            
+            let index_to_set = title_find.unwrap_or(indexes[0]);
+
             if return_map[index_to_set].source == School::NA {
                 return_map[index_to_set].currently_offered = course_desc.currently_offered;
                 return_map[index_to_set].source = course_desc.source;
@@ -521,8 +829,8 @@ pub fn merge_descriptions(schools_vec: Vec<Vec<CourseDescription>>) -> Vec<Cours
             if return_map[index_to_set].description.len() < course_desc.description.len() {
                 return_map[index_to_set].description = course_desc.description;
             }
-            
         } else {
+            identifiers.push(&course_desc.identifier);
             return_map.push(course_desc);
         }
     }
@@ -607,22 +915,21 @@ pub fn find_reqs_courses(courses: &mut Vec<Course>) -> Vec<Course> {
 }
 
 pub fn find_fees(descs: &mut Vec<CourseDescription>) -> Vec<CourseDescription> {
-    for mut desc in descs.iter_mut() {
-        if desc.description.contains("$") {
-            // find the first $
-            let fee = between_multiple(&desc.description, "$", vec![" ", ".", ","]);
-
-            let parsed_fee = fee.parse::<u64>();
-
-            if parsed_fee.is_ok() {
-                desc.set_fee(parsed_fee.unwrap());
-            }
-        }
+    for desc in descs.iter_mut() {
+        let fees = parse_fees(&desc.description);
+        desc.set_fees(fees);
     }
 
     descs.to_vec()
 }
 
+/// Fills in every description's offered_schedule from its "Offered" text
+pub fn parse_schedules(descs: &mut Vec<CourseDescription>) {
+    for desc in descs.iter_mut() {
+        desc.offered_schedule = parse_offered(&desc.offered);
+    }
+}
+
 pub fn convert_courses_to_descs(courses: Vec<PartialPomCourse>) -> Vec<CourseDescription> {
     let mut return_vec = Vec::new();
 
@@ -635,41 +942,63 @@ pub fn convert_courses_to_descs(courses: Vec<PartialPomCourse>) -> Vec<CourseDes
             credits: course.credits.clone(),
             instructors: Vec::new(),
             offered:"".to_string(),
+            offered_schedule: OfferedSchedule::default(),
             currently_offered: true,
             prerequisites: String::new(),
             corequisites: String::new(),
             fee: 0,
+            fees: Vec::new(),
         })
     }
 
     return_vec    
 }
 
-pub async fn scrape_all_descriptions() -> Result<Vec<CourseDescription>> {
+/// Scrapes every catalog, parsing only the pages that changed since
+/// `pages` and updating it. A school that can't be read keeps its
+/// descriptions from last time; it's an error only if none could be read.
+pub async fn scrape_all_descriptions(
+    pages: &mut CatalogPages,
+) -> std::result::Result<Vec<CourseDescription>, String> {
     info!("Scraping Pomona API for current courses");
     //let courses = full_pomona_update().await.unwrap();
 
     //let converted_courses = convert_courses_to_descs(courses);
 
-    info!("Scraping HMC descriptions");
-    let hmc_courses = scrape_url(hmc_url, HarveyMudd).await?;
-    info!("Scraping CMC descriptions");
-    let cmc_courses = scrape_url(cmc_url, ClaremontMckenna).await?;
-    info!("Scraping Scripps descriptions");
-    let scripps_courses = scrape_url(scripps_url, Scripps).await?;
-    info!("Scraping Pitzer descriptions");
-    let pitzer_courses = scrape_url(pitzer_url, Pitzer).await?;
-    info!("Scraping Pomona descriptions");
-    let pomona_courses = scrape_url(pomona_url, Pomona).await?;
-
-    let mut all_descs = merge_descriptions(vec![
-        //converted_courses,
-        hmc_courses,
-        cmc_courses,
-        pomona_courses,
-        scripps_courses,
-        pitzer_courses,
-    ]);
+    let mut schools_vec = Vec::new();
+    let mut failed = Vec::new();
+
+    // Order decides which school's listing is kept for cross-listed courses
+    for style in [HarveyMudd, ClaremontMckenna, Pomona, Scripps, Pitzer, Keck, ClaremontGraduate] {
+        if print_url(&style).is_none() {
+            info!("No catalog configured for {:?}", style);
+            continue;
+        }
+
+        info!("Scraping {:?} descriptions", style);
+
+        match scrape_catalog(style.clone(), pages).await {
+            Ok((descs, school_pages)) => {
+                pages.set_school(style, school_pages);
+                schools_vec.push(descs);
+            }
+            Err(e) => {
+                error!("Keeping the last {:?} descriptions: {}", style, e);
+                schools_vec.push(pages.descriptions(&style));
+                failed.push(format!("{:?}", style));
+            }
+        }
+    }
+
+    if failed.len() == schools_vec.len() {
+        return Err("No catalog could be read".to_string());
+    }
+
+    if !failed.is_empty() {
+        warn!("Could not read the {} catalogs", failed.join(", "));
+    }
+
+    let mut all_descs = merge_descriptions(schools_vec);
 
     // Find prerequisites and corequisites
     all_descs = find_reqs(&mut all_descs);
@@ -683,26 +1012,23 @@ pub async fn scrape_all_descriptions() -> Result<Vec<CourseDescription>> {
 pub fn merge_description_and_courses(
     courses: Vec<Course>,
     descriptions: Vec<CourseDescription>,
+    rules: &MatchRules,
 ) -> (Vec<Course>, Vec<CourseDescription>) {
     let mut courses_vec: Vec<Course> = Vec::new();
 
     let mut descs_vec: Vec<CourseDescription> = descriptions.clone();
+    let mut index = DescriptionIndex::new(&descriptions, rules);
 
     for course in courses {
         let mut new_course = course.clone();
 
         // Find the description for this course
-        let description = find_description(course, &descriptions);
+        let description = index.find(&course).map(|m| descriptions[m.index].clone());
 
         if description.is_some() {
             let mut desc = description.unwrap();
 
-            println!("{}", desc.title);
-
-            let index = descs_vec
-                .iter()
-                .position(|r| &r.title == &desc.title && r.identifier.contains(&desc.identifier))
-                .unwrap();
+            let index = index.position(&desc.title, &desc.identifier).unwrap();
 
             desc.set_instructors(new_course.get_instructors());
             desc.set_offered();
@@ -717,21 +1043,25 @@ pub fn merge_description_and_courses(
 
             new_course.set_description(desc.description.clone());
             new_course.set_prerequisites(desc.prerequisites.clone());
+            new_course.set_offered(desc.offered.clone(), desc.offered_schedule.clone());
             new_course.set_corequisites(desc.corequisites.clone());
-            new_course.set_fee(desc.fee.clone());
+            new_course.set_fees(desc.fees.clone());
 
             descs_vec[index] = desc;
         }
 
+        // Courses without a catalog entry may still list fees themselves
+        if new_course.get_fees().is_empty() {
+            let mut fees = parse_fees(&new_course.get_description());
+            fees.extend(parse_fees(new_course.get_notes()));
+            new_course.set_fees(fees);
+        }
+
         courses_vec.push(new_course);
     }
 
-    let total_added_descs = courses_vec
-        .clone()
-        .into_iter()
-        .filter(|x| x.get_description().len() > 1)
-        .count();
-    println!(
+    let (total_added_descs, _) = description_match_stats(&courses_vec);
+    info!(
         "{}/{} courses have descriptions, out of {} catalog entries!",
         total_added_descs,
         courses_vec.len(),
@@ -751,7 +1081,7 @@ pub fn merge_description_and_courses(
         .filter(|x| x.get_fee() > 0)
         .count();
     
-    println!(
+    info!(
         "{} courses and {} catalog entries have fees!",
         course_fees,
         catalog_fees
@@ -793,102 +1123,30 @@ pub fn merge_description_and_courses(
     (courses_vec, descs_vec)
 }
 
+/// Number of courses that have a description, out of all courses
+pub fn description_match_stats(courses: &[Course]) -> (usize, usize) {
+    let matched = courses
+        .iter()
+        .filter(|x| x.get_description().len() > 1)
+        .count();
+
+    (matched, courses.len())
+}
+
+/// Finds the catalog description for a course: the one pinned in the
+/// overrides, else an exact title match, else the only description for
+/// the same course (ignoring the section), else the closest title among
+/// those (or among all descriptions from the course's college, if it
+/// scores at least `rules.min_score`). Blocked descriptions are skipped.
+/// Use DescriptionIndex directly when matching many courses.
 pub fn find_description(
     course: Course,
     course_descriptions: &Vec<CourseDescription>,
+    rules: &MatchRules,
 ) -> Option<CourseDescription> {
-    // Only use from own catalog
-    let course_descriptions: Vec<CourseDescription> = course_descriptions
-        .iter()
-        .filter(|x| Some(x.source.clone()) == course.get_school() || x.source == School::NA)
-        .map(|x| x.clone())
-        .collect();
-
-    let mut course_title = course
-        .get_title()
-        .clone()
-        .to_lowercase()
-        .replace("&", "and");
-
-    if course_title.contains(" - ") {
-        course_title = course_title.split("-").collect::<Vec<&str>>()[1]
-            .trim()
-            .to_string();
-    }
-
-    let course_identifier = course.get_identifier();
-
-    // Check for exact match first
-    let exact_title_match = course_descriptions
-        .iter()
-        .filter(|x| &x.title.to_lowercase() == &course_title)
-        .collect::<Vec<&CourseDescription>>();
-
-    if exact_title_match.is_empty() {
-        // Find all matching identifiers
-        let matching_identifiers: Vec<CourseDescription> = course_descriptions
-            .iter()
-            .cloned()
-            .filter(|d| course_identifier.contains(&d.identifier))
-            .collect();
-
-        // If none, search the entire list for titles:
-        if matching_identifiers.is_empty() {
-            // Then find all matching titles
-            let matching_titles = find_fuzzy_title(&course_title, &course_descriptions);
-
-            // If none, return None
-            if matching_titles.is_empty() {
-                return None;
-            } else {
-                // Return best if found
-                return Some(
-                    course_descriptions
-                        .iter()
-                        .find(|d| d.title == matching_titles[0])
-                        .unwrap()
-                        .clone(),
-                );
-            }
-        } else {
-            if matching_identifiers.len() == 1 {
-                return Some(matching_identifiers[0].clone());
-            } else {
-                // Otherwise, search the matching identifiers for titles
-                let matching_titles = find_fuzzy_title(&course_title, &matching_identifiers);
-
-                // If none, search through all descriptions for titles
-                if matching_titles.is_empty() {
-                    let matching_titles = find_fuzzy_title(&course_title, &course_descriptions);
-
-                    // If none, return None
-                    if matching_titles.is_empty() {
-                        return None;
-                    } else {
-                        // Return best if found
-                        return Some(
-                            course_descriptions
-                                .iter()
-                                .find(|d| d.title == matching_titles[0])
-                                .unwrap()
-                                .clone(),
-                        );
-                    }
-                } else {
-                    // Otherwise, return best if found
-                    return Some(
-                        course_descriptions
-                            .iter()
-                            .find(|d| d.title == matching_titles[0])
-                            .unwrap()
-                            .clone(),
-                    );
-                }
-            }
-        }
-    } else {
-        return Some(exact_title_match[0].clone());
-    }
+    DescriptionIndex::new(course_descriptions, rules)
+        .find(&course)
+        .map(|m| course_descriptions[m.index].clone())
 }
 
 pub fn find_fuzzy_title(title: &str, search_list: &Vec<CourseDescription>) -> Vec<String> {
@@ -908,29 +1166,54 @@ pub fn find_fuzzy_title(title: &str, search_list: &Vec<CourseDescription>) -> Ve
 pub fn merge_courses(previous: Vec<Course>, new: Vec<Course>) -> Vec<Course> {
     let mut return_vec: Vec<Course> = Vec::new();
     info!("Running over {} x {}", previous.len(), new.len());
+
+    let mut previous_by_title: HashMap<&String, Vec<&Course>> = HashMap::new();
+    for previous_course in &previous {
+        previous_by_title
+            .entry(previous_course.get_title())
+            .or_default()
+            .push(previous_course);
+    }
+
     for new_course in new {
         let mut final_course = new_course.clone();
 
-        for previous_course in &previous {
-            if final_course.get_title() == previous_course.get_title() {
-                if final_course.get_description().len() < previous_course.get_description().len() {
-                    final_course.set_description(previous_course.get_description());
-                }
+        let same_title = previous_by_title
+            .get(new_course.get_title())
+            .map(|v| v.as_slice())
+            .unwrap_or(&[]);
 
-                if final_course.get_notes() != previous_course.get_notes() {
-                    final_course.set_notes(previous_course.get_notes().to_string());
-                }
+        for previous_course in same_title {
+            if final_course.get_description().len() < previous_course.get_description().len() {
+                final_course.set_description(previous_course.get_description());
+            }
 
-                if final_course.get_prerequisites().len()
-                    < previous_course.get_prerequisites().len()
-                {
-                    final_course.set_prerequisites(previous_course.get_prerequisites());
-                }
+            if final_course.get_notes() != previous_course.get_notes() {
+                final_course.set_notes(previous_course.get_notes().to_string());
+            }
 
-                if final_course.get_corequisites().len() < previous_course.get_corequisites().len()
-                {
-                    final_course.set_corequisites(previous_course.get_corequisites());
-                }
+            if final_course.get_prerequisites().len()
+                < previous_course.get_prerequisites().len()
+            {
+                final_course.set_prerequisites(previous_course.get_prerequisites());
+            }
+
+            if final_course.get_corequisites().len() < previous_course.get_corequisites().len()
+            {
+                final_course.set_corequisites(previous_course.get_corequisites());
+            }
+
+            // Descriptions are only scraped every few updates, so keep what
+            // was read from them until then
+            if final_course.get_fees().is_empty() {
+                final_course.set_fees(previous_course.get_fees().clone());
+            }
+
+            if final_course.get_offered().is_empty() {
+                final_course.set_offered(
+                    previous_course.get_offered().clone(),
+                    previous_course.get_offered_schedule().clone(),
+                );
             }
         }
 
@@ -939,3 +1222,138 @@ pub fn merge_courses(previous: Vec<Course>, new: Vec<Course>) -> Vec<Course> {
 
     return_vec
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::snapshot::{current_snapshot, publish_courses};
+    use serde_json::{json, Value};
+
+    // Slices of real caches. The last catalog is cut-down copies of real
+    // entries ("MATH-0", "Calculus"), to cover identifiers and titles that
+    // only contain another listing's. Expected values were written by the
+    // merges as they were before they were indexed.
+    const CATALOGS: &str = include_str!("../fixtures/merge/catalogs.json");
+    const PREVIOUS_COURSES: &str = include_str!("../fixtures/merge/previous_courses.json");
+    const NEW_COURSES: &str = include_str!("../fixtures/merge/new_courses.json");
+    const MERGE_DESCRIPTIONS: &str = include_str!("../fixtures/merge/expected/merge_descriptions.json");
+    const MERGE_COURSES: &str = include_str!("../fixtures/merge/expected/merge_courses.json");
+    // A real CGU section from the course cache
+    const GRADUATE_COURSES: &str = include_str!("../fixtures/merge/graduate_courses.json");
+
+    #[test]
+    fn merges_descriptions_as_before() {
+        let catalogs: Vec<Vec<CourseDescription>> = serde_json::from_str(CATALOGS).unwrap();
+        let expected: Vec<Value> = serde_json::from_str(MERGE_DESCRIPTIONS).unwrap();
+
+        let merged: Vec<Value> = merge_descriptions(catalogs)
+            .iter()
+            .map(|d| json!([d.identifier, d.title, d.source, d.currently_offered, d.description]))
+            .collect();
+
+        assert_eq!(merged.len(), expected.len());
+        for (merged, expected) in merged.iter().zip(&expected) {
+            assert_eq!(merged, expected);
+        }
+    }
+
+    #[test]
+    fn merges_courses_as_before() {
+        let previous: Vec<Course> = serde_json::from_str(PREVIOUS_COURSES).unwrap();
+        let new: Vec<Course> = serde_json::from_str(NEW_COURSES).unwrap();
+        let expected: Vec<Value> = serde_json::from_str(MERGE_COURSES).unwrap();
+
+        let merged: Vec<Value> = merge_courses(previous, new)
+            .iter()
+            .map(|c| {
+                json!([
+                    c.get_identifier(),
+                    c.get_description(),
+                    c.get_notes(),
+                    c.get_prerequisites(),
+                    c.get_corequisites()
+                ])
+            })
+            .collect();
+
+        assert_eq!(merged.len(), expected.len());
+        for (merged, expected) in merged.iter().zip(&expected) {
+            assert_eq!(merged, expected);
+        }
+    }
+
+    fn new_course(identifier: &str) -> Course {
+        let new: Vec<Course> = serde_json::from_str(NEW_COURSES).unwrap();
+        new.into_iter().find(|c| c.get_identifier() == identifier).unwrap()
+    }
+
+    #[test]
+    fn merge_courses_keeps_fees_and_offered_between_scrapes() {
+        let mut previous = new_course("ART-105-SC-02");
+        previous.set_fees(parse_fees("Materials fee $75"));
+        previous.set_offered("Fall".to_string(), parse_offered("Fall"));
+
+        let merged = merge_courses(vec![previous.clone()], vec![new_course("ART-105-SC-02")]);
+
+        assert_eq!(merged[0].get_fees(), previous.get_fees());
+        assert_eq!(merged[0].get_fee(), 75);
+        assert_eq!(merged[0].get_offered(), "Fall");
+        assert_eq!(merged[0].get_offered_schedule(), previous.get_offered_schedule());
+    }
+
+    #[test]
+    fn graduate_descriptions_match_graduate_courses() {
+        // KGI sections are listed the same way, so that case reuses the
+        // CGU section under a KG code, meeting at KGI
+        for (style, college) in [(Keck, "KG"), (ClaremontGraduate, "CG")] {
+            let courses = GRADUATE_COURSES
+                .replace("-CG-", &format!("-{}-", college))
+                .replace("\"ClaremontGraduate\"", &format!("\"{:?}\"", style));
+            let courses: Vec<Course> = serde_json::from_str(&courses).unwrap();
+
+            let entry = CatalogEntry {
+                code: "EDUC 170G".to_string(),
+                title: "Introduction to Public School Teaching".to_string(),
+                description: "Foundations of teaching and learning in public schools.".to_string(),
+                ..CatalogEntry::default()
+            };
+            let description = entry.into_description(style);
+            assert_eq!(description.identifier, format!("EDUC-170G-{}", college));
+
+            let (courses, _) = merge_description_and_courses(courses, vec![description], &MatchRules::default());
+
+            assert_eq!(courses[0].get_identifier(), &format!("EDUC-170G-{}-01", college));
+            assert_eq!(courses[0].get_description(), "Foundations of teaching and learning in public schools.");
+        }
+    }
+
+    #[tokio::test]
+    async fn catalog_fees_and_offered_reach_published_courses() {
+        let catalogs: Vec<Vec<CourseDescription>> = serde_json::from_str(CATALOGS).unwrap();
+        let mut descriptions = merge_descriptions(catalogs);
+
+        let drawing = descriptions.iter_mut().find(|d| d.identifier == "ART-105-SC").unwrap();
+        drawing.description.push_str(" Materials fee $75.");
+        drawing.offered = "Fall".to_string();
+
+        let mut descriptions = find_fees(&mut descriptions);
+        parse_schedules(&mut descriptions);
+
+        let courses = vec![new_course("ART-105-SC-02"), new_course("ECON-167-PO-01")];
+        let (courses, _) = merge_description_and_courses(courses, descriptions, &MatchRules::default());
+        let courses = merge_courses(Vec::new(), courses);
+        publish_courses(courses, Vec::new(), "".to_string(), MatchRules::default()).await;
+
+        let snapshot = current_snapshot();
+        let drawing = snapshot
+            .courses
+            .iter()
+            .find(|c| c.get_identifier() == "ART-105-SC-02")
+            .unwrap();
+
+        assert_eq!(drawing.get_fee(), 75);
+        assert_eq!(drawing.get_fees()[0].cents, 7500);
+        assert_eq!(drawing.get_offered(), "Fall");
+        assert_eq!(drawing.get_offered_schedule(), &parse_offered("Fall"));
+    }
+}
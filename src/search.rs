@@ -0,0 +1,341 @@
+// ----------------------------------------------------------------------------
+// Full-text search over the course catalog.
+//
+// An inverted index over every description's title, description text,
+// prerequisites and instructors, rebuilt whenever descriptions are
+// published. Words are lowercased and stemmed, so "programming" and
+// "programs" both find "program"; results are ranked with BM25, titles
+// counting most. Query words the catalog doesn't contain match words a
+// typo or two away instead, and the last word also matches as a prefix so
+// results show up while typing.
+// ----------------------------------------------------------------------------
+
+use crate::scrape_descriptions::CourseDescription;
+use std::collections::HashMap;
+
+// Too common to say anything about a course
+const STOP_WORDS: &[&str] = &[
+    "a", "an", "and", "are", "as", "at", "be", "by", "for", "from", "in", "into", "is", "it",
+    "its", "of", "on", "or", "that", "the", "their", "this", "to", "with",
+];
+
+// Stripped by `stem`, longest first, with what replaces them
+const SUFFIXES: &[(&str, &str)] = &[
+    ("ational", "ate"),
+    ("ization", "ize"),
+    ("ications", "ic"),
+    ("ication", "ic"),
+    ("ations", ""),
+    ("ation", ""),
+    ("ically", "ic"),
+    ("ities", ""),
+    ("ments", ""),
+    ("ment", ""),
+    ("ness", ""),
+    ("ings", ""),
+    ("ical", "ic"),
+    ("ing", ""),
+    ("ity", ""),
+    ("ies", "y"),
+    ("ied", "y"),
+    ("ers", ""),
+    ("ly", ""),
+    ("er", ""),
+    ("ed", ""),
+    ("es", ""),
+    ("s", ""),
+];
+
+// Shortest stem a suffix may leave behind
+const MIN_STEM: usize = 3;
+
+// BM25 parameters
+const K1: f32 = 1.2;
+const B: f32 = 0.75;
+
+// Title, description, prerequisites, instructors
+const FIELD_WEIGHTS: [f32; 4] = [3.0, 1.0, 0.5, 2.0];
+
+// Weight of a word matched as a prefix of the last query word
+const PREFIX_WEIGHT: f32 = 0.8;
+// Most words one prefix may expand to
+const MAX_PREFIX_TERMS: usize = 50;
+
+/// Reduces an English word to a rough stem, so different forms of it
+/// match. Not a real Porter stemmer, but the same word always gets the
+/// same stem, which is all the index needs.
+pub fn stem(word: &str) -> String {
+    let mut stemmed = word.to_string();
+
+    for (suffix, replacement) in SUFFIXES {
+        let base = match word.strip_suffix(suffix) {
+            Some(base) if base.chars().count() >= MIN_STEM => base,
+            _ => continue,
+        };
+
+        // "class", "status", "analysis"
+        if *suffix == "s" && (base.ends_with('s') || base.ends_with('u') || base.ends_with('i')) {
+            continue;
+        }
+
+        stemmed = format!("{}{}", base, replacement);
+
+        // "programming" to "program", but not "skill" to "skil"
+        let undouble = matches!(*suffix, "ing" | "ings" | "ed" | "er" | "ers");
+        let chars: Vec<char> = stemmed.chars().collect();
+        if let [.., a, b] = chars.as_slice() {
+            if undouble && a == b && !"aeioulsz".contains(*b) {
+                stemmed.pop();
+            }
+        }
+
+        break;
+    }
+
+    // "course" and "courses"
+    if stemmed.ends_with('e') && stemmed.chars().count() > MIN_STEM {
+        stemmed.pop();
+    }
+
+    stemmed
+}
+
+/// Lowercase words in `text`, without stop words or single characters
+pub fn words(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .map(|word| word.to_lowercase())
+        .filter(|word| word.chars().count() > 1 && !STOP_WORDS.contains(&word.as_str()))
+        .collect()
+}
+
+/// Stemmed words in `text`
+pub fn tokenize(text: &str) -> Vec<String> {
+    words(text).iter().map(|word| stem(word)).collect()
+}
+
+/// Edits (insertions, deletions, substitutions and swaps of neighbours)
+/// between two words, or None if more than `max`
+fn edit_distance(a: &str, b: &str, max: usize) -> Option<usize> {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+
+    if (a.len() as isize - b.len() as isize).abs() as usize > max {
+        return None;
+    }
+
+    // Rows of the optimal string alignment table
+    let mut before: Vec<usize> = Vec::new();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+
+    for i in 1..=a.len() {
+        let mut current = vec![i; b.len() + 1];
+
+        for j in 1..=b.len() {
+            let cost = if a[i - 1] == b[j - 1] { 0 } else { 1 };
+
+            current[j] = (previous[j] + 1)
+                .min(current[j - 1] + 1)
+                .min(previous[j - 1] + cost);
+
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                current[j] = current[j].min(before[j - 2] + 1);
+            }
+        }
+
+        if current.iter().min().unwrap() > &max {
+            return None;
+        }
+
+        before = std::mem::replace(&mut previous, current);
+    }
+
+    Some(previous[b.len()]).filter(|d| *d <= max)
+}
+
+/// Typos tolerated in a word of this many characters
+fn allowed_typos(len: usize) -> usize {
+    match len {
+        0..=3 => 0,
+        4..=7 => 1,
+        _ => 2,
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Posting {
+    document: usize,
+    // Occurrences in each field
+    counts: [u32; 4],
+}
+
+/// Inverted index over a description list
+#[derive(Clone, Debug, Default)]
+pub struct CatalogIndex {
+    postings: HashMap<String, Vec<Posting>>,
+    // Sorted, for prefix lookups
+    terms: Vec<String>,
+    // Words in each field of each description
+    lengths: Vec<[u32; 4]>,
+    average_lengths: [f32; 4],
+}
+
+impl CatalogIndex {
+    pub fn new(descriptions: &[CourseDescription]) -> Self {
+        let mut postings: HashMap<String, Vec<Posting>> = HashMap::new();
+        let mut lengths = Vec::with_capacity(descriptions.len());
+        let mut totals = [0u64; 4];
+
+        for (document, desc) in descriptions.iter().enumerate() {
+            let fields = [
+                tokenize(&desc.title),
+                tokenize(&desc.description),
+                tokenize(&desc.prerequisites),
+                tokenize(&desc.instructors.join(" ")),
+            ];
+
+            let mut counts: HashMap<&String, [u32; 4]> = HashMap::new();
+            let mut length = [0u32; 4];
+
+            for (field, tokens) in fields.iter().enumerate() {
+                for token in tokens {
+                    counts.entry(token).or_default()[field] += 1;
+                }
+
+                length[field] = tokens.len() as u32;
+                totals[field] += tokens.len() as u64;
+            }
+
+            for (token, counts) in counts {
+                postings
+                    .entry(token.clone())
+                    .or_default()
+                    .push(Posting { document, counts });
+            }
+
+            lengths.push(length);
+        }
+
+        let mut average_lengths = [0.0; 4];
+        for field in 0..4 {
+            average_lengths[field] = totals[field] as f32 / descriptions.len().max(1) as f32;
+        }
+
+        let mut terms: Vec<String> = postings.keys().cloned().collect();
+        terms.sort_unstable();
+
+        Self {
+            postings,
+            terms,
+            lengths,
+            average_lengths,
+        }
+    }
+
+    /// Indexed words `term` may stand for, with how much each counts
+    fn expand(&self, word: &str, term: &str, is_last: bool) -> Vec<(&str, f32)> {
+        let mut expansions: Vec<(&str, f32)> = Vec::new();
+
+        if let Some((term, _)) = self.postings.get_key_value(term) {
+            expansions.push((term.as_str(), 1.0));
+        } else {
+            let max = allowed_typos(term.chars().count());
+
+            if max > 0 {
+                for candidate in &self.terms {
+                    if let Some(distance) = edit_distance(term, candidate, max) {
+                        expansions.push((candidate.as_str(), 1.0 / (1.0 + distance as f32)));
+                    }
+                }
+            }
+        }
+
+        // Still being typed
+        if is_last {
+            let start = self.terms.partition_point(|t| t.as_str() < word);
+
+            let prefixed = self.terms[start..]
+                .iter()
+                .take_while(|t| t.starts_with(word))
+                .filter(|t| t.as_str() != term)
+                .take(MAX_PREFIX_TERMS);
+
+            for candidate in prefixed {
+                expansions.push((candidate.as_str(), PREFIX_WEIGHT));
+            }
+        }
+
+        expansions
+    }
+
+    fn bm25(&self, posting: &Posting, document_count: usize) -> f32 {
+        let idf = (1.0 + (self.documents() as f32 - document_count as f32 + 0.5)
+            / (document_count as f32 + 0.5))
+            .ln();
+
+        let lengths = &self.lengths[posting.document];
+
+        (0..4)
+            .map(|field| {
+                let count = posting.counts[field] as f32;
+
+                if count == 0.0 {
+                    return 0.0;
+                }
+
+                let average = self.average_lengths[field].max(1.0);
+                let norm = 1.0 - B + B * lengths[field] as f32 / average;
+
+                FIELD_WEIGHTS[field] * count * (K1 + 1.0) / (count + K1 * norm)
+            })
+            .sum::<f32>()
+            * idf
+    }
+
+    pub fn documents(&self) -> usize {
+        self.lengths.len()
+    }
+
+    /// Descriptions matching `query` that `allowed` accepts, as (index,
+    /// score) pairs, best first
+    pub fn search(&self, query: &str, allowed: impl Fn(usize) -> bool) -> Vec<(usize, f32)> {
+        let query_words = words(query);
+        // A trailing space means the last word is finished
+        let typing = !query.ends_with(char::is_whitespace);
+
+        let mut scores: HashMap<usize, f32> = HashMap::new();
+
+        for (i, word) in query_words.iter().enumerate() {
+            let is_last = typing && i == query_words.len() - 1;
+
+            // Each query word scores a description once, through its best
+            // matching expansion
+            let mut best: HashMap<usize, f32> = HashMap::new();
+
+            for (term, weight) in self.expand(word, &stem(word), is_last) {
+                let postings = &self.postings[term];
+
+                for posting in postings {
+                    if !allowed(posting.document) {
+                        continue;
+                    }
+
+                    let score = weight * self.bm25(posting, postings.len());
+                    let entry = best.entry(posting.document).or_insert(0.0);
+
+                    if score > *entry {
+                        *entry = score;
+                    }
+                }
+            }
+
+            for (document, score) in best {
+                *scores.entry(document).or_insert(0.0) += score;
+            }
+        }
+
+        let mut results: Vec<(usize, f32)> = scores.into_iter().collect();
+        results.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap().then(a.0.cmp(&b.0)));
+
+        results
+    }
+}
@@ -0,0 +1,154 @@
+// ----------------------------------------------------------------------------
+// "Similar courses" across the five catalogs.
+//
+// Each description becomes a TF-IDF vector of its stemmed title and
+// description words (title words counted twice), and similarity is the
+// cosine between two vectors. Vectors are normalized when the index is
+// built, so a lookup only sums the products of shared words through an
+// inverted index instead of comparing against every description.
+// ----------------------------------------------------------------------------
+
+use crate::search::tokenize;
+use crate::scrape_descriptions::CourseDescription;
+use std::collections::HashMap;
+
+// How many times a title word counts, compared to a description word
+const TITLE_WEIGHT: f32 = 2.0;
+
+/// Sparse, normalized vector of (term, weight)
+pub type TermVector = Vec<(usize, f32)>;
+
+#[derive(Clone, Debug, Default)]
+pub struct SimilarityIndex {
+    terms: HashMap<String, usize>,
+    idf: Vec<f32>,
+    vectors: Vec<TermVector>,
+    // For each term, the descriptions containing it and its weight there
+    postings: Vec<Vec<(usize, f32)>>,
+}
+
+/// Weighted occurrences of each word in a title and description
+fn term_counts(title: &str, text: &str) -> HashMap<String, f32> {
+    let mut counts: HashMap<String, f32> = HashMap::new();
+
+    for token in tokenize(title) {
+        *counts.entry(token).or_insert(0.0) += TITLE_WEIGHT;
+    }
+
+    for token in tokenize(text) {
+        *counts.entry(token).or_insert(0.0) += 1.0;
+    }
+
+    counts
+}
+
+fn normalize(vector: &mut TermVector) {
+    let norm = vector.iter().map(|(_, w)| w * w).sum::<f32>().sqrt();
+
+    if norm > 0.0 {
+        for (_, w) in vector.iter_mut() {
+            *w /= norm;
+        }
+    }
+}
+
+impl SimilarityIndex {
+    pub fn new(descriptions: &[CourseDescription]) -> Self {
+        let counts: Vec<HashMap<String, f32>> = descriptions
+            .iter()
+            .map(|desc| term_counts(&desc.title, &desc.description))
+            .collect();
+
+        let mut terms: HashMap<String, usize> = HashMap::new();
+        let mut document_counts: Vec<usize> = Vec::new();
+
+        for doc in &counts {
+            for term in doc.keys() {
+                let next = terms.len();
+                let id = *terms.entry(term.clone()).or_insert(next);
+
+                if id == document_counts.len() {
+                    document_counts.push(0);
+                }
+                document_counts[id] += 1;
+            }
+        }
+
+        let total = descriptions.len() as f32;
+        let idf: Vec<f32> = document_counts
+            .iter()
+            .map(|count| (total / *count as f32).ln())
+            .collect();
+
+        let mut index = Self {
+            terms,
+            idf,
+            vectors: Vec::new(),
+            postings: vec![Vec::new(); document_counts.len()],
+        };
+
+        for (i, doc) in counts.iter().enumerate() {
+            let vector = index.weigh(doc);
+
+            for (term, weight) in &vector {
+                index.postings[*term].push((i, *weight));
+            }
+
+            index.vectors.push(vector);
+        }
+
+        index
+    }
+
+    /// TF-IDF vector of term counts, leaving out words the catalog
+    /// doesn't have
+    fn weigh(&self, counts: &HashMap<String, f32>) -> TermVector {
+        let mut vector: TermVector = counts
+            .iter()
+            .filter_map(|(term, count)| {
+                let id = *self.terms.get(term)?;
+
+                // Dampened, so a word repeated ten times isn't ten times
+                // as important
+                Some((id, (1.0 + count.ln()) * self.idf[id]))
+            })
+            .filter(|(_, weight)| *weight > 0.0)
+            .collect();
+
+        normalize(&mut vector);
+        vector.sort_unstable_by_key(|(id, _)| *id);
+
+        vector
+    }
+
+    /// Vector of the description at `i`
+    pub fn vector(&self, i: usize) -> Option<&TermVector> {
+        self.vectors.get(i)
+    }
+
+    /// Vector of any title and description, e.g. a course with no catalog
+    /// entry
+    pub fn vector_for(&self, title: &str, text: &str) -> TermVector {
+        self.weigh(&term_counts(title, text))
+    }
+
+    /// Descriptions `allowed` accepts by cosine similarity to `vector`, as
+    /// (index, similarity) pairs, most similar first. Descriptions sharing
+    /// no words are left out.
+    pub fn nearest(&self, vector: &TermVector, allowed: impl Fn(usize) -> bool) -> Vec<(usize, f32)> {
+        let mut scores: HashMap<usize, f32> = HashMap::new();
+
+        for (term, weight) in vector {
+            for (i, other) in &self.postings[*term] {
+                if allowed(*i) {
+                    *scores.entry(*i).or_insert(0.0) += weight * other;
+                }
+            }
+        }
+
+        let mut results: Vec<(usize, f32)> = scores.into_iter().collect();
+        results.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap().then(a.0.cmp(&b.0)));
+
+        results
+    }
+}
@@ -0,0 +1,263 @@
+// ----------------------------------------------------------------------------
+// Read-only caches, published as immutable snapshots.
+//
+// The update loop builds a new Snapshot whenever courses, descriptions,
+// locations or menus change and swaps it in atomically. Routes load the
+// current snapshot without taking a lock, and the large responses are
+// serialized (and gzipped) once per update instead of once per request.
+// Building a snapshot runs on a blocking thread so it never stalls the
+// runtime the server shares.
+//
+// Anything written by requests (share codes, health, metrics) stays in
+// MEMORY_DATABASE.
+// ----------------------------------------------------------------------------
+
+use crate::course_api::*;
+use crate::geo::Coordinates;
+use crate::get_unix_timestamp;
+use crate::menu::SchoolMenu;
+use crate::merge_index::{DescriptionMatches, MatchRules};
+use crate::rooms::RoomIndex;
+use crate::scrape_descriptions::CourseDescription;
+use crate::search::CatalogIndex;
+use crate::similar::SimilarityIndex;
+use ::serde::Serialize;
+use actix_web::http::header::{ACCEPT_ENCODING, CONTENT_ENCODING, CONTENT_TYPE, VARY};
+use actix_web::web::Bytes;
+use actix_web::{HttpRequest, HttpResponse};
+use arc_swap::ArcSwap;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use lazy_static::lazy_static;
+use std::collections::HashMap;
+use std::io::Write;
+use std::sync::Arc;
+
+#[derive(Debug, Serialize)]
+struct ReturnCourses<'a> {
+    timestamp: u64,
+    courses: &'a Vec<Course>,
+    term: &'a str,
+}
+
+#[derive(Debug, Serialize)]
+struct ReturnCatalog<'a> {
+    timestamp: u64,
+    catalog: &'a Vec<CourseDescription>,
+}
+
+/// A JSON response body, serialized and gzipped ahead of time
+#[derive(Clone, Debug)]
+pub struct PreparedBody {
+    json: Bytes,
+    gzip: Bytes,
+}
+
+impl PreparedBody {
+    pub fn new<T: Serialize>(value: &T) -> Self {
+        let json = serde_json::to_vec(value).unwrap();
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::best());
+        encoder.write_all(&json).unwrap();
+        let gzip = encoder.finish().unwrap();
+
+        Self {
+            json: Bytes::from(json),
+            gzip: Bytes::from(gzip),
+        }
+    }
+
+    /// Responds with the gzipped body if the client accepts it. Setting
+    /// Content-Encoding ourselves stops the Compress middleware from
+    /// compressing it again.
+    pub fn respond(&self, req: &HttpRequest) -> HttpResponse {
+        if accepts_gzip(req) {
+            HttpResponse::Ok()
+                .insert_header((CONTENT_TYPE, "application/json"))
+                .insert_header((CONTENT_ENCODING, "gzip"))
+                .insert_header((VARY, "Accept-Encoding"))
+                .body(self.gzip.clone())
+        } else {
+            HttpResponse::Ok()
+                .insert_header((CONTENT_TYPE, "application/json"))
+                .insert_header((VARY, "Accept-Encoding"))
+                .body(self.json.clone())
+        }
+    }
+}
+
+fn accepts_gzip(req: &HttpRequest) -> bool {
+    let header = match req.headers().get(ACCEPT_ENCODING).and_then(|h| h.to_str().ok()) {
+        Some(header) => header,
+        None => return false,
+    };
+
+    header.split(',').any(|encoding| {
+        let mut parts = encoding.split(';').map(|part| part.trim());
+        let name = parts.next().unwrap_or("");
+        let refused = parts.any(|param| {
+            param
+                .strip_prefix("q=")
+                .and_then(|q| q.parse::<f32>().ok())
+                .map_or(false, |q| q == 0.0)
+        });
+
+        (name.eq_ignore_ascii_case("gzip") || name == "*") && !refused
+    })
+}
+
+#[derive(Clone, Debug)]
+pub struct Snapshot {
+    pub last_change: u64,
+    pub term: String,
+    pub courses: Arc<Vec<Course>>,
+    pub areas: Arc<Vec<CourseArea>>,
+    pub descriptions: Arc<Vec<CourseDescription>>,
+    pub locations: Arc<HashMap<String, Coordinates>>,
+    pub menus: Arc<HashMap<School, SchoolMenu>>,
+    // Built from courses
+    pub rooms: Arc<RoomIndex>,
+    // Built from descriptions
+    pub catalog_index: Arc<CatalogIndex>,
+    pub similarity: Arc<SimilarityIndex>,
+    // Built from both, with the overrides file as it was then
+    pub description_matches: Arc<DescriptionMatches>,
+
+    pub courses_body: PreparedBody,
+    pub catalog_body: PreparedBody,
+    pub areas_body: PreparedBody,
+    pub locations_body: PreparedBody,
+    pub menus_body: PreparedBody,
+}
+
+impl Snapshot {
+    fn new() -> Self {
+        let mut snapshot = Self {
+            last_change: get_unix_timestamp(),
+            term: "".to_string(),
+            courses: Arc::new(Vec::new()),
+            areas: Arc::new(Vec::new()),
+            descriptions: Arc::new(Vec::new()),
+            locations: Arc::new(HashMap::new()),
+            menus: Arc::new(HashMap::new()),
+            rooms: Arc::new(RoomIndex::default()),
+            catalog_index: Arc::new(CatalogIndex::default()),
+            similarity: Arc::new(SimilarityIndex::default()),
+            description_matches: Arc::new(DescriptionMatches::default()),
+            courses_body: PreparedBody::new(&()),
+            catalog_body: PreparedBody::new(&()),
+            areas_body: PreparedBody::new(&Vec::<CourseArea>::new()),
+            locations_body: PreparedBody::new(&HashMap::<String, Coordinates>::new()),
+            menus_body: PreparedBody::new(&HashMap::<School, SchoolMenu>::new()),
+        };
+
+        snapshot.render_courses();
+        snapshot.render_catalog();
+
+        snapshot
+    }
+
+    fn render_courses(&mut self) {
+        self.courses_body = PreparedBody::new(&ReturnCourses {
+            timestamp: self.last_change,
+            courses: &self.courses,
+            term: &self.term,
+        });
+    }
+
+    // The catalog is stamped with the course timestamp, so clients
+    // refetch it whenever courses change
+    fn render_catalog(&mut self) {
+        self.catalog_body = PreparedBody::new(&ReturnCatalog {
+            timestamp: self.last_change,
+            catalog: &self.descriptions,
+        });
+    }
+
+    fn match_descriptions(&mut self, rules: &MatchRules) {
+        self.description_matches = Arc::new(DescriptionMatches::new(
+            &self.courses,
+            &self.descriptions,
+            rules,
+        ));
+    }
+}
+
+// GLOBAL snapshot of the read-only caches
+lazy_static! {
+    static ref SNAPSHOT: ArcSwap<Snapshot> = ArcSwap::from_pointee(Snapshot::new());
+}
+
+/// The current snapshot. Cheap, lock-free, and stays valid (if stale)
+/// for as long as the caller holds on to it.
+pub fn current_snapshot() -> Arc<Snapshot> {
+    SNAPSHOT.load_full()
+}
+
+// Only the update loop (and startup) publish, so there is never a
+// competing writer whose changes could be lost between load and store.
+// Indexing, matching and gzipping are CPU bound, so the update runs on a
+// blocking thread.
+async fn publish(update: impl FnOnce(&mut Snapshot) + Send + 'static) {
+    let mut next = Snapshot::clone(&current_snapshot());
+
+    let next = tokio::task::spawn_blocking(move || {
+        update(&mut next);
+        next
+    })
+    .await
+    .expect("Building the snapshot panicked");
+
+    SNAPSHOT.store(Arc::new(next));
+}
+
+/// Publishes new courses and marks them as changed now. Descriptions are
+/// matched against them here, once per publish, with the caller's rules.
+pub async fn publish_courses(
+    courses: Vec<Course>,
+    areas: Vec<CourseArea>,
+    term: String,
+    rules: MatchRules,
+) {
+    publish(move |snapshot| {
+        snapshot.last_change = get_unix_timestamp();
+        snapshot.term = term;
+        snapshot.areas_body = PreparedBody::new(&areas);
+        snapshot.rooms = Arc::new(RoomIndex::new(&courses));
+        snapshot.courses = Arc::new(courses);
+        snapshot.areas = Arc::new(areas);
+        snapshot.render_courses();
+        snapshot.render_catalog();
+        snapshot.match_descriptions(&rules);
+    })
+    .await;
+}
+
+/// Publishes new descriptions. They are matched against courses when the
+/// courses are next published, which the update loop (and startup) always
+/// does right after.
+pub async fn publish_descriptions(descriptions: Vec<CourseDescription>) {
+    publish(move |snapshot| {
+        snapshot.catalog_index = Arc::new(CatalogIndex::new(&descriptions));
+        snapshot.similarity = Arc::new(SimilarityIndex::new(&descriptions));
+        snapshot.descriptions = Arc::new(descriptions);
+        snapshot.render_catalog();
+    })
+    .await;
+}
+
+pub async fn publish_locations(locations: HashMap<String, Coordinates>) {
+    publish(move |snapshot| {
+        snapshot.locations_body = PreparedBody::new(&locations);
+        snapshot.locations = Arc::new(locations);
+    })
+    .await;
+}
+
+pub async fn publish_menus(menus: HashMap<School, SchoolMenu>) {
+    publish(move |snapshot| {
+        snapshot.menus_body = PreparedBody::new(&menus);
+        snapshot.menus = Arc::new(menus);
+    })
+    .await;
+}
@@ -0,0 +1,281 @@
+// ----------------------------------------------------------------------------
+// Walking time between back-to-back classes.
+//
+// Distances are straight lines between building coordinates, stretched by
+// a detour factor since nobody walks through walls, at an average walking
+// pace. Good enough to catch a 10 minute walk from Pitzer to Pomona.
+// ----------------------------------------------------------------------------
+
+use crate::course_api::*;
+use crate::geo::*;
+use ::serde::{Deserialize, Serialize};
+use chrono::NaiveDate;
+use std::collections::HashMap;
+
+// Campus paths are longer than the straight line between buildings
+const DETOUR_FACTOR: f64 = 1.3;
+// About 3 mph
+const WALKING_METERS_PER_MINUTE: f64 = 80.0;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Stop {
+    pub identifier: String,
+    pub title: String,
+    pub school: School,
+    pub building: String,
+    pub room: String,
+    pub time: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Transition {
+    pub day: Day,
+    // The class being left, with its end time
+    pub from: Stop,
+    // The next class, with its start time
+    pub to: Stop,
+    pub gap_minutes: i64,
+    // None when either building's coordinates are unknown
+    pub distance_meters: Option<f64>,
+    pub walking_minutes: Option<f64>,
+    pub feasible: Option<bool>,
+}
+
+struct Meeting<'a> {
+    course: &'a Course,
+    timing: CourseTiming,
+    start: u32,
+    end: u32,
+}
+
+// First and last date, None for the start or end of the term
+type DateRange = (Option<NaiveDate>, Option<NaiveDate>);
+
+/// The dates two meetings both run, None if they never do
+fn shared_dates(a: &CourseTiming, b: &CourseTiming) -> Option<DateRange> {
+    if !a.dates_overlap(b) {
+        return None;
+    }
+
+    let (a_start, a_end) = a.get_date_range();
+    let (b_start, b_end) = b.get_date_range();
+
+    let end = match (a_end, b_end) {
+        (Some(a_end), Some(b_end)) => Some(a_end.min(b_end)),
+        (a_end, b_end) => a_end.or(b_end),
+    };
+
+    Some((a_start.max(b_start), end))
+}
+
+/// Whether a meeting runs on every date in a range
+fn covers(timing: &CourseTiming, (start, end): DateRange) -> bool {
+    let (timing_start, timing_end) = timing.get_date_range();
+
+    timing_start.is_none_or(|t| start.is_some_and(|s| t <= s))
+        && timing_end.is_none_or(|t| end.is_some_and(|e| e <= t))
+}
+
+fn stop(meeting: &Meeting, time: String) -> Stop {
+    let location = meeting.timing.get_location();
+
+    Stop {
+        identifier: meeting.course.get_identifier().clone(),
+        title: meeting.course.get_title().clone(),
+        school: location.get_school().clone(),
+        building: location.get_building().clone(),
+        room: location.get_room().clone(),
+        time,
+    }
+}
+
+fn transition(
+    day: &Day,
+    from: &Meeting,
+    to: &Meeting,
+    locations: &HashMap<String, Coordinates>,
+) -> Transition {
+    let from_location = from.timing.get_location();
+    let to_location = to.timing.get_location();
+
+    let gap_minutes = to.start as i64 - from.end as i64;
+
+    // Online or unassigned classes don't need walking to
+    let distance_meters = if from_location.is_placeholder() || to_location.is_placeholder() {
+        None
+    } else if from_location.get_school() == to_location.get_school()
+        && from_location.get_building() == to_location.get_building()
+    {
+        Some(0.0)
+    } else {
+        match (
+            coordinates_for(from_location, locations),
+            coordinates_for(to_location, locations),
+        ) {
+            (Some(a), Some(b)) => Some(a.distance_meters(&b) * DETOUR_FACTOR),
+            _ => None,
+        }
+    };
+
+    let walking_minutes = distance_meters.map(|d| d / WALKING_METERS_PER_MINUTE);
+
+    Transition {
+        day: day.clone(),
+        from: stop(from, from.timing.get_end_time_str()),
+        to: stop(to, to.timing.get_start_time_str()),
+        gap_minutes,
+        distance_meters: distance_meters.map(|d| d.round()),
+        walking_minutes: walking_minutes.map(|m| (m * 10.0).round() / 10.0),
+        feasible: walking_minutes.map(|m| m <= gap_minutes as f64),
+    }
+}
+
+/// Every pair of consecutive classes on the same day, with how far apart
+/// they are and whether the gap between them is long enough to walk it.
+/// Classes are only consecutive in the weeks both of them meet, so a
+/// first-half course is never paired with a second-half one.
+pub fn walking_transitions(
+    courses: &[Course],
+    locations: &HashMap<String, Coordinates>,
+) -> Vec<Transition> {
+    let week = [
+        Day::Monday,
+        Day::Tuesday,
+        Day::Wednesday,
+        Day::Thursday,
+        Day::Friday,
+        Day::Saturday,
+        Day::Sunday,
+    ];
+
+    let mut transitions = Vec::new();
+
+    for day in week.iter() {
+        let mut meetings: Vec<Meeting> = courses
+            .iter()
+            .flat_map(|course| {
+                course
+                    .get_timings()
+                    .into_iter()
+                    .filter(|timing| timing.get_days().contains(day))
+                    // Arranged and TBA meetings aren't on the clock
+                    .filter(|timing| timing.has_time())
+                    .map(move |timing| Meeting {
+                        course,
+                        start: timing.get_start_time_index(),
+                        end: timing.get_end_time_index(),
+                        timing,
+                    })
+            })
+            .collect();
+
+        meetings.sort_by_key(|m| (m.start, m.end));
+
+        for (i, from) in meetings.iter().enumerate() {
+            for (j, to) in meetings.iter().enumerate().skip(i + 1) {
+                let shared = match shared_dates(&from.timing, &to.timing) {
+                    Some(shared) => shared,
+                    None => continue,
+                };
+
+                // A class in between on every one of those dates means
+                // these two are never back to back
+                if meetings[i + 1..j].iter().any(|m| covers(&m.timing, shared)) {
+                    continue;
+                }
+
+                transitions.push(transition(day, from, to, locations));
+            }
+        }
+    }
+
+    transitions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn course(identifier: &str, start: &str, end: &str, dates: (Option<&str>, Option<&str>)) -> Course {
+        serde_json::from_value(serde_json::json!({
+            "identifier": identifier,
+            "id": "",
+            "code": "",
+            "dept": "",
+            "section": "",
+            "title": identifier,
+            "max_seats": 0,
+            "seats_taken": 0,
+            "seats_remaining": 0,
+            "credits": 100,
+            "credits_hmc": 300,
+            "status": "Open",
+            "timing": [{
+                "days": ["Monday"],
+                "start_time": start,
+                "end_time": end,
+                "location": { "school": "Pomona", "building": "Carnegie Building", "room": "10" },
+                "start_date": dates.0,
+                "end_date": dates.1
+            }],
+            "instructors": [],
+            "notes": "",
+            "description": "",
+            "prerequisites": "",
+            "corequisites": "",
+            "offered": "",
+            "perm_count": 0,
+            "fee": 0,
+            "associations": []
+        }))
+        .unwrap()
+    }
+
+    fn pairs(courses: &[Course]) -> Vec<(String, String)> {
+        walking_transitions(courses, &HashMap::new())
+            .into_iter()
+            .map(|t| (t.from.identifier, t.to.identifier))
+            .collect()
+    }
+
+    const FIRST_HALF: (Option<&str>, Option<&str>) = (Some("2021-08-30"), Some("2021-10-15"));
+    const SECOND_HALF: (Option<&str>, Option<&str>) = (Some("2021-10-25"), Some("2021-12-10"));
+    const WHOLE_TERM: (Option<&str>, Option<&str>) = (None, None);
+
+    fn pair(from: &str, to: &str) -> (String, String) {
+        (from.to_string(), to.to_string())
+    }
+
+    #[test]
+    fn pairs_consecutive_classes() {
+        let courses = [
+            course("A", "09:00:00", "09:50:00", WHOLE_TERM),
+            course("B", "10:00:00", "10:50:00", WHOLE_TERM),
+            course("C", "11:00:00", "11:50:00", WHOLE_TERM),
+        ];
+
+        assert_eq!(pairs(&courses), vec![pair("A", "B"), pair("B", "C")]);
+    }
+
+    #[test]
+    fn half_semester_classes_only_pair_in_their_weeks() {
+        let courses = [
+            course("A", "09:00:00", "09:50:00", FIRST_HALF),
+            course("B", "10:00:00", "10:50:00", SECOND_HALF),
+            course("C", "11:00:00", "11:50:00", WHOLE_TERM),
+        ];
+
+        assert_eq!(pairs(&courses), vec![pair("A", "C"), pair("B", "C")]);
+    }
+
+    #[test]
+    fn classes_around_a_half_semester_one_pair_in_the_other_half() {
+        let courses = [
+            course("A", "09:00:00", "09:50:00", WHOLE_TERM),
+            course("B", "10:00:00", "10:50:00", FIRST_HALF),
+            course("C", "11:00:00", "11:50:00", WHOLE_TERM),
+        ];
+
+        assert_eq!(pairs(&courses), vec![pair("A", "B"), pair("A", "C"), pair("B", "C")]);
+    }
+}